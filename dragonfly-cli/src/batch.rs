@@ -0,0 +1,99 @@
+use crate::interrupt;
+use anyhow::bail;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+
+/// File extensions of the images and videos picked up from a directory of inputs
+const INPUT_EXTENSIONS: [&str; 10] = [
+    "jpg", "jpeg", "png", "tif", "tiff", "webp", "mp4", "mov", "mkv", "webm",
+];
+
+/// Returns the inputs to convert, replacing each directory with the images and videos it contains
+pub fn input_paths(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut inputs = vec![];
+    for path in paths {
+        if path.is_dir() {
+            let mut dir_inputs = fs::read_dir(path)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && is_input_file(path))
+                .collect::<Vec<_>>();
+            dir_inputs.sort();
+            inputs.extend(dir_inputs);
+        } else {
+            inputs.push(path.clone());
+        }
+    }
+    Ok(inputs)
+}
+
+/// Returns true if the file extension is one of an image or video dragonfly can convert
+pub fn is_input_file(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| INPUT_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Returns the output path of each input, replacing {stem} in the template with the input file name without the
+/// extension and {index} with the position of the input, and erroring if two inputs would share an output
+pub fn output_paths(template: &str, input_paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let output_paths = input_paths
+        .iter()
+        .enumerate()
+        .map(|(index, input_path)| output_path(template, input_path, index))
+        .collect::<Vec<_>>();
+    let mut seen = HashSet::new();
+    for (input_path, output_path) in input_paths.iter().zip(&output_paths) {
+        if !seen.insert(output_path) {
+            bail!(
+                "{:?} and another input would both be written to {:?}, add {{index}} to the output template",
+                input_path,
+                output_path
+            );
+        }
+    }
+    Ok(output_paths)
+}
+
+/// Returns the output path of a single input, see [`output_paths`]
+pub fn output_path(template: &str, input_path: &Path, index: usize) -> PathBuf {
+    let stem = input_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    PathBuf::from(
+        template
+            .replace("{stem}", &stem)
+            .replace("{index}", &index.to_string()),
+    )
+}
+
+/// Extracts the frames of one input to a temporary directory and encodes them, removing the frames afterwards
+pub fn convert(
+    input_path: &Path,
+    output_path: &Path,
+    extract_args: &dragonfly::ExtractFramesDescriptor,
+    encode_args: &dragonfly::EncodeFramesDescriptor,
+    tmpdir: &Path,
+) -> anyhow::Result<ExitStatus> {
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let extraction_dir = dragonfly::ExtractionDir::new_in(tmpdir)?;
+    dragonfly::extract_frames(
+        input_path,
+        extraction_dir.path(),
+        extract_args,
+        None::<fn(dragonfly::ProgressEvent)>,
+        interrupt::token(),
+    )?;
+    Ok(dragonfly::encode_frames(
+        output_path,
+        extraction_dir.path(),
+        encode_args,
+        None::<fn(dragonfly::ProgressEvent)>,
+        interrupt::token(),
+    )?)
+}
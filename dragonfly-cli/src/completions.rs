@@ -0,0 +1,196 @@
+use clap::{Arg, Command, ValueEnum};
+use std::fmt::Write;
+
+/// Shells that completion scripts can be generated for
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Returns a completion script for the command's subcommands, flags, and flag values
+pub fn generate(shell: Shell, command: &mut Command) -> String {
+    // Building propagates the global flags and adds --help and --version to every subcommand
+    command.build();
+    match shell {
+        Shell::Bash => bash(command),
+        // zsh runs the bash script through its bash compatibility layer
+        Shell::Zsh => format!(
+            "#compdef {}\nautoload -U bashcompinit\nbashcompinit\n{}",
+            command.get_name(),
+            bash(command)
+        ),
+        Shell::Fish => fish(command),
+    }
+}
+
+fn bash(command: &Command) -> String {
+    let name = command.get_name();
+    let mut script = String::new();
+    writeln!(script, "_{name}() {{").unwrap();
+    writeln!(script, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"").unwrap();
+    writeln!(script, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(script, "    case \"$prev\" in").unwrap();
+    let mut value_flags = vec![];
+    for arg in all_args(command) {
+        let values = possible_values(arg);
+        if let (Some(long), false) = (arg.get_long(), values.is_empty()) {
+            if !value_flags.contains(&long) {
+                value_flags.push(long);
+                writeln!(
+                    script,
+                    "        --{long}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;",
+                    values.join(" ")
+                )
+                .unwrap();
+            }
+        }
+    }
+    writeln!(script, "    esac").unwrap();
+    writeln!(script, "    local words").unwrap();
+    writeln!(script, "    if [ \"$COMP_CWORD\" -eq 1 ]; then").unwrap();
+    writeln!(script, "        words=\"{}\"", words(command)).unwrap();
+    writeln!(script, "    else").unwrap();
+    writeln!(script, "        case \"${{COMP_WORDS[1]}}\" in").unwrap();
+    for subcommand in command.get_subcommands() {
+        writeln!(
+            script,
+            "            {}) words=\"{}\" ;;",
+            subcommand.get_name(),
+            words(subcommand)
+        )
+        .unwrap();
+    }
+    writeln!(script, "        esac").unwrap();
+    writeln!(script, "    fi").unwrap();
+    // Positional arguments are paths, except for the first word which is always a subcommand
+    writeln!(
+        script,
+        "    if [[ \"$cur\" == -* || \"$COMP_CWORD\" -eq 1 ]]; then"
+    )
+    .unwrap();
+    writeln!(
+        script,
+        "        COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))"
+    )
+    .unwrap();
+    writeln!(script, "    else").unwrap();
+    writeln!(
+        script,
+        "        COMPREPLY=($(compgen -W \"$words\" -- \"$cur\") $(compgen -f -- \"$cur\"))"
+    )
+    .unwrap();
+    writeln!(script, "    fi").unwrap();
+    writeln!(script, "}}").unwrap();
+    writeln!(script, "complete -o filenames -F _{name} {name}").unwrap();
+    script
+}
+
+fn fish(command: &Command) -> String {
+    let name = command.get_name();
+    let mut script = String::new();
+    for arg in flags(command) {
+        writeln!(
+            script,
+            "complete -c {name} -n \"__fish_use_subcommand\"{}",
+            fish_arg(arg)
+        )
+        .unwrap();
+    }
+    for subcommand in command.get_subcommands() {
+        writeln!(
+            script,
+            "complete -c {name} -n \"__fish_use_subcommand\" -f -a {} -d {}",
+            subcommand.get_name(),
+            fish_quote(
+                &subcommand
+                    .get_about()
+                    .map(|s| s.to_string())
+                    .unwrap_or_default()
+            )
+        )
+        .unwrap();
+        let condition = format!("__fish_seen_subcommand_from {}", subcommand.get_name());
+        for arg in flags(subcommand) {
+            writeln!(
+                script,
+                "complete -c {name} -n \"{condition}\"{}",
+                fish_arg(arg)
+            )
+            .unwrap();
+        }
+        for nested in subcommand.get_subcommands() {
+            writeln!(
+                script,
+                "complete -c {name} -n \"{condition}\" -f -a {} -d {}",
+                nested.get_name(),
+                fish_quote(
+                    &nested
+                        .get_about()
+                        .map(|s| s.to_string())
+                        .unwrap_or_default()
+                )
+            )
+            .unwrap();
+        }
+    }
+    script
+}
+
+fn fish_arg(arg: &Arg) -> String {
+    let mut options = String::new();
+    if let Some(short) = arg.get_short() {
+        write!(options, " -s {short}").unwrap();
+    }
+    if let Some(long) = arg.get_long() {
+        write!(options, " -l {long}").unwrap();
+    }
+    let values = possible_values(arg);
+    if !values.is_empty() {
+        write!(options, " -x -a {}", fish_quote(&values.join(" "))).unwrap();
+    } else if arg.get_action().takes_values() {
+        options.push_str(" -r");
+    }
+    if let Some(help) = arg.get_help() {
+        write!(options, " -d {}", fish_quote(&help.to_string())).unwrap();
+    }
+    options
+}
+
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Returns the subcommands and flags that can follow the command
+fn words(command: &Command) -> String {
+    command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .chain(flags(command).filter_map(|arg| arg.get_long().map(|long| format!("--{long}"))))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn flags(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+}
+
+/// Returns the flags of the command and every subcommand
+fn all_args(command: &Command) -> Vec<&Arg> {
+    let mut args = flags(command).collect::<Vec<_>>();
+    for subcommand in command.get_subcommands() {
+        args.extend(all_args(subcommand));
+    }
+    args
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect()
+}
@@ -0,0 +1,34 @@
+use dragonfly::CancellationToken;
+
+lazy_static::lazy_static! {
+    /// Cancelled by Ctrl-C, stopping every job the CLI started with it
+    static ref CANCELLATION_TOKEN: CancellationToken = CancellationToken::new();
+}
+
+/// Makes Ctrl-C cancel the running job, which kills its ffmpeg processes and removes its partial output,
+/// rather than exiting at once and leaving ffmpeg running. A second Ctrl-C exits immediately.
+pub fn install_handler() {
+    // Initialized before the handler can run, which must not allocate
+    let _ = &*CANCELLATION_TOKEN;
+    #[cfg(unix)]
+    {
+        extern "C" fn handle_sigint(_: libc::c_int) {
+            if CANCELLATION_TOKEN.is_cancelled() {
+                unsafe { libc::_exit(130) };
+            }
+            CANCELLATION_TOKEN.cancel();
+        }
+        let handler: extern "C" fn(libc::c_int) = handle_sigint;
+        unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+    }
+}
+
+/// Returns the token Ctrl-C cancels
+pub fn token() -> Option<&'static CancellationToken> {
+    Some(&CANCELLATION_TOKEN)
+}
+
+/// Returns true once Ctrl-C was pressed
+pub fn is_interrupted() -> bool {
+    CANCELLATION_TOKEN.is_cancelled()
+}
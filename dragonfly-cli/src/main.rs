@@ -1,24 +1,60 @@
-use clap::{Parser, Subcommand};
-use indicatif::{ProgressBar, ProgressStyle};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use indicatif::ProgressBar;
+use output::Output;
+use serde_json::json;
+use session::Session;
 use std::env::temp_dir;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::os::unix::prelude::OsStrExt;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
-use which::which;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+mod batch;
+mod completions;
+mod interrupt;
+mod output;
+mod session;
+mod terminal_image;
+mod watch;
 
 #[derive(Parser)]
-#[clap(author, version, about, long_about = None)]
+#[clap(name = "dragonfly", author, version, about, long_about = None)]
 struct DragonflyCli {
+    #[arg(
+        help = "Print JSON events, one per line, instead of human readable output",
+        long,
+        global = true
+    )]
+    json: bool,
+    #[arg(
+        help = "Kill any ffmpeg or ffprobe process that runs longer than this many seconds",
+        long,
+        global = true
+    )]
+    timeout: Option<f64>,
+    #[arg(
+        help = "Retry a failed extraction, encode, or probe this many times, such as for inputs on a flaky network mount",
+        long,
+        global = true,
+        default_value = "0"
+    )]
+    retries: u32,
+    #[arg(
+        help = "Directory for the extracted frames, such as on a fast or spacious drive, defaults to the system temp directory",
+        long,
+        global = true,
+        env = "DRAGONFLY_TMPDIR"
+    )]
+    tmpdir: Option<PathBuf>,
     #[command(subcommand)]
     subcommand: DragonflySubCommand,
 }
 
 /// Extract rectilinear frames from a equirectangular (360) image
 #[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
 enum DragonflySubCommand {
-    /// Extract rectilinear frames from a equirectangular (360) image, then encode them into a seamless video (mp4, webm, gif)
+    /// Extract rectilinear frames from a equirectangular (360) image, then encode them into a seamless video (mp4, mov, mkv, webm, gif, webp, apng)
     Run {
         #[arg(help = "Path to input 360 image")]
         input_path: PathBuf,
@@ -28,6 +64,226 @@ enum DragonflySubCommand {
         encode_args: dragonfly::EncodeFramesDescriptor,
         #[arg(help = "Path to output media file", default_value = "output.mp4")]
         output_path: PathBuf,
+        #[arg(
+            help = "Stream frames straight into the encoder instead of writing them to a temporary directory",
+            long
+        )]
+        stream: bool,
+        #[command(flatten)]
+        text_args: TextOverlayArgs,
+        #[arg(
+            help = "Draw the text while encoding rather than on each extracted frame, so only {frame} is replaced",
+            long
+        )]
+        text_at_encode: bool,
+        #[arg(
+            help = "Keep the extracted frames after encoding so they can be re-encoded with the encode command",
+            long
+        )]
+        keep_frames: bool,
+        #[arg(
+            help = "Name of the session that keeps the extracted frames, defaults to the input file name",
+            long
+        )]
+        session: Option<String>,
+        #[arg(
+            help = "Save the input, output, and every setting to a project file that the render command can reproduce",
+            long
+        )]
+        save_project: Option<PathBuf>,
+        #[arg(
+            help = "Spin the whole panorama in a 360 video that slowly pans in YouTube and VR players, overrides the projection, pitch, and field of view",
+            long
+        )]
+        rotating_panorama: bool,
+        #[arg(
+            help = "Measure how closely the last frame leads back into the first, to tell whether --loop-blend is needed",
+            long,
+            conflicts_with = "stream"
+        )]
+        loop_quality: bool,
+        #[arg(
+            help = "Path to a JSON or TOML tour of waypoints to turn to and pause on, which sets the camera path, frame count, and video length",
+            long,
+            value_parser = dragonfly::parse_tour,
+            conflicts_with = "camera_path"
+        )]
+        tour: Option<dragonfly::Tour>,
+        #[arg(
+            help = "Encode the frames at this height and optional bitrate, such as 1080p:6M, to {name}_{height}p next to the output path rather than to the output path itself. May be repeated",
+            long = "rendition",
+            conflicts_with = "stream"
+        )]
+        renditions: Vec<dragonfly::Rendition>,
+        #[arg(
+            help = "Number of renditions to encode at the same time",
+            long,
+            default_value = "1"
+        )]
+        rendition_jobs: usize,
+    },
+    /// Render the input of a project file saved with run --save-project, using the project's settings
+    Render {
+        #[arg(help = "Path to the project file")]
+        project_path: PathBuf,
+        #[arg(
+            help = "Path to output media file, defaults to the output saved in the project",
+            long = "output"
+        )]
+        output_path: Option<PathBuf>,
+    },
+    /// Convert many 360 images or videos, one after another or a few at a time
+    Batch {
+        #[arg(
+            help = "Paths to input 360 images and videos, directories are replaced with the files they contain",
+            required = true
+        )]
+        input_paths: Vec<PathBuf>,
+        #[command(flatten)]
+        extract_args: dragonfly::ExtractFramesDescriptor,
+        #[command(flatten)]
+        encode_args: dragonfly::EncodeFramesDescriptor,
+        #[arg(
+            help = "Path of each output media file, where {stem} is the input file name without the extension and {index} the position of the input",
+            long = "output",
+            default_value = "{stem}_spin.mp4"
+        )]
+        output_template: String,
+        #[arg(
+            help = "Number of inputs to convert at once, each running up to --j ffmpeg processes",
+            long,
+            default_value = "1"
+        )]
+        jobs: usize,
+    },
+    /// Rotate through several 360 images in one continuous video, dissolving from each into the next
+    Crossfade {
+        #[arg(
+            help = "Paths to the input 360 images, in the order the video visits them",
+            required = true,
+            num_args = 2..
+        )]
+        input_paths: Vec<PathBuf>,
+        #[command(flatten)]
+        extract_args: dragonfly::ExtractFramesDescriptor,
+        #[command(flatten)]
+        encode_args: dragonfly::EncodeFramesDescriptor,
+        #[arg(
+            help = "Length in seconds of the dissolve between two inputs",
+            long,
+            default_value = "1.0"
+        )]
+        crossfade: f32,
+        #[arg(
+            help = "Path to output media file",
+            long = "output",
+            default_value = "output.mp4"
+        )]
+        output_path: PathBuf,
+    },
+    /// Pan across an ordinary wide panorama that is not a 360 image, then encode the frames into a video
+    Pan {
+        #[arg(help = "Path to input panorama")]
+        input_path: PathBuf,
+        #[command(flatten)]
+        pan_args: dragonfly::PanDescriptor,
+        #[command(flatten)]
+        encode_args: dragonfly::EncodeFramesDescriptor,
+        #[arg(
+            help = "Path to output media file",
+            long = "output",
+            default_value = "output.mp4"
+        )]
+        output_path: PathBuf,
+    },
+    /// Render a time-ordered sequence of 360 stills as one rotation that advances through time as it turns
+    Hyperlapse {
+        #[arg(
+            help = "Paths to the input 360 stills in time order, or directories whose stills are taken in file name order",
+            required = true
+        )]
+        input_paths: Vec<PathBuf>,
+        #[command(flatten)]
+        extract_args: dragonfly::ExtractFramesDescriptor,
+        #[command(flatten)]
+        encode_args: dragonfly::EncodeFramesDescriptor,
+        #[arg(
+            help = "Path to output media file",
+            long = "output",
+            default_value = "output.mp4"
+        )]
+        output_path: PathBuf,
+    },
+    /// Convert each 360 image or video added to a directory, such as the frames of a tethered camera
+    Watch {
+        #[arg(help = "Directory to watch for new 360 images and videos")]
+        dir: PathBuf,
+        #[command(flatten)]
+        extract_args: dragonfly::ExtractFramesDescriptor,
+        #[command(flatten)]
+        encode_args: dragonfly::EncodeFramesDescriptor,
+        #[arg(
+            help = "Path of each output media file, where {stem} is the input file name without the extension and {index} the number of inputs converted before it",
+            long = "output",
+            default_value = "{stem}_spin.mp4"
+        )]
+        output_template: String,
+        #[arg(
+            help = "Seconds between checks for new files, a file is converted once it is unchanged between two checks",
+            long,
+            default_value = "2"
+        )]
+        interval: u64,
+        #[arg(help = "Also convert the files already in the directory", long)]
+        existing: bool,
+    },
+    /// Analyze a 360 image or video and show the resolution its frames would be extracted at
+    Probe {
+        #[arg(help = "Path to input 360 image or video")]
+        input_path: PathBuf,
+        #[command(flatten)]
+        args: dragonfly::ExtractFramesDescriptor,
+    },
+    /// Render a few sample frames to project the extraction time, disk usage, and output size of a run
+    Estimate {
+        #[arg(help = "Path to input 360 image or video")]
+        input_path: PathBuf,
+        #[command(flatten)]
+        extract_args: dragonfly::ExtractFramesDescriptor,
+        #[command(flatten)]
+        encode_args: dragonfly::EncodeFramesDescriptor,
+        #[arg(
+            help = "Path to the output media file, whose extension picks the container",
+            default_value = "output.mp4"
+        )]
+        output_path: PathBuf,
+        #[arg(help = "Number of sample frames to render", long, default_value = "5")]
+        samples: usize,
+    },
+    /// Render a single rectilinear frame to dial in the view before extracting every frame
+    Preview {
+        #[arg(help = "Path to input 360 image or video")]
+        input_path: PathBuf,
+        #[arg(
+            help = "The yaw in degrees of the camera",
+            long,
+            default_value = "0.0",
+            allow_negative_numbers = true
+        )]
+        yaw: f32,
+        #[command(flatten)]
+        args: dragonfly::ExtractFramesDescriptor,
+        #[command(flatten)]
+        text_args: TextOverlayArgs,
+        #[arg(
+            help = "Path to output image",
+            short,
+            long = "output",
+            default_value = "preview.jpg"
+        )]
+        output_path: PathBuf,
+        #[arg(help = "Draw the rendered frame in the terminal", long)]
+        show: bool,
     },
     /// Extract rectilinear frames from a equirectangular (360) image
     Extract {
@@ -35,54 +291,467 @@ enum DragonflySubCommand {
         input_path: PathBuf,
         #[command(flatten)]
         args: dragonfly::ExtractFramesDescriptor,
+        #[arg(
+            help = "Render a rotating tiny planet, overrides the projection, pitch, and field of view",
+            long
+        )]
+        little_planet: bool,
+        #[arg(
+            help = "Spin the whole panorama, keeping the equirectangular projection, overrides the projection, pitch, and field of view",
+            long,
+            conflicts_with = "little_planet"
+        )]
+        rotating_panorama: bool,
+        #[command(flatten)]
+        text_args: TextOverlayArgs,
         #[arg(help = "Output directory for extracted frames, defaults to a temporary directory")]
         extract_path: Option<PathBuf>,
+        #[arg(
+            help = "Name of the session to register the frames under, defaults to the input file name",
+            long
+        )]
+        session: Option<String>,
+        #[arg(help = "Draw the first extracted frame in the terminal", long)]
+        show: bool,
     },
-    /// Encode extracted rectilinear frames into a seamless video (mp4, webm, gif)
+    /// Encode extracted rectilinear frames into a seamless video (mp4, mov, mkv, webm, gif, webp, apng)
     Encode {
-        #[arg(help = "Path to directory containing extracted images")]
+        #[arg(
+            help = "Path to directory containing extracted images, defaults to the frames of the session"
+        )]
         extract_path: Option<PathBuf>,
+        #[arg(
+            help = "Name of the session whose frames to encode, defaults to the most recent session",
+            long,
+            conflicts_with = "extract_path"
+        )]
+        session: Option<String>,
         #[command(flatten)]
         args: dragonfly::EncodeFramesDescriptor,
         #[arg(
-            help = "Path to output media file",
+            help = "Path to output media file. May be repeated to encode the same frames to several formats, such as --output output.mp4 --output output.webm --output output.gif",
             long = "output",
             default_value = "output.mp4"
         )]
+        output_paths: Vec<PathBuf>,
+        #[command(flatten)]
+        text_args: TextOverlayArgs,
+        #[arg(
+            help = "Measure how closely the last frame leads back into the first, to tell whether --loop-blend is needed",
+            long
+        )]
+        loop_quality: bool,
+    },
+    /// Check whether an encoded video loops seamlessly, comparing its last frame with its first
+    VerifyLoop {
+        #[arg(help = "Path to the encoded video")]
+        video_path: PathBuf,
+        #[command(flatten)]
+        args: dragonfly::ExtractFramesDescriptor,
+    },
+    /// Export the six cube faces of a 360 image, such as for a game engine skybox
+    Cubemap {
+        #[arg(help = "Path to input 360 image")]
+        input_path: PathBuf,
+        #[command(flatten)]
+        args: dragonfly::CubemapDescriptor,
+        #[arg(
+            help = "Path to output image, which each face is appended to when writing one image per face",
+            short,
+            long = "output",
+            default_value = "cubemap.png"
+        )]
         output_path: PathBuf,
     },
+    /// Tile evenly spaced extracted frames into a single image, giving an overview of the whole rotation
+    Montage {
+        #[arg(
+            help = "Path to directory containing extracted images, defaults to the frames of the session"
+        )]
+        extract_path: Option<PathBuf>,
+        #[arg(
+            help = "Name of the session whose frames to tile, defaults to the most recent session",
+            long,
+            conflicts_with = "extract_path"
+        )]
+        session: Option<String>,
+        #[command(flatten)]
+        args: dragonfly::MontageDescriptor,
+        #[arg(
+            help = "Path to output image",
+            short,
+            long = "output",
+            default_value = "montage.jpg"
+        )]
+        output_path: PathBuf,
+        #[arg(help = "Draw the montage in the terminal", long)]
+        show: bool,
+    },
+    /// Remove extracted frames left in the temporary directory, or the --tmpdir, by previous extract and run commands
+    Clean {
+        #[arg(
+            help = "Only remove directories last modified at least this many hours ago",
+            long,
+            default_value = "0"
+        )]
+        older_than: u64,
+    },
+    /// Manage the named sessions of extracted frames
+    Sessions {
+        #[command(subcommand)]
+        subcommand: SessionsSubCommand,
+    },
+    /// Print a shell completion script, for example `dragonfly completions bash > /etc/bash_completion.d/dragonfly`
+    Completions { shell: completions::Shell },
+}
+
+#[derive(Subcommand, Debug)]
+enum SessionsSubCommand {
+    /// List every session, oldest first
+    List,
+    /// Show the details of a session
+    Show { name: String },
+    /// Remove a session, along with its frames if they are in the temporary directory
+    Rm { name: String },
+}
+
+/// Options for drawing text on every frame
+#[derive(Args, Debug)]
+struct TextOverlayArgs {
+    #[arg(
+        help = "Text to draw on every frame, which may reference {filename}, {frame}, {yaw}, {pitch}, {roll}, {h_fov}, and {v_fov}",
+        long
+    )]
+    text: Option<String>,
+    #[arg(
+        help = "Path to the font file used to draw the text, defaults to the system font",
+        long
+    )]
+    text_font: Option<PathBuf>,
+    #[arg(help = "Position of the text", long, default_value = "bottom-left")]
+    text_position: dragonfly::Anchor,
+    #[arg(help = "Font size of the text", long, default_value = "32")]
+    text_size: u32,
+    #[arg(
+        help = "Color of the text, as an ffmpeg color name or hex value",
+        long,
+        default_value = "white"
+    )]
+    text_color: String,
+    #[arg(
+        help = "Distance in pixels between the text and the edge of the frame",
+        long,
+        default_value = "16"
+    )]
+    text_margin: u32,
+}
+
+impl TextOverlayArgs {
+    fn into_text_overlay(self) -> Option<dragonfly::TextOverlay> {
+        Some(dragonfly::TextOverlay {
+            text: self.text?,
+            font_file: self.text_font,
+            position: self.text_position,
+            font_size: self.text_size,
+            font_color: self.text_color,
+            margin: self.text_margin,
+        })
+    }
 }
 
 lazy_static::lazy_static! {
     pub static ref DRAGONFLY_TEMP_DIR: PathBuf = std::env::var("DRAGONFLY_TEMP_DIR")
         .map(PathBuf::from).unwrap_or_else(|_| temp_dir());
-    pub static ref DRAGONFLY_EXTRACT_DIR_CACHE_FILE: PathBuf = DRAGONFLY_TEMP_DIR.join(".dragonfly");
 }
 
-/// Creates a temporary directory to hold the extracted frames
-/// Returns the path to the directory
-fn create_tmp_extract_dir() -> anyhow::Result<PathBuf> {
-    let extraction_path = DRAGONFLY_TEMP_DIR.join(format!(
-        "com.jshrake.dragonfly-{}",
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)?
-            .as_secs()
-    ));
-    std::fs::create_dir_all(&extraction_path)?;
-    Ok(extraction_path)
+/// Advances the progress bar as frames are extracted
+fn extract_progress(output: &Output, pb: &ProgressBar, event: dragonfly::ProgressEvent) {
+    output.progress(&event);
+    match event {
+        // Video inputs extract one frame per source frame rather than the requested count
+        dragonfly::ProgressEvent::ExtractStarted { frame_count } => {
+            pb.set_length(frame_count as u64)
+        }
+        dragonfly::ProgressEvent::FrameExtracted { .. } => pb.inc(1),
+        _ => {}
+    }
 }
 
-fn store_extract_dir(dir: &Path) -> anyhow::Result<()> {
-    let mut file = File::create(DRAGONFLY_EXTRACT_DIR_CACHE_FILE.clone())?;
-    file.write_all(dir.as_os_str().as_bytes())?;
+/// Shows the encoder's progress next to the spinner
+fn encode_progress(output: &Output, pb: &ProgressBar, event: dragonfly::ProgressEvent) {
+    output.progress(&event);
+    if let dragonfly::ProgressEvent::EncodeProgress { frame, fps } = event {
+        pb.set_message(format!("Encoding... {frame} frames at {fps:.1} fps"));
+    }
+}
+
+/// Tags equirectangular output as 360 video where the container allows, since it is itself 360 video
+fn tag_spherical(
+    extract_args: &dragonfly::ExtractFramesDescriptor,
+    encode_args: &mut dragonfly::EncodeFramesDescriptor,
+    output_path: &Path,
+) {
+    if extract_args.projection == dragonfly::OutputProjection::Equirectangular
+        && output_path
+            .extension()
+            .is_some_and(|ext| ext == "mp4" || ext == "mov")
+    {
+        encode_args.spherical = true;
+    }
+}
+
+/// Extracts frames to a temporary directory and encodes them, returning the directory so the caller can keep it
+fn render(
+    input_path: &Path,
+    output_path: &Path,
+    extract_args: &dragonfly::ExtractFramesDescriptor,
+    encode_args: &dragonfly::EncodeFramesDescriptor,
+    tmpdir: &Path,
+    output: &Output,
+) -> anyhow::Result<(std::process::ExitStatus, dragonfly::ExtractionDir)> {
+    let (report, extraction_dir) = extract(input_path, extract_args, tmpdir, output)?;
+    let extract_path = extraction_dir.path();
+    output.write_line(&format!(
+        "Encoding frames from {:?} to {:?}",
+        extract_path, output_path
+    ))?;
+    let pb = output.spinner();
+    let encode_start = Instant::now();
+    let status = dragonfly::encode_frames(
+        output_path,
+        extract_path,
+        encode_args,
+        Some(|event| encode_progress(output, &pb, event)),
+        interrupt::token(),
+    )?;
+    pb.finish_and_clear();
+    output.event(json!({
+        "event": "rendered",
+        "input_path": input_path,
+        "output_path": output_path,
+        "exit_code": status.code(),
+        "frame_count": report.frames.len(),
+        "width": report.width,
+        "height": report.height,
+        "extract_duration_secs": report.duration.as_secs_f64(),
+        "encode_duration_secs": encode_start.elapsed().as_secs_f64(),
+    }))?;
+    Ok((status, extraction_dir))
+}
+
+/// Extracts the frames of an input to a new temporary directory
+fn extract(
+    input_path: &Path,
+    extract_args: &dragonfly::ExtractFramesDescriptor,
+    tmpdir: &Path,
+    output: &Output,
+) -> anyhow::Result<(dragonfly::ExtractReport, dragonfly::ExtractionDir)> {
+    // The frames are removed when the directory is dropped, including on failure, unless kept
+    let extraction_dir = dragonfly::ExtractionDir::new_in(tmpdir)?;
+    let extract_path = extraction_dir.path();
+    output.write_line(&format!(
+        "Extracting {} frames from {:?} to {:?}",
+        extract_args.frame_count, input_path, extract_path
+    ))?;
+    let pb = output.progress_bar(extract_args.frame_count as u64);
+    let report = dragonfly::extract_frames(
+        input_path,
+        extract_path,
+        extract_args,
+        Some(|event| extract_progress(output, &pb, event)),
+        interrupt::token(),
+    )?;
+    pb.finish_and_clear();
+    Ok((report, extraction_dir))
+}
+
+/// Encodes every rendition from the frames extracted once from an input, `jobs` at a time
+fn render_renditions(
+    input_path: &Path,
+    output_path: &Path,
+    (report, extraction_dir): (dragonfly::ExtractReport, dragonfly::ExtractionDir),
+    encode_args: &dragonfly::EncodeFramesDescriptor,
+    renditions: &[dragonfly::Rendition],
+    jobs: usize,
+    output: &Output,
+) -> anyhow::Result<(std::process::ExitStatus, dragonfly::ExtractionDir)> {
+    let extract_path = extraction_dir.path();
+    output.write_line(&format!(
+        "Encoding {} renditions from {:?}",
+        renditions.len(),
+        extract_path
+    ))?;
+    let pb = output.spinner();
+    let encode_start = Instant::now();
+    let encoded = dragonfly::encode_renditions(
+        output_path,
+        extract_path,
+        encode_args,
+        renditions,
+        jobs,
+        interrupt::token(),
+    )?;
+    pb.finish_and_clear();
+    for (rendition, (rendition_path, status)) in renditions.iter().zip(&encoded) {
+        output.write_line(&format!("Encoded {rendition} to {rendition_path:?}"))?;
+        output.event(json!({
+            "event": "rendered",
+            "input_path": input_path,
+            "output_path": rendition_path,
+            "rendition": rendition,
+            "exit_code": status.code(),
+            "frame_count": report.frames.len(),
+            "extract_duration_secs": report.duration.as_secs_f64(),
+            "encode_duration_secs": encode_start.elapsed().as_secs_f64(),
+        }))?;
+    }
+    // Report the first failed encoder, if any
+    let status = encoded
+        .iter()
+        .map(|(_, status)| *status)
+        .find(|status| !status.success())
+        .or_else(|| encoded.first().map(|(_, status)| *status))
+        .ok_or_else(|| anyhow::anyhow!("no renditions to encode"))?;
+    Ok((status, extraction_dir))
+}
+
+/// Prints how seamlessly the extracted frames loop, suggesting --loop-blend when the seam pops
+fn report_loop_quality(output: &Output, extract_path: &Path) -> anyhow::Result<()> {
+    let quality = dragonfly::loop_quality(extract_path)?;
+    output.write_line(&format!(
+        "Loop seam SSIM {:.4}, neighboring frames {:.4}{}",
+        quality.seam_ssim,
+        quality.step_ssim,
+        if quality.pops() {
+            ", the loop may pop, try --loop-blend"
+        } else {
+            ""
+        }
+    ))?;
+    output.event(json!({ "event": "loop_quality", "quality": quality, "pops": quality.pops() }))?;
+    Ok(())
+}
+
+/// Emits the result of converting one input of a batch or watch, returning whether it succeeded and a message
+/// describing it
+fn report_conversion(
+    output: &Output,
+    input_path: &Path,
+    output_path: &Path,
+    result: anyhow::Result<std::process::ExitStatus>,
+) -> (bool, String) {
+    let error = match result {
+        Ok(status) if status.success() => None,
+        Ok(status) => Some(format!("ffmpeg {status}")),
+        Err(e) => Some(format!("{e:#}")),
+    };
+    let event = json!({
+        "event": if error.is_some() { "convert_failed" } else { "converted" },
+        "input_path": input_path,
+        "output_path": output_path,
+        "message": error,
+    });
+    // A closed stdout shows up when the batch or watch writes its next message
+    output.event(event).ok();
+    match error {
+        Some(error) => (false, format!("Failed to convert {input_path:?}: {error}")),
+        None => (true, format!("Converted {input_path:?} to {output_path:?}")),
+    }
+}
+
+/// Returns the frames the user specified by path or session, or the frames of the most recent session
+fn resolve_extract_path(
+    extract_path: Option<PathBuf>,
+    session: Option<String>,
+    output: &Output,
+) -> anyhow::Result<PathBuf> {
+    Ok(match (extract_path, session) {
+        (Some(extract_path), _) => extract_path,
+        (None, Some(name)) => Session::load(&name)?.extract_path,
+        (None, None) => {
+            if let Some(session) = Session::latest()? {
+                session.extract_path
+            } else {
+                output.error(
+                    "Unable to find an extraction session. Please specify the extract path explicitly.",
+                )?;
+                std::process::exit(exitcode::USAGE);
+            }
+        }
+    })
+}
+
+/// Exits if the output would be encoded with an encoder this ffmpeg build lacks
+fn check_encoder(
+    environment: &dragonfly::EnvironmentReport,
+    args: &dragonfly::EncodeFramesDescriptor,
+    output_path: &Path,
+    output: &Output,
+) -> anyhow::Result<()> {
+    if let Some(encoder) = args.video_encoder(output_path)? {
+        if !environment.has_encoder(encoder) {
+            output.error(&format!(
+                "ffmpeg was built without the {encoder} encoder, please choose another with --encoder or --codec"
+            ))?;
+            std::process::exit(exitcode::UNAVAILABLE);
+        }
+    }
     Ok(())
 }
 
-fn retrieve_extract_dir() -> anyhow::Result<PathBuf> {
-    let mut file = File::open(DRAGONFLY_EXTRACT_DIR_CACHE_FILE.clone())?;
-    let mut buf = String::new();
-    file.read_to_string(&mut buf)?;
-    Ok(buf.into())
+/// Returns the encode settings for one of several outputs of the same frames
+///
+/// Only the first output exports the poster and sprite sheet, and outputs in another format than the first
+/// use the default encoder of their format and drop spherical metadata the format cannot hold.
+fn output_args(
+    args: &dragonfly::EncodeFramesDescriptor,
+    first_output_path: &Path,
+    output_path: &Path,
+) -> dragonfly::EncodeFramesDescriptor {
+    if output_path == first_output_path {
+        return args.clone();
+    }
+    let mut args = dragonfly::EncodeFramesDescriptor {
+        poster: None,
+        sprite_sheet: None,
+        ..args.clone()
+    };
+    if output_path.extension() != first_output_path.extension() {
+        args.codec = None;
+        args.encoder = None;
+        // Only mp4 and mov carry spherical metadata
+        args.spherical &= output_path
+            .extension()
+            .is_some_and(|extension| extension == "mp4" || extension == "mov");
+    }
+    args
+}
+
+/// Describes a stream on one line, such as "video hevc 3840x1920 yuv420p10le at 29.970 fps, rotated 90 degrees"
+fn stream_summary(stream: &dragonfly::StreamInfo) -> String {
+    let mut summary = stream.kind.to_string();
+    if let Some(codec) = &stream.codec {
+        summary.push_str(&format!(" {codec}"));
+    }
+    if let (Some(width), Some(height)) = (stream.width, stream.height) {
+        summary.push_str(&format!(" {width}x{height}"));
+    }
+    if let Some(pix_fmt) = &stream.pix_fmt {
+        summary.push_str(&format!(" {pix_fmt}"));
+    }
+    if let (dragonfly::StreamKind::Video, Some(frame_rate)) = (stream.kind, stream.frame_rate) {
+        summary.push_str(&format!(" at {frame_rate:.3} fps"));
+    }
+    if let (Some(sample_rate), Some(channels)) = (stream.sample_rate, stream.channels) {
+        summary.push_str(&format!(" {sample_rate} Hz, {channels} channels"));
+    }
+    match stream.rotation {
+        Some(rotation) if rotation != 0.0 => {
+            summary.push_str(&format!(", rotated {rotation} degrees"))
+        }
+        _ => {}
+    }
+    summary
 }
 
 fn main() -> anyhow::Result<()> {
@@ -90,110 +759,891 @@ fn main() -> anyhow::Result<()> {
     env_logger::init();
 
     let cli = DragonflyCli::parse();
-    let stdout = console::Term::stdout();
-    let stderr = console::Term::stderr();
-    // Ensure all required binaries are on the PATH
-    let required_binaries = [
-        dragonfly::FFMPEG_BINARY_PATH.as_os_str(),
-        dragonfly::FFPROBE_BINARY_PATH.as_os_str(),
-    ];
-    for required_binary in required_binaries {
-        if let Some(binary_name) = required_binary.to_str() {
-            if which(binary_name).is_err() {
-                stderr.write_line(&format!(
-                    "\"{}\" not found, please install it at https://ffmpeg.org/",
-                    binary_name
-                ))?;
-                std::process::exit(exitcode::UNAVAILABLE);
-            }
+    let output = Output::new(cli.json);
+    interrupt::install_handler();
+    // Only the frames move to --tmpdir, the session registry stays where every run can find it
+    let tmpdir = cli.tmpdir.unwrap_or_else(|| DRAGONFLY_TEMP_DIR.clone());
+    dragonfly::set_process_policy(dragonfly::ProcessPolicy {
+        timeout: cli.timeout.map(Duration::from_secs_f64),
+        retries: cli.retries,
+    });
+    let hint = resume_hint(&cli.subcommand);
+    if let Err(e) = execute(cli.subcommand, &tmpdir, &output) {
+        if interrupt::is_interrupted() {
+            output.error(&format!(
+                "Interrupted, stopped ffmpeg and removed the partial output. {hint}"
+            ))?;
+            // The conventional exit code of a process ended by SIGINT
+            std::process::exit(130);
         }
+        let out_of_space = e.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref(),
+                Some(dragonfly::DragonflyError::InsufficientDiskSpace { .. })
+            )
+        });
+        if out_of_space {
+            output.error_with_hint(
+                &e,
+                "Free up space, pass --tmpdir to extract to a larger disk, or pass --no-disk-check to extract anyway",
+            )?;
+            // The exit code main returns along with any other error
+            std::process::exit(1);
+        }
+        // Scripts reading JSON events learn of the failure without parsing stderr
+        output.event(json!({ "event": "error", "message": format!("{e:#}") }))?;
+        return Err(e);
     }
+    std::process::exit(exitcode::OK);
+}
 
-    match cli.subcommand {
-        DragonflySubCommand::Run { .. } => {
-            todo!();
+/// Returns how to pick up the work of the subcommand after Ctrl-C interrupts it
+fn resume_hint(subcommand: &DragonflySubCommand) -> String {
+    match subcommand {
+        DragonflySubCommand::Extract {
+            extract_path: Some(extract_path),
+            ..
+        } => format!(
+            "Run the same command again to resume from the frames already extracted to {extract_path:?}"
+        ),
+        DragonflySubCommand::Batch { .. } => {
+            "Run the command again on the inputs that were not converted".to_string()
+        }
+        _ => "Run the command again to start over, or extract to a directory with the extract command \
+            to be able to resume an interrupted extraction"
+            .to_string(),
+    }
+}
+
+fn execute(subcommand: DragonflySubCommand, tmpdir: &Path, output: &Output) -> anyhow::Result<()> {
+    let needs_ffmpeg = !matches!(
+        subcommand,
+        DragonflySubCommand::Clean { .. }
+            | DragonflySubCommand::Sessions { .. }
+            | DragonflySubCommand::Completions { .. }
+    );
+    #[cfg(feature = "download")]
+    if needs_ffmpeg {
+        dragonfly::ensure_ffmpeg()?;
+    }
+    // Ensure ffmpeg is installed with the features dragonfly needs before starting any work
+    let environment = dragonfly::check_environment();
+    let errors = environment.errors();
+    if !errors.is_empty() && needs_ffmpeg {
+        for error in errors {
+            output.error(&error)?;
+        }
+        std::process::exit(exitcode::UNAVAILABLE);
+    }
+
+    match subcommand {
+        DragonflySubCommand::Run {
+            input_path,
+            extract_args,
+            encode_args,
+            output_path,
+            stream,
+            text_args,
+            text_at_encode,
+            keep_frames,
+            session,
+            save_project,
+            rotating_panorama,
+            loop_quality,
+            tour,
+            renditions,
+            rendition_jobs,
+        } => {
+            let mut extract_args = if rotating_panorama {
+                extract_args.with_rotating_panorama()
+            } else {
+                extract_args
+            };
+            let mut encode_args = encode_args;
+            encode_args.downsample = extract_args.supersample;
+            if let Some(tour) = tour {
+                // Render one frame per output frame so the dwell and transition times play in real time
+                encode_args.length = tour.duration();
+                extract_args.frame_count =
+                    (tour.duration() * encode_args.fps).round().max(1.0) as usize;
+                extract_args.camera_path = Some(tour.camera_path()?);
+            }
+            if text_at_encode {
+                encode_args.text_overlay = text_args.into_text_overlay();
+            } else {
+                extract_args.text_overlay = text_args.into_text_overlay();
+            }
+            tag_spherical(&extract_args, &mut encode_args, &output_path);
+            if let Some(project_path) = save_project {
+                let project = dragonfly::Project {
+                    input_path: input_path.clone(),
+                    output_path: output_path.clone(),
+                    extract: extract_args.clone(),
+                    encode: encode_args.clone(),
+                };
+                project.write(&project_path)?;
+                output.write_line(&format!("Saved project to {project_path:?}"))?;
+                output.event(json!({ "event": "project_saved", "path": project_path }))?;
+            }
+            // Catch a misspelled preset or missing encoder before spending time on extraction
+            check_encoder(&environment, &encode_args, &output_path, output)?;
+            let status = if stream {
+                output.write_line(&format!(
+                    "Streaming {} frames from {:?} to {:?}",
+                    extract_args.frame_count, input_path, output_path
+                ))?;
+                let pb = output.progress_bar(extract_args.frame_count as u64);
+                let start = Instant::now();
+                let status = dragonfly::extract_and_encode(
+                    &input_path,
+                    &output_path,
+                    &extract_args,
+                    &encode_args,
+                    Some(|event| {
+                        output.progress(&event);
+                        if let dragonfly::ProgressEvent::FrameExtracted { .. } = event {
+                            pb.inc(1);
+                        }
+                    }),
+                    interrupt::token(),
+                )?;
+                pb.finish_and_clear();
+                output.event(json!({
+                    "event": "rendered",
+                    "input_path": input_path,
+                    "output_path": output_path,
+                    "exit_code": status.code(),
+                    "frame_count": extract_args.frame_count,
+                    "duration_secs": start.elapsed().as_secs_f64(),
+                }))?;
+                status
+            } else {
+                let (status, extraction_dir) = if renditions.is_empty() {
+                    render(
+                        &input_path,
+                        &output_path,
+                        &extract_args,
+                        &encode_args,
+                        tmpdir,
+                        output,
+                    )?
+                } else {
+                    render_renditions(
+                        &input_path,
+                        &output_path,
+                        extract(&input_path, &extract_args, tmpdir, output)?,
+                        &encode_args,
+                        &renditions,
+                        rendition_jobs,
+                        output,
+                    )?
+                };
+                if loop_quality {
+                    report_loop_quality(output, extraction_dir.path())?;
+                }
+                if keep_frames || session.is_some() {
+                    let extract_path = extraction_dir.keep();
+                    // Register the frames so they can be re-encoded with different settings
+                    let name = session.unwrap_or_else(|| session::input_session_name(&input_path));
+                    let session = Session::new(&name, &input_path, &extract_path, true);
+                    session.save()?;
+                    output.write_line(&format!(
+                        "Kept extracted frames in {:?} as session {:?}",
+                        extract_path, session.name
+                    ))?;
+                    output.event(json!({ "event": "session_saved", "session": session }))?;
+                }
+                status
+            };
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(exitcode::SOFTWARE));
+            }
+        }
+        DragonflySubCommand::Render {
+            project_path,
+            output_path,
+        } => {
+            let project = dragonfly::Project::read(&project_path)?;
+            let output_path = output_path.unwrap_or(project.output_path);
+            check_encoder(&environment, &project.encode, &output_path, output)?;
+            let (status, _) = render(
+                &project.input_path,
+                &output_path,
+                &project.extract,
+                &project.encode,
+                tmpdir,
+                output,
+            )?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(exitcode::SOFTWARE));
+            }
+        }
+        DragonflySubCommand::Batch {
+            input_paths,
+            extract_args,
+            encode_args,
+            output_template,
+            jobs,
+        } => {
+            let input_paths = batch::input_paths(&input_paths)?;
+            let output_paths = batch::output_paths(&output_template, &input_paths)?;
+            let mut encode_args = encode_args;
+            encode_args.downsample = extract_args.supersample;
+            if let Some(output_path) = output_paths.first() {
+                tag_spherical(&extract_args, &mut encode_args, output_path);
+                check_encoder(&environment, &encode_args, output_path, output)?;
+            }
+            output.write_line(&format!("Converting {} inputs", input_paths.len()))?;
+            let pb = output.progress_bar(input_paths.len() as u64);
+            // Each worker takes the next unconverted input until none are left
+            let next_input = AtomicUsize::new(0);
+            let failed = Mutex::new(vec![]);
+            std::thread::scope(|scope| {
+                for _ in 0..jobs.max(1) {
+                    scope.spawn(|| loop {
+                        if interrupt::is_interrupted() {
+                            break;
+                        }
+                        let index = next_input.fetch_add(1, Ordering::Relaxed);
+                        let (Some(input_path), Some(output_path)) =
+                            (input_paths.get(index), output_paths.get(index))
+                        else {
+                            break;
+                        };
+                        let result = batch::convert(
+                            input_path,
+                            output_path,
+                            &extract_args,
+                            &encode_args,
+                            tmpdir,
+                        );
+                        let (converted, message) =
+                            report_conversion(output, input_path, output_path, result);
+                        if !converted {
+                            failed.lock().unwrap().push(input_path);
+                        }
+                        pb.println(message);
+                        pb.inc(1);
+                    });
+                }
+            });
+            pb.finish_and_clear();
+            if interrupt::is_interrupted() {
+                return Err(dragonfly::DragonflyError::Cancelled.into());
+            }
+            // Keep converting after a failure so one bad file does not cost the rest of the shoot
+            let failed = failed.into_inner().unwrap();
+            output.write_line(&format!(
+                "Converted {} of {} inputs",
+                input_paths.len() - failed.len(),
+                input_paths.len()
+            ))?;
+            output.event(json!({
+                "event": "batch_finished",
+                "converted": input_paths.len() - failed.len(),
+                "failed": failed,
+            }))?;
+            if !failed.is_empty() {
+                std::process::exit(exitcode::SOFTWARE);
+            }
+        }
+        DragonflySubCommand::Crossfade {
+            input_paths,
+            extract_args,
+            encode_args,
+            crossfade,
+            output_path,
+        } => {
+            let mut encode_args = encode_args;
+            encode_args.downsample = extract_args.supersample;
+            tag_spherical(&extract_args, &mut encode_args, &output_path);
+            check_encoder(&environment, &encode_args, &output_path, output)?;
+            // The extracted frames are spread over the length of the video
+            let crossfade_frames =
+                (crossfade / encode_args.length * extract_args.frame_count as f32).round() as usize;
+            let sequence =
+                dragonfly::CrossfadeSequence::new(&input_paths, &extract_args, crossfade_frames)?;
+            let mut segment_dirs = vec![];
+            for (input_path, segment) in sequence.input_paths.iter().zip(&sequence.segments) {
+                let segment_dir = dragonfly::ExtractionDir::new_in(tmpdir)?;
+                output.write_line(&format!(
+                    "Extracting {} frames from {:?} to {:?}",
+                    segment.frame_count,
+                    input_path,
+                    segment_dir.path()
+                ))?;
+                let pb = output.progress_bar(segment.frame_count as u64);
+                dragonfly::extract_frames(
+                    input_path,
+                    segment_dir.path(),
+                    segment,
+                    Some(|event| extract_progress(output, &pb, event)),
+                    interrupt::token(),
+                )?;
+                pb.finish_and_clear();
+                segment_dirs.push(segment_dir);
+            }
+            let extraction_dir = dragonfly::ExtractionDir::new_in(tmpdir)?;
+            let extract_path = extraction_dir.path();
+            output.write_line(&format!(
+                "Blending {} frames into {:?}",
+                sequence.frame_count(),
+                extract_path
+            ))?;
+            let segment_paths = segment_dirs
+                .iter()
+                .map(|dir| dir.path().to_path_buf())
+                .collect::<Vec<_>>();
+            sequence.assemble(&segment_paths, extract_path)?;
+            drop(segment_dirs);
+            output.write_line(&format!(
+                "Encoding frames from {:?} to {:?}",
+                extract_path, output_path
+            ))?;
+            let pb = output.spinner();
+            let status = dragonfly::encode_frames(
+                &output_path,
+                extract_path,
+                &encode_args,
+                Some(|event| encode_progress(output, &pb, event)),
+                interrupt::token(),
+            )?;
+            pb.finish_and_clear();
+            output.event(json!({
+                "event": "crossfaded",
+                "input_paths": input_paths,
+                "output_path": output_path,
+                "exit_code": status.code(),
+                "frame_count": sequence.frame_count(),
+            }))?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(exitcode::SOFTWARE));
+            }
+        }
+        DragonflySubCommand::Pan {
+            input_path,
+            pan_args,
+            encode_args,
+            output_path,
+        } => {
+            check_encoder(&environment, &encode_args, &output_path, output)?;
+            let extraction_dir = dragonfly::ExtractionDir::new_in(tmpdir)?;
+            let extract_path = extraction_dir.path();
+            output.write_line(&format!(
+                "Extracting {} frames from {:?} to {:?}",
+                pan_args.frame_count, input_path, extract_path
+            ))?;
+            let pb = output.progress_bar(pan_args.frame_count as u64);
+            dragonfly::extract_pan_frames(
+                &input_path,
+                extract_path,
+                &pan_args,
+                Some(|event| extract_progress(output, &pb, event)),
+                interrupt::token(),
+            )?;
+            pb.finish_and_clear();
+            output.write_line(&format!(
+                "Encoding frames from {:?} to {:?}",
+                extract_path, output_path
+            ))?;
+            let pb = output.spinner();
+            let status = dragonfly::encode_frames(
+                &output_path,
+                extract_path,
+                &encode_args,
+                Some(|event| encode_progress(output, &pb, event)),
+                interrupt::token(),
+            )?;
+            pb.finish_and_clear();
+            output.event(json!({
+                "event": "panned",
+                "input_path": input_path,
+                "output_path": output_path,
+                "exit_code": status.code(),
+                "frame_count": pan_args.frame_count,
+            }))?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(exitcode::SOFTWARE));
+            }
+        }
+        DragonflySubCommand::Hyperlapse {
+            input_paths,
+            extract_args,
+            encode_args,
+            output_path,
+        } => {
+            let mut encode_args = encode_args;
+            encode_args.downsample = extract_args.supersample;
+            tag_spherical(&extract_args, &mut encode_args, &output_path);
+            check_encoder(&environment, &encode_args, &output_path, output)?;
+            let sequence = dragonfly::HyperlapseSequence::new(&batch::input_paths(&input_paths)?)?;
+            let extraction_dir = dragonfly::ExtractionDir::new_in(tmpdir)?;
+            let extract_path = extraction_dir.path();
+            output.write_line(&format!(
+                "Extracting {} frames from {} stills to {:?}",
+                extract_args.frame_count,
+                sequence.input_paths.len(),
+                extract_path
+            ))?;
+            let pb = output.progress_bar(extract_args.frame_count as u64);
+            sequence.extract(
+                extract_path,
+                &extract_args,
+                Some(|event| extract_progress(output, &pb, event)),
+                interrupt::token(),
+            )?;
+            pb.finish_and_clear();
+            output.write_line(&format!(
+                "Encoding frames from {:?} to {:?}",
+                extract_path, output_path
+            ))?;
+            let pb = output.spinner();
+            let status = dragonfly::encode_frames(
+                &output_path,
+                extract_path,
+                &encode_args,
+                Some(|event| encode_progress(output, &pb, event)),
+                interrupt::token(),
+            )?;
+            pb.finish_and_clear();
+            output.event(json!({
+                "event": "hyperlapsed",
+                "input_paths": sequence.input_paths,
+                "output_path": output_path,
+                "exit_code": status.code(),
+                "frame_count": extract_args.frame_count,
+            }))?;
+            if !status.success() {
+                std::process::exit(status.code().unwrap_or(exitcode::SOFTWARE));
+            }
+        }
+        DragonflySubCommand::Watch {
+            dir,
+            extract_args,
+            encode_args,
+            output_template,
+            interval,
+            existing,
+        } => {
+            let mut encode_args = encode_args;
+            encode_args.downsample = extract_args.supersample;
+            let first_output_path = batch::output_path(&output_template, &dir, 0);
+            tag_spherical(&extract_args, &mut encode_args, &first_output_path);
+            check_encoder(&environment, &encode_args, &first_output_path, output)?;
+            let mut watcher = watch::DirectoryWatcher::new(&dir, existing)?;
+            output.write_line(&format!(
+                "Watching {dir:?} for new 360 images and videos, press Ctrl-C to stop"
+            ))?;
+            // Runs until interrupted, converting each file once it has been completely written
+            let mut index = 0;
+            while !interrupt::is_interrupted() {
+                for input_path in watcher.poll()? {
+                    let output_path = batch::output_path(&output_template, &input_path, index);
+                    watcher.ignore(&output_path);
+                    output.write_line(&format!("Converting {input_path:?} to {output_path:?}"))?;
+                    // A failed conversion is reported without ending the session
+                    let result = batch::convert(
+                        &input_path,
+                        &output_path,
+                        &extract_args,
+                        &encode_args,
+                        tmpdir,
+                    );
+                    if interrupt::is_interrupted() {
+                        break;
+                    }
+                    let (_, message) = report_conversion(output, &input_path, &output_path, result);
+                    output.write_line(&message)?;
+                    index += 1;
+                }
+                std::thread::sleep(Duration::from_secs(interval));
+            }
+            output.write_line("Stopped watching")?;
+        }
+        DragonflySubCommand::Probe { input_path, args } => {
+            let probe = dragonfly::probe_input(&input_path)?;
+            let args = match &probe.gpano {
+                Some(gpano) => args.with_gpano(gpano),
+                None => args,
+            };
+            let media = dragonfly::probe(&input_path)?;
+            let (output_width, output_height) = args.output_resolution(probe.width, probe.height);
+            output.write_line(&format!("Resolution: {}x{}", probe.width, probe.height))?;
+            output.write_line(&format!("Aspect ratio: {:.3}", probe.aspect_ratio))?;
+            if let Some(codec) = &probe.codec {
+                output.write_line(&format!("Codec: {codec}"))?;
+            }
+            if let Some(pix_fmt) = &probe.pix_fmt {
+                output.write_line(&format!("Pixel format: {pix_fmt}"))?;
+            }
+            if let Some(bit_depth) = probe.bit_depth {
+                output.write_line(&format!("Bit depth: {bit_depth}"))?;
+            }
+            if let Some(video) = &probe.video {
+                output.write_line(&format!(
+                    "Video: {} frames at {:.3} fps",
+                    video.frame_count, video.frame_rate
+                ))?;
+            }
+            output.write_line(&format!("Projection: {}", probe.projection))?;
+            if let Some(gpano) = &probe.gpano {
+                if let (Some(w), Some(h)) =
+                    (gpano.full_pano_width_pixels, gpano.full_pano_height_pixels)
+                {
+                    output.write_line(&format!("GPano full panorama: {w}x{h}"))?;
+                }
+                if let (Some(w), Some(h)) = (
+                    gpano.cropped_area_image_width_pixels,
+                    gpano.cropped_area_image_height_pixels,
+                ) {
+                    output.write_line(&format!(
+                        "GPano cropped area: {w}x{h} at {},{}",
+                        gpano.cropped_area_left_pixels.unwrap_or(0),
+                        gpano.cropped_area_top_pixels.unwrap_or(0)
+                    ))?;
+                }
+            }
+            output.write_line(&format!(
+                "Input field of view: {}x{} degrees",
+                args.ih_fov, args.iv_fov
+            ))?;
+            output.write_line(&format!(
+                "Output resolution: {output_width}x{output_height} at {}x{} degrees",
+                args.h_fov, args.v_fov
+            ))?;
+            if let Some(format_name) = &media.format_name {
+                output.write_line(&format!("Format: {format_name}"))?;
+            }
+            if let Some(duration) = media.duration {
+                output.write_line(&format!("Duration: {duration:.3} seconds"))?;
+            }
+            for stream in &media.streams {
+                output.write_line(&format!(
+                    "Stream #{}: {}",
+                    stream.index,
+                    stream_summary(stream)
+                ))?;
+            }
+            output.event(json!({
+                "event": "probed",
+                "input_path": input_path,
+                "probe": probe,
+                "media": media,
+                "ih_fov": args.ih_fov,
+                "iv_fov": args.iv_fov,
+                "output_width": output_width,
+                "output_height": output_height,
+            }))?;
+        }
+        DragonflySubCommand::Estimate {
+            input_path,
+            extract_args,
+            encode_args,
+            output_path,
+            samples,
+        } => {
+            let mut encode_args = encode_args;
+            encode_args.downsample = extract_args.supersample;
+            // The samples are removed along with the directory
+            let extraction_dir = dragonfly::ExtractionDir::new_in(tmpdir)?;
+            output.write_line(&format!(
+                "Rendering {samples} sample frames of {input_path:?}"
+            ))?;
+            let estimate = dragonfly::estimate(
+                &input_path,
+                &output_path,
+                extraction_dir.path(),
+                &extract_args,
+                &encode_args,
+                samples,
+            )?;
+            output.write_line(&format!(
+                "Frames: {} at {}x{}, {:.2?} each",
+                estimate.frame_count, estimate.width, estimate.height, estimate.frame_duration
+            ))?;
+            output.write_line(&format!(
+                "Extraction time: about {:.0?}",
+                estimate.extract_duration
+            ))?;
+            output.write_line(&format!(
+                "Extracted frames: about {}",
+                dragonfly::format_size(estimate.frames_size)
+            ))?;
+            output.write_line(&format!(
+                "Output size: at most about {}",
+                dragonfly::format_size(estimate.output_size)
+            ))?;
+            output.event(json!({
+                "event": "estimated",
+                "frame_count": estimate.frame_count,
+                "width": estimate.width,
+                "height": estimate.height,
+                "sample_count": estimate.sample_count,
+                "frame_duration_secs": estimate.frame_duration.as_secs_f64(),
+                "extract_duration_secs": estimate.extract_duration.as_secs_f64(),
+                "frames_size_bytes": estimate.frames_size,
+                "output_size_bytes": estimate.output_size,
+            }))?;
+        }
+        DragonflySubCommand::Preview {
+            input_path,
+            yaw,
+            args,
+            text_args,
+            output_path,
+            show,
+        } => {
+            // A single frame that neither rotates nor sweeps renders exactly the requested view
+            let pitch = args.pitch.unwrap_or(args.pitch_start);
+            let args = dragonfly::ExtractFramesDescriptor {
+                frame_count: 1,
+                yaw_start: yaw,
+                yaw_end: yaw,
+                pitch: Some(pitch),
+                camera_path: None,
+                text_overlay: text_args.into_text_overlay(),
+                ..args
+            };
+            dragonfly::extract_frame(&input_path, &output_path, &args, 0)?;
+            output.write_line(&format!(
+                "Rendered the view at yaw {yaw} and pitch {pitch} to {output_path:?}"
+            ))?;
+            output.event(json!({ "event": "previewed", "output_path": output_path }))?;
+            if show {
+                output.show_image(&output_path)?;
+            }
         }
         DragonflySubCommand::Extract {
             input_path,
             extract_path,
             args,
+            little_planet,
+            rotating_panorama,
+            text_args,
+            session,
+            show,
         } => {
-            // The extract path was either specified by the user, or we need to create a temporary directory
-            let extract_path = if let Some(extract_path) = extract_path {
-                extract_path
+            let mut args = if little_planet {
+                args.with_little_planet()
+            } else if rotating_panorama {
+                args.with_rotating_panorama()
             } else {
-                create_tmp_extract_dir()?
+                args
             };
-            // Store the extract path so we can use it in future encode commands without the user having to specify it
-            if store_extract_dir(&extract_path).is_err() {
-                stderr.write_line(
-                    "Unexpectedly failed to store extract path. Attempting to continue...",
-                )?;
-            }
-
-            stdout.write_line(&format!(
+            args.text_overlay = text_args.into_text_overlay();
+            // The extract path was either specified by the user, or we need to create a temporary directory
+            let (extract_path, extraction_dir) = match extract_path {
+                Some(extract_path) => (extract_path, None),
+                None => {
+                    let extraction_dir = dragonfly::ExtractionDir::new_in(tmpdir)?;
+                    (extraction_dir.path().to_path_buf(), Some(extraction_dir))
+                }
+            };
+            output.write_line(&format!(
                 "Extracting {} frames from {:?} to {:?}",
                 args.frame_count, input_path, extract_path
             ))?;
-            let pb = ProgressBar::new(args.frame_count as u64);
-            dragonfly::extract_frames(
+            let pb = output.progress_bar(args.frame_count as u64);
+            let report = dragonfly::extract_frames(
                 &input_path,
                 &extract_path,
                 &args,
-                Some(|_, _| {
-                    pb.inc(1);
-                }),
+                Some(|event| extract_progress(output, &pb, event)),
+                interrupt::token(),
             )?;
             pb.finish_and_clear();
+            output.write_line(&format!(
+                "Extracted {} {}x{} frames in {:.1?}",
+                report.frames.len(),
+                report.width,
+                report.height,
+                report.duration
+            ))?;
+            // The frames are kept for future encode commands until removed by the clean command
+            let temporary = extraction_dir.map(|dir| dir.keep()).is_some();
+            // Register the frames so future encode commands can find them without the user specifying the path
+            let name = session.unwrap_or_else(|| session::input_session_name(&input_path));
+            let session = Session::new(&name, &input_path, &extract_path, temporary);
+            session.save()?;
+            output.write_line(&format!("Registered session {:?}", session.name))?;
+            if let Some(frame) = report.frames.first().filter(|_| show) {
+                output.show_image(&frame.path)?;
+            }
+            output.event(json!({
+                "event": "extracted",
+                "session": session,
+                "frame_count": report.frames.len(),
+                "width": report.width,
+                "height": report.height,
+                "duration_secs": report.duration.as_secs_f64(),
+            }))?;
         }
         DragonflySubCommand::Encode {
             extract_path,
-            output_path,
+            session,
+            output_paths,
             args,
+            text_args,
+            loop_quality,
         } => {
-            // The user either specified the extract path explicitly, or we will attempt to find the last extract path used
-            let extract_path = if let Some(extract_path) = extract_path {
-                extract_path
-            } else {
-                // TODO: If this returns an error, we should tell the user rather than exit the process
-                if let Ok(extract_path) = retrieve_extract_dir() {
-                    extract_path
-                } else {
-                    stderr.write_line(
-                        "Unable to find the last extract path. Please specify it explicitly.",
-                    )?;
-                    std::process::exit(exitcode::USAGE);
+            let mut args = args;
+            args.text_overlay = text_args.into_text_overlay();
+            let extract_path = resolve_extract_path(extract_path, session, output)?;
+            if loop_quality {
+                report_loop_quality(output, &extract_path)?;
+            }
+            let outputs = output_paths
+                .iter()
+                .map(|output_path| {
+                    (
+                        output_path,
+                        output_args(&args, &output_paths[0], output_path),
+                    )
+                })
+                .collect::<Vec<_>>();
+            // Catch a missing encoder for any format before encoding the first
+            for (output_path, args) in &outputs {
+                check_encoder(&environment, args, output_path, output)?;
+            }
+            for (output_path, args) in outputs {
+                output.write_line(&format!(
+                    "Encoding frames from {:?} to {:?}",
+                    extract_path, output_path
+                ))?;
+                let pb = output.spinner();
+                let start = Instant::now();
+                let status = dragonfly::encode_frames(
+                    output_path,
+                    &extract_path,
+                    &args,
+                    Some(|event| encode_progress(output, &pb, event)),
+                    interrupt::token(),
+                )?;
+                pb.finish_and_clear();
+                output.event(json!({
+                    "event": "encoded",
+                    "extract_path": extract_path,
+                    "output_path": output_path,
+                    "poster_path": args.poster,
+                    "sprite_sheet_path": args.sprite_sheet,
+                    "exit_code": status.code(),
+                    "duration_secs": start.elapsed().as_secs_f64(),
+                }))?;
+                if !status.success() {
+                    std::process::exit(status.code().unwrap_or(exitcode::SOFTWARE));
                 }
-            };
-            stdout.write_line(&format!(
-                "Encoding frames from {:?} to {:?}",
-                extract_path, output_path
+            }
+        }
+        DragonflySubCommand::VerifyLoop { video_path, args } => {
+            let quality = dragonfly::verify_loop(&video_path)?;
+            let issues = args.loop_issues();
+            output.write_line(&format!(
+                "Loop seam SSIM {:.4} PSNR {:.2} dB, neighboring frames SSIM {:.4} PSNR {:.2} dB",
+                quality.seam_ssim, quality.seam_psnr, quality.step_ssim, quality.step_psnr
             ))?;
-            let pb = ProgressBar::new_spinner();
-            pb.enable_steady_tick(Duration::from_millis(120));
-            pb.set_style(
-                ProgressStyle::with_template("{spinner:.blue} {msg}")
-                    .unwrap()
-                    // For more spinners check out the cli-spinners project:
-                    // https://github.com/sindresorhus/cli-spinners/blob/master/spinners.json
-                    .tick_strings(&[
-                        "▹▹▹▹▹",
-                        "▸▹▹▹▹",
-                        "▹▸▹▹▹",
-                        "▹▹▸▹▹",
-                        "▹▹▹▸▹",
-                        "▹▹▹▹▸",
-                        "▪▪▪▪▪",
-                    ]),
-            );
-            pb.set_message("Encoding...");
-            let status = dragonfly::encode_frames(&output_path, &extract_path, &args)?;
-            pb.finish_and_clear();
-            if !status.success() {
-                std::process::exit(status.code().unwrap_or(exitcode::SOFTWARE));
+            for issue in &issues {
+                output.write_line(&format!("Warning: {issue}"))?;
+            }
+            let seamless = !quality.pops() && issues.is_empty();
+            output.write_line(if seamless {
+                "The video loops seamlessly"
+            } else {
+                "The video may not loop seamlessly"
+            })?;
+            output.event(json!({
+                "event": "loop_verified",
+                "video_path": video_path,
+                "quality": quality,
+                "issues": issues,
+                "seamless": seamless,
+            }))?;
+            if !seamless {
+                std::process::exit(exitcode::DATAERR);
             }
         }
+        DragonflySubCommand::Cubemap {
+            input_path,
+            args,
+            output_path,
+        } => {
+            let output_paths = dragonfly::cubemap(&input_path, &output_path, &args)?;
+            for path in &output_paths {
+                output.write_line(&format!("Wrote {path:?}"))?;
+            }
+            output.event(json!({ "event": "cubemap", "output_paths": output_paths }))?;
+        }
+        DragonflySubCommand::Montage {
+            extract_path,
+            session,
+            args,
+            output_path,
+            show,
+        } => {
+            let extract_path = resolve_extract_path(extract_path, session, output)?;
+            dragonfly::montage(&extract_path, &output_path, &args)?;
+            output.write_line(&format!(
+                "Tiled frames from {extract_path:?} into {output_path:?}"
+            ))?;
+            output.event(json!({ "event": "montage", "output_path": output_path }))?;
+            if show {
+                output.show_image(&output_path)?;
+            }
+        }
+        DragonflySubCommand::Clean { older_than } => {
+            let removed = dragonfly::remove_extraction_dirs(
+                tmpdir,
+                Duration::from_secs(older_than * 60 * 60),
+            )?;
+            // Forget the sessions whose frames are gone
+            for session in Session::list()? {
+                if removed.contains(&session.extract_path) {
+                    session.remove()?;
+                }
+            }
+            output.write_line(&format!(
+                "Removed {} extraction directories from {:?}",
+                removed.len(),
+                tmpdir
+            ))?;
+            output.event(json!({ "event": "cleaned", "removed": removed }))?;
+        }
+        DragonflySubCommand::Completions { shell } => {
+            let script = completions::generate(shell, &mut DragonflyCli::command());
+            print!("{script}");
+        }
+        DragonflySubCommand::Sessions { subcommand } => match subcommand {
+            SessionsSubCommand::List => {
+                for session in Session::list()? {
+                    output.write_line(&format!(
+                        "{}\t{:?}\t{:?}",
+                        session.name, session.input_path, session.extract_path
+                    ))?;
+                    output.event(json!({ "event": "session", "session": session }))?;
+                }
+            }
+            SessionsSubCommand::Show { name } => {
+                let session = Session::load(&name)?;
+                let frame_count = dragonfly::ExtractionManifest::read(&session.extract_path)
+                    .map(|manifest| manifest.report.frames.len())
+                    .unwrap_or(0);
+                output.write_line(&format!("Name: {}", session.name))?;
+                output.write_line(&format!("Input: {:?}", session.input_path))?;
+                output.write_line(&format!("Frames: {:?}", session.extract_path))?;
+                output.write_line(&format!("Frame count: {frame_count}"))?;
+                output.write_line(&format!("Temporary: {}", session.temporary))?;
+                output.event(json!({
+                    "event": "session",
+                    "session": session,
+                    "frame_count": frame_count,
+                }))?;
+            }
+            SessionsSubCommand::Rm { name } => {
+                let session = Session::load(&name)?;
+                session.remove()?;
+                output.write_line(&format!("Removed session {:?}", session.name))?;
+                output.event(json!({ "event": "session_removed", "session": session }))?;
+            }
+        },
     }
-
-    std::process::exit(exitcode::OK);
+    Ok(())
 }
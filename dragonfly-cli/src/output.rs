@@ -0,0 +1,136 @@
+use dragonfly::ProgressEvent;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::{json, Value};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+/// Writes what the CLI is doing, either as human readable messages and progress bars, or as one JSON event per
+/// line on stdout for scripts and GUIs
+pub struct Output {
+    json: bool,
+    stdout: console::Term,
+    stderr: console::Term,
+}
+
+impl Output {
+    pub fn new(json: bool) -> Self {
+        Self {
+            json,
+            stdout: console::Term::stdout(),
+            stderr: console::Term::stderr(),
+        }
+    }
+
+    /// Writes a human readable message, which JSON output omits
+    pub fn write_line(&self, message: &str) -> io::Result<()> {
+        if self.json {
+            return Ok(());
+        }
+        self.stdout.write_line(message)
+    }
+
+    /// Writes an error to stderr, or as an error event with JSON output
+    pub fn error(&self, message: &str) -> io::Result<()> {
+        if self.json {
+            return self.event(json!({ "event": "error", "message": message }));
+        }
+        self.stderr.write_line(message)
+    }
+
+    /// Writes an error to stderr followed by a hint on how to get past it, or as one error event carrying the
+    /// hint with JSON output
+    pub fn error_with_hint(&self, error: &anyhow::Error, hint: &str) -> io::Result<()> {
+        if self.json {
+            return self.event(json!({
+                "event": "error",
+                "message": format!("{error:#}"),
+                "hint": hint,
+            }));
+        }
+        // Formatted the way anyhow reports an error returned from main
+        self.stderr.write_line(&format!("Error: {error:?}"))?;
+        self.stderr.write_line(hint)
+    }
+
+    /// Writes an event with JSON output, which human readable output omits
+    pub fn event(&self, event: Value) -> io::Result<()> {
+        if !self.json {
+            return Ok(());
+        }
+        self.stdout.write_line(&event.to_string())
+    }
+
+    /// Draws the image in the terminal, unless the output is JSON or not a terminal
+    pub fn show_image(&self, path: &Path) -> anyhow::Result<()> {
+        if self.json || !self.stdout.is_term() {
+            return Ok(());
+        }
+        crate::terminal_image::show(path, &self.stdout)
+    }
+
+    /// Writes the progress event with JSON output
+    pub fn progress(&self, event: &ProgressEvent) {
+        // Progress is best effort, a closed stdout shows up in the next result event
+        self.event(progress_json(event)).ok();
+    }
+
+    /// Creates a progress bar, hidden with JSON output
+    pub fn progress_bar(&self, len: u64) -> ProgressBar {
+        if self.json {
+            return ProgressBar::hidden();
+        }
+        ProgressBar::new(len)
+    }
+
+    /// Creates the spinner shown while ffmpeg encodes the output video, hidden with JSON output
+    pub fn spinner(&self) -> ProgressBar {
+        if self.json {
+            return ProgressBar::hidden();
+        }
+        let pb = ProgressBar::new_spinner();
+        pb.enable_steady_tick(Duration::from_millis(120));
+        pb.set_style(
+            ProgressStyle::with_template("{spinner:.blue} {msg}")
+                .unwrap()
+                // For more spinners check out the cli-spinners project:
+                // https://github.com/sindresorhus/cli-spinners/blob/master/spinners.json
+                .tick_strings(&[
+                    "▹▹▹▹▹",
+                    "▸▹▹▹▹",
+                    "▹▸▹▹▹",
+                    "▹▹▸▹▹",
+                    "▹▹▹▸▹",
+                    "▹▹▹▹▸",
+                    "▪▪▪▪▪",
+                ]),
+        );
+        pb.set_message("Encoding...");
+        pb
+    }
+}
+
+fn progress_json(event: &ProgressEvent) -> Value {
+    match event {
+        ProgressEvent::ExtractStarted { frame_count } => {
+            json!({ "event": "extract_started", "frame_count": frame_count })
+        }
+        ProgressEvent::FrameExtracted {
+            index,
+            path,
+            duration,
+        } => json!({
+            "event": "frame_extracted",
+            "index": index,
+            "path": path,
+            "duration_secs": duration.as_secs_f64(),
+        }),
+        ProgressEvent::EncodeStarted { frame_count } => {
+            json!({ "event": "encode_started", "frame_count": frame_count })
+        }
+        ProgressEvent::EncodeProgress { frame, fps } => {
+            json!({ "event": "encode_progress", "frame": frame, "fps": fps })
+        }
+        ProgressEvent::Finished => json!({ "event": "finished" }),
+    }
+}
@@ -0,0 +1,114 @@
+use crate::DRAGONFLY_TEMP_DIR;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static::lazy_static! {
+    pub static ref DRAGONFLY_SESSIONS_DIR: PathBuf = DRAGONFLY_TEMP_DIR.join(".dragonfly-sessions");
+}
+
+/// Extracted frames registered under a name, so later encode commands can find them
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Session {
+    pub name: String,
+    pub input_path: PathBuf,
+    pub extract_path: PathBuf,
+    /// True when dragonfly created the extract path, so removing the session also removes the frames
+    pub temporary: bool,
+    /// Milliseconds since the Unix epoch
+    pub created: u64,
+}
+
+impl Session {
+    pub fn new(name: &str, input_path: &Path, extract_path: &Path, temporary: bool) -> Self {
+        Self {
+            name: session_name(name),
+            input_path: input_path.to_path_buf(),
+            extract_path: extract_path.to_path_buf(),
+            temporary,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Registers the session, replacing any session with the same name along with its temporary frames
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Ok(previous) = Session::load(&self.name) {
+            if previous.extract_path != self.extract_path {
+                previous.remove()?;
+            }
+        }
+        fs::create_dir_all(&*DRAGONFLY_SESSIONS_DIR)?;
+        fs::write(session_path(&self.name), toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(name: &str) -> anyhow::Result<Self> {
+        let path = session_path(&session_name(name));
+        let session = fs::read_to_string(&path)
+            .with_context(|| format!("No session named {name:?}, see `dragonfly sessions list`"))?;
+        Ok(toml::from_str(&session)?)
+    }
+
+    /// Returns every session, oldest first
+    pub fn list() -> anyhow::Result<Vec<Self>> {
+        let entries = match fs::read_dir(&*DRAGONFLY_SESSIONS_DIR) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut sessions = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|session| toml::from_str::<Session>(&session).ok())
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| session.created);
+        Ok(sessions)
+    }
+
+    /// Returns the most recently created session
+    pub fn latest() -> anyhow::Result<Option<Self>> {
+        Ok(Session::list()?.pop())
+    }
+
+    /// Unregisters the session and removes its frames if dragonfly created them
+    pub fn remove(&self) -> anyhow::Result<()> {
+        if self.temporary {
+            match fs::remove_dir_all(&self.extract_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        fs::remove_file(session_path(&self.name))?;
+        Ok(())
+    }
+}
+
+/// Returns the default session name for an input, its file name without the extension
+pub fn input_session_name(input_path: &Path) -> String {
+    input_path
+        .file_stem()
+        .map(|stem| session_name(&stem.to_string_lossy()))
+        .unwrap_or_else(|| "default".to_string())
+}
+
+/// Replaces the characters of a session name that are not safe in a file name
+fn session_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+fn session_path(name: &str) -> PathBuf {
+    DRAGONFLY_SESSIONS_DIR.join(format!("{name}.toml"))
+}
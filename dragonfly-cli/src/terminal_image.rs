@@ -0,0 +1,136 @@
+use anyhow::bail;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Widest the image is drawn, in terminal columns
+const MAX_COLUMNS: u32 = 80;
+
+/// The ways a terminal can draw an image
+#[derive(Debug)]
+enum Protocol {
+    /// <https://sw.kovidgoyal.net/kitty/graphics-protocol/>
+    Kitty,
+    /// <https://iterm2.com/documentation-images.html>, also understood by WezTerm
+    Iterm,
+    /// Colored half block characters, which work in any terminal with true color
+    HalfBlocks,
+}
+
+impl Protocol {
+    fn detect() -> Self {
+        let term = std::env::var("TERM").unwrap_or_default();
+        let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+        if term == "xterm-kitty" || std::env::var_os("KITTY_WINDOW_ID").is_some() {
+            Protocol::Kitty
+        } else if term_program == "iTerm.app" || term_program == "WezTerm" {
+            Protocol::Iterm
+        } else {
+            Protocol::HalfBlocks
+        }
+    }
+}
+
+/// Draws the image in the terminal, so users connected over SSH can check the framing without copying files
+pub fn show(path: &Path, term: &console::Term) -> anyhow::Result<()> {
+    let columns = (term.size().1 as u32).clamp(1, MAX_COLUMNS);
+    let mut out = term.clone();
+    match Protocol::detect() {
+        Protocol::Kitty => {
+            // Kitty only decodes PNG, so let ffmpeg convert the frame
+            let png = ffmpeg_output(
+                path,
+                &format!("scale={}:-2", columns * 10),
+                &["-c:v", "png", "-f", "image2pipe"],
+            )?;
+            let encoded = base64(&png);
+            let chunks = encoded.as_bytes().chunks(4096).collect::<Vec<_>>();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = (i + 1 < chunks.len()) as u8;
+                let control = if i == 0 {
+                    format!("a=T,f=100,c={columns},m={more}")
+                } else {
+                    format!("m={more}")
+                };
+                write!(
+                    out,
+                    "\x1b_G{control};{}\x1b\\",
+                    String::from_utf8_lossy(chunk)
+                )?;
+            }
+            writeln!(out)?;
+        }
+        Protocol::Iterm => {
+            let image = std::fs::read(path)?;
+            writeln!(
+                out,
+                "\x1b]1337;File=inline=1;size={};width={columns}:{}\x07",
+                image.len(),
+                base64(&image)
+            )?;
+        }
+        Protocol::HalfBlocks => {
+            // Each character cell shows two pixels stacked vertically, its foreground above its background
+            let width = columns as usize;
+            let rgb = ffmpeg_output(
+                path,
+                &format!("scale={width}:-2"),
+                &["-pix_fmt", "rgb24", "-f", "rawvideo"],
+            )?;
+            let rows = rgb.chunks_exact(width * 3).collect::<Vec<_>>();
+            for pair in rows.chunks(2) {
+                let mut line = String::new();
+                for x in 0..width {
+                    let top = &pair[0][x * 3..x * 3 + 3];
+                    let bottom = pair.get(1).map_or(top, |row| &row[x * 3..x * 3 + 3]);
+                    line.push_str(&format!(
+                        "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                        top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                    ));
+                }
+                writeln!(out, "{line}\x1b[0m")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the image scaled by ffmpeg and written in the given format
+fn ffmpeg_output(path: &Path, scale: &str, format_args: &[&str]) -> anyhow::Result<Vec<u8>> {
+    let output = Command::new(dragonfly::FFMPEG_BINARY_PATH.as_os_str())
+        .args(["-hide_banner", "-loglevel", "error", "-i"])
+        .arg(path)
+        .args(["-frames:v", "1", "-vf", scale])
+        .args(format_args)
+        .arg("-")
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "Unable to draw {:?}: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
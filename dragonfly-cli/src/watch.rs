@@ -0,0 +1,70 @@
+use crate::batch;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Finds the panoramas added to a directory by polling it, so files still being copied from the camera are only
+/// picked up once their size and modification time stop changing
+pub struct DirectoryWatcher {
+    dir: PathBuf,
+    /// Size and modification time of each file not yet picked up, as of the last poll
+    pending: HashMap<PathBuf, (u64, SystemTime)>,
+    /// Files already picked up or written by dragonfly, which are never picked up again
+    ignored: HashSet<PathBuf>,
+}
+
+impl DirectoryWatcher {
+    /// Watches the directory, ignoring the files already in it unless `existing` is true
+    pub fn new(dir: &Path, existing: bool) -> anyhow::Result<Self> {
+        let mut watcher = Self {
+            dir: dir.to_path_buf(),
+            pending: HashMap::new(),
+            ignored: HashSet::new(),
+        };
+        if !existing {
+            watcher.ignored = watcher.input_files()?.into_keys().collect();
+        }
+        Ok(watcher)
+    }
+
+    /// Never picks up the file, such as an output written into the watched directory
+    pub fn ignore(&mut self, path: &Path) {
+        let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+        let parent = fs::canonicalize(parent.unwrap_or(Path::new("."))).ok();
+        if parent.is_some() && parent == fs::canonicalize(&self.dir).ok() {
+            if let Some(file_name) = path.file_name() {
+                self.ignored.insert(self.dir.join(file_name));
+            }
+        }
+    }
+
+    /// Returns the new files that have not changed since the previous poll, oldest name first
+    pub fn poll(&mut self) -> anyhow::Result<Vec<PathBuf>> {
+        let files = self.input_files()?;
+        let mut ready = files
+            .iter()
+            .filter(|(path, state)| self.pending.get(*path) == Some(state))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        ready.sort();
+        self.ignored.extend(ready.iter().cloned());
+        self.pending = files
+            .into_iter()
+            .filter(|(path, _)| !self.ignored.contains(path))
+            .collect();
+        Ok(ready)
+    }
+
+    fn input_files(&self) -> anyhow::Result<HashMap<PathBuf, (u64, SystemTime)>> {
+        Ok(fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| batch::is_input_file(path) && !self.ignored.contains(path))
+            .filter_map(|path| {
+                let metadata = fs::metadata(&path).ok().filter(|m| m.is_file())?;
+                Some((path, (metadata.len(), metadata.modified().ok()?)))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,411 @@
+//! Async variants of [`extract_frames`](crate::extract_frames) and [`encode_frames`](crate::encode_frames)
+//!
+//! ffmpeg runs under `tokio::process`, so many jobs can share a runtime without each blocking a thread.
+//! Dropping a returned future kills its ffmpeg processes. The [`Executor`](crate::Executor) trait is blocking, so
+//! these functions always spawn real processes. The [`ProcessPolicy`](crate::ProcessPolicy) does not apply here;
+//! wrap a future in `tokio::time::timeout` to bound how long it runs.
+
+use crate::manifest::{self, ExtractionManifest, MANIFEST_FILE};
+use crate::poster;
+use crate::progress::{self, EncodeProgress, ExtractProgress};
+use crate::sprites;
+use crate::{
+    cancel, check_input_exists, command_line, encode_command, extracted_frame_count,
+    ffprobe_command, inject_spherical_metadata, parse_ffprobe_output, remove_frames_on_cancel,
+    Backend, CancellationToken, DragonflyError, EncodeFramesDescriptor, ExtractFramesDescriptor,
+    ExtractReport, Extraction, FrameSource, InputInfo, ProgressEvent, ProgressHandler,
+    RenderStrategy, Result, SourceColor, SystemExecutor,
+};
+use log::{debug, warn};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
+use tokio::process::Child;
+
+/// How often a job waiting on ffmpeg checks its cancellation token
+const CANCELLATION_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+fn spawn(command: Command) -> Result<Child> {
+    debug!("Spawning command: {:?}", &command);
+    Ok(tokio::process::Command::from(command)
+        .kill_on_drop(true)
+        .spawn()?)
+}
+
+/// Calls `f` with each line of the stream until it closes, or returns an error once the token is cancelled
+async fn for_each_line(
+    stream: impl AsyncRead + Unpin,
+    cancellation_token: Option<&CancellationToken>,
+    mut f: impl FnMut(&str),
+) -> Result<()> {
+    let mut lines = BufReader::new(stream).lines();
+    loop {
+        if cancel::is_cancelled(cancellation_token) {
+            return Err(DragonflyError::Cancelled);
+        }
+        // Reading a line is cancel safe, so a timeout only delays the read until the token is checked again
+        match tokio::time::timeout(CANCELLATION_POLL_INTERVAL, lines.next_line()).await {
+            Ok(Ok(Some(line))) => f(&line),
+            Ok(Ok(None)) => return Ok(()),
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => {}
+        }
+    }
+}
+
+/// Reads the piped stderr of a child that has closed, or is about to close, its other outputs
+async fn read_stderr(child: &mut Child) -> String {
+    let mut stderr = String::new();
+    if let Some(mut child_stderr) = child.stderr.take() {
+        child_stderr.read_to_string(&mut stderr).await.ok();
+    }
+    stderr
+}
+
+/// Awaits the first of the running ffmpeg processes to exit, like the blocking `wait_for_any`
+async fn wait_for_any(
+    running: &mut Vec<(usize, String, Child)>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<usize> {
+    loop {
+        let error = if cancel::is_cancelled(cancellation_token) {
+            Some(DragonflyError::Cancelled)
+        } else {
+            let mut error = None;
+            for i in 0..running.len() {
+                if let Some(status) = running[i].2.try_wait()? {
+                    let (frame, command, mut child) = running.swap_remove(i);
+                    if status.success() {
+                        return Ok(frame);
+                    }
+                    error = Some(DragonflyError::FfmpegFailed {
+                        command,
+                        status,
+                        stderr: read_stderr(&mut child).await,
+                    });
+                    break;
+                }
+            }
+            error
+        };
+        if let Some(error) = error {
+            for (_, _, mut child) in running.drain(..) {
+                child.kill().await.ok();
+            }
+            return Err(error);
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+}
+
+async fn ffprobe_info(input_path: &Path) -> Result<InputInfo> {
+    check_input_exists(input_path)?;
+    let ffprobe_output = tokio::process::Command::from(ffprobe_command(input_path)?)
+        .output()
+        .await?;
+    parse_ffprobe_output(
+        input_path,
+        ffprobe_output.status,
+        &ffprobe_output.stdout,
+        &String::from_utf8_lossy(&ffprobe_output.stderr),
+    )?
+    .input_info()
+}
+
+/// Extracts frames like [`extract_frames`](crate::extract_frames) without blocking the calling thread
+///
+/// The native backend renders in process and has no async variant, so it is rejected.
+pub async fn extract_frames_async(
+    input_path: &Path,
+    extraction_path: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<ExtractReport> {
+    if descriptor.backend == Backend::Native {
+        return Err(DragonflyError::UnsupportedByBackend(
+            Backend::Native,
+            "async extraction".to_string(),
+        ));
+    }
+    let extraction_start = Instant::now();
+    let progress_handler = progress_handler.as_ref();
+    // Hashing a large input is blocking IO
+    let input_path_buf = input_path.to_path_buf();
+    let input_hash = tokio::task::spawn_blocking(move || manifest::input_hash(&input_path_buf))
+        .await
+        .map_err(io::Error::other)?;
+    if let Some(report) = manifest::reuse(
+        input_hash.as_deref(),
+        extraction_path,
+        descriptor,
+        progress_handler,
+    ) {
+        return Ok(ExtractReport {
+            duration: extraction_start.elapsed(),
+            ..report
+        });
+    }
+    fs::remove_file(extraction_path.join(MANIFEST_FILE)).ok();
+    let report = render_frames(
+        input_path,
+        extraction_path,
+        descriptor,
+        progress_handler,
+        cancellation_token,
+        extraction_start,
+    )
+    .await?;
+    ExtractionManifest {
+        input_hash,
+        descriptor: descriptor.clone(),
+        report: report.clone(),
+    }
+    .write(extraction_path)?;
+    Ok(report)
+}
+
+async fn render_frames(
+    input_path: &Path,
+    extraction_path: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    progress_handler: Option<&impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    extraction_start: Instant,
+) -> Result<ExtractReport> {
+    let input = ffprobe_info(input_path).await?;
+    let extraction = Extraction::new(input_path, descriptor, &input)?;
+    let descriptor = &extraction.descriptor;
+    progress::report(
+        progress_handler,
+        ProgressEvent::ExtractStarted {
+            frame_count: descriptor.frame_count,
+        },
+    );
+    let RenderStrategy::Ffmpeg {
+        remaining,
+        single_pass,
+    } = extraction.render_strategy(&input, extraction_path, progress_handler)?
+    else {
+        return Err(DragonflyError::UnsupportedByBackend(
+            Backend::Native,
+            "async extraction".to_string(),
+        ));
+    };
+    if single_pass {
+        match extract_frames_single_pass(
+            &extraction,
+            extraction_path,
+            progress_handler,
+            cancellation_token,
+        )
+        .await
+        .map_err(|e| remove_frames_on_cancel(e, extraction_path))
+        {
+            Ok(frame_durations) => {
+                progress::report(progress_handler, ProgressEvent::Finished);
+                return Ok(extraction.report(
+                    extraction_path,
+                    frame_durations,
+                    true,
+                    extraction_start.elapsed(),
+                ));
+            }
+            Err(DragonflyError::Cancelled) => return Err(DragonflyError::Cancelled),
+            Err(e) => warn!(
+                "Single pass extraction failed ({e}), falling back to one ffmpeg process per frame"
+            ),
+        }
+    }
+
+    // Scheduled like the blocking extraction, j ffmpeg processes at a time
+    let j = descriptor.thread_count();
+    let mut running: Vec<(usize, String, Child)> = Vec::with_capacity(j);
+    let mut started = vec![extraction_start; descriptor.frame_count];
+    let mut frame_durations = vec![Duration::ZERO; descriptor.frame_count];
+    let mut on_finished = |frame: usize, started: Instant| {
+        frame_durations[frame] = started.elapsed();
+        progress::report(
+            progress_handler,
+            ProgressEvent::FrameExtracted {
+                index: frame,
+                path: Some(extraction.frame_path(extraction_path, frame)),
+                duration: started.elapsed(),
+            },
+        );
+    };
+    for frame in remaining {
+        while running.len() >= j {
+            let finished = wait_for_any(&mut running, cancellation_token)
+                .await
+                .map_err(|e| remove_frames_on_cancel(e, extraction_path))?;
+            on_finished(finished, started[finished]);
+        }
+        let ffmpeg_cmd = extraction.render_frame_command(
+            extraction_path,
+            frame,
+            &extraction.frame_path(extraction_path, frame),
+        )?;
+        let command = command_line(&ffmpeg_cmd);
+        let ffmpeg_child = spawn(ffmpeg_cmd)?;
+        started[frame] = Instant::now();
+        running.push((frame, command, ffmpeg_child));
+    }
+    while !running.is_empty() {
+        let finished = wait_for_any(&mut running, cancellation_token)
+            .await
+            .map_err(|e| remove_frames_on_cancel(e, extraction_path))?;
+        on_finished(finished, started[finished]);
+    }
+    progress::report(progress_handler, ProgressEvent::Finished);
+    Ok(extraction.report(
+        extraction_path,
+        frame_durations,
+        false,
+        extraction_start.elapsed(),
+    ))
+}
+
+/// Returns the time spent rendering each frame. ffmpeg failing usually means the v360 filter does not support
+/// runtime commands.
+async fn extract_frames_single_pass(
+    extraction: &Extraction<'_>,
+    extraction_path: &Path,
+    progress_handler: Option<&impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<Vec<Duration>> {
+    let (ffmpeg_cmd, _script) = extraction.single_pass_command(extraction_path)?;
+    let command = command_line(&ffmpeg_cmd);
+    let mut ffmpeg_child = spawn(ffmpeg_cmd)?;
+    let stderr = ffmpeg_child.stderr.take().expect("piped stderr");
+    let mut extract_progress = ExtractProgress::new(
+        extraction.descriptor.frame_count,
+        Some((extraction_path, extraction.descriptor.intermediate_format)),
+    );
+    if let Err(e) = for_each_line(stderr, cancellation_token, |line| {
+        extract_progress.line(line, progress_handler)
+    })
+    .await
+    {
+        ffmpeg_child.kill().await.ok();
+        return Err(e);
+    }
+    let status = ffmpeg_child.wait().await?;
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command,
+            status,
+            stderr: extract_progress.log().to_string(),
+        });
+    }
+    Ok(extract_progress.into_durations())
+}
+
+/// Encodes frames like [`encode_frames`](crate::encode_frames) without blocking the calling thread
+pub async fn encode_frames_async(
+    output_path: &Path,
+    extraction_path: &Path,
+    descriptor: &EncodeFramesDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<ExitStatus> {
+    let descriptor = descriptor.with_preset()?;
+    let source = FrameSource::Directory {
+        path: extraction_path,
+        frame_count: extracted_frame_count(extraction_path)?,
+        color: SourceColor::of_extraction(extraction_path),
+    };
+    let mut ffmpeg_cmd = encode_command(output_path, &source, &descriptor)?;
+    ffmpeg_cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let command = command_line(&ffmpeg_cmd);
+    let progress_handler = progress_handler.as_ref();
+    progress::report(
+        progress_handler,
+        ProgressEvent::EncodeStarted {
+            frame_count: descriptor.output_frame_count(),
+        },
+    );
+    let mut ffmpeg_child = spawn(ffmpeg_cmd)?;
+    let stdout = ffmpeg_child.stdout.take().expect("piped stdout");
+    let mut encode_progress = EncodeProgress::default();
+    if let Err(e) = for_each_line(stdout, cancellation_token, |line| {
+        encode_progress.line(line, progress_handler)
+    })
+    .await
+    {
+        ffmpeg_child.kill().await.ok();
+        fs::remove_file(output_path).ok();
+        return Err(e);
+    }
+    let stderr = read_stderr(&mut ffmpeg_child).await;
+    let status = ffmpeg_child.wait().await?;
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command,
+            status,
+            stderr,
+        });
+    }
+    if descriptor.spherical {
+        // Rewriting the file is blocking IO
+        let output_path = output_path.to_path_buf();
+        tokio::task::spawn_blocking(move || inject_spherical_metadata(&output_path))
+            .await
+            .map_err(io::Error::other)??;
+    }
+    if let Some(poster_cmd) = poster::poster_command(extraction_path, &descriptor)? {
+        let command = command_line(&poster_cmd);
+        let output = tokio::process::Command::from(poster_cmd).output().await?;
+        if !output.status.success() {
+            return Err(DragonflyError::FfmpegFailed {
+                command,
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+    }
+    if descriptor.sprite_sheet.is_some() {
+        let output_path = output_path.to_path_buf();
+        let descriptor = descriptor.clone().into_owned();
+        tokio::task::spawn_blocking(move || {
+            sprites::write_sprite_sheet(&output_path, &descriptor, &SystemExecutor)
+        })
+        .await
+        .map_err(io::Error::other)??;
+    }
+    progress::report(progress_handler, ProgressEvent::Finished);
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_the_native_backend() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let descriptor = ExtractFramesDescriptor {
+            backend: Backend::Native,
+            ..Default::default()
+        };
+        let result = runtime.block_on(extract_frames_async(
+            Path::new("input.jpg"),
+            Path::new("frames"),
+            &descriptor,
+            None::<fn(ProgressEvent)>,
+            None,
+        ));
+        assert!(
+            matches!(
+                result,
+                Err(DragonflyError::UnsupportedByBackend(Backend::Native, _))
+            ),
+            "{result:?}"
+        );
+    }
+}
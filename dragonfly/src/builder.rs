@@ -0,0 +1,177 @@
+use crate::{
+    encode_frames_with_executor, extract_and_encode_with_executor, extract_frames_with_executor,
+    CancellationToken, Codec, DragonflyError, EncodeFramesDescriptor, Encoder, Executor,
+    ExtractFramesDescriptor, ExtractionDir, OutputProjection, ProgressEvent, Result,
+    SystemExecutor,
+};
+use std::path::PathBuf;
+use std::process::ExitStatus;
+
+/// Extracts frames from a 360 input and encodes them to an output in one call, managing the
+/// intermediate frames directory
+pub struct Dragonfly {
+    input_path: PathBuf,
+    output_path: Option<PathBuf>,
+    extract_descriptor: ExtractFramesDescriptor,
+    encode_descriptor: EncodeFramesDescriptor,
+    stream: bool,
+    progress_handler: Option<Box<dyn Fn(ProgressEvent)>>,
+    cancellation_token: Option<CancellationToken>,
+    executor: Box<dyn Executor>,
+}
+
+impl Dragonfly {
+    pub fn new(input_path: impl Into<PathBuf>) -> Self {
+        Self {
+            input_path: input_path.into(),
+            output_path: None,
+            extract_descriptor: ExtractFramesDescriptor::default(),
+            encode_descriptor: EncodeFramesDescriptor::default(),
+            stream: false,
+            progress_handler: None,
+            cancellation_token: None,
+            executor: Box::new(SystemExecutor),
+        }
+    }
+
+    /// The output video, whose extension selects the container
+    pub fn output(mut self, output_path: impl Into<PathBuf>) -> Self {
+        self.output_path = Some(output_path.into());
+        self
+    }
+
+    pub fn frames(mut self, frame_count: usize) -> Self {
+        self.extract_descriptor.frame_count = frame_count;
+        self
+    }
+
+    /// The horizontal field of view in degrees
+    pub fn fov(mut self, h_fov: f32) -> Self {
+        self.extract_descriptor.h_fov = h_fov;
+        self
+    }
+
+    /// The vertical field of view in degrees
+    pub fn v_fov(mut self, v_fov: f32) -> Self {
+        self.extract_descriptor.v_fov = v_fov;
+        self
+    }
+
+    /// Degrees added to every yaw, turning the starting direction
+    pub fn yaw_offset(mut self, yaw_offset: f32) -> Self {
+        self.extract_descriptor.yaw_offset = yaw_offset;
+        self
+    }
+
+    /// The fixed pitch in degrees, looking up when positive
+    pub fn pitch(mut self, pitch: f32) -> Self {
+        self.extract_descriptor.pitch = Some(pitch);
+        self
+    }
+
+    /// The fixed roll in degrees
+    pub fn roll(mut self, roll: f32) -> Self {
+        self.extract_descriptor.roll = roll;
+        self
+    }
+
+    pub fn projection(mut self, projection: OutputProjection) -> Self {
+        self.extract_descriptor.projection = projection;
+        self
+    }
+
+    /// The length of the video in seconds
+    pub fn length(mut self, length: f32) -> Self {
+        self.encode_descriptor.length = length;
+        self
+    }
+
+    pub fn fps(mut self, fps: f32) -> Self {
+        self.encode_descriptor.fps = fps;
+        self
+    }
+
+    pub fn codec(mut self, codec: Codec) -> Self {
+        self.encode_descriptor.codec = Some(codec);
+        self
+    }
+
+    pub fn encoder(mut self, encoder: Encoder) -> Self {
+        self.encode_descriptor.encoder = Some(encoder);
+        self
+    }
+
+    /// Replaces every extraction setting, for settings without a builder method
+    pub fn extract_descriptor(mut self, descriptor: ExtractFramesDescriptor) -> Self {
+        self.extract_descriptor = descriptor;
+        self
+    }
+
+    /// Replaces every encode setting, for settings without a builder method
+    pub fn encode_descriptor(mut self, descriptor: EncodeFramesDescriptor) -> Self {
+        self.encode_descriptor = descriptor;
+        self
+    }
+
+    /// Streams frames straight into the encoder instead of writing them to disk, see [`extract_and_encode`](crate::extract_and_encode)
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    pub fn on_progress(mut self, progress_handler: impl Fn(ProgressEvent) + 'static) -> Self {
+        self.progress_handler = Some(Box::new(progress_handler));
+        self
+    }
+
+    pub fn cancellation_token(mut self, cancellation_token: CancellationToken) -> Self {
+        self.cancellation_token = Some(cancellation_token);
+        self
+    }
+
+    /// Starts ffmpeg and ffprobe with the executor instead of spawning them directly
+    pub fn executor(mut self, executor: impl Executor + 'static) -> Self {
+        self.executor = Box::new(executor);
+        self
+    }
+
+    /// Extracts the frames to a temporary directory, encodes them, and removes the directory
+    pub fn run(self) -> Result<ExitStatus> {
+        let output_path = self
+            .output_path
+            .as_deref()
+            .ok_or(DragonflyError::MissingOutputPath)?;
+        let progress_handler = self
+            .progress_handler
+            .as_ref()
+            .map(|handler| move |event| handler(event));
+        if self.stream {
+            return extract_and_encode_with_executor(
+                &self.input_path,
+                output_path,
+                &self.extract_descriptor,
+                &self.encode_descriptor,
+                progress_handler,
+                self.cancellation_token.as_ref(),
+                self.executor.as_ref(),
+            );
+        }
+        let extraction_dir = ExtractionDir::new()?;
+        extract_frames_with_executor(
+            &self.input_path,
+            extraction_dir.path(),
+            &self.extract_descriptor,
+            progress_handler,
+            self.cancellation_token.as_ref(),
+            self.executor.as_ref(),
+        )?;
+        encode_frames_with_executor(
+            output_path,
+            extraction_dir.path(),
+            &self.encode_descriptor,
+            progress_handler,
+            self.cancellation_token.as_ref(),
+            self.executor.as_ref(),
+        )
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{CameraPose, DragonflyError, Easing, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// The camera orientation and field of view at a normalized time along a [`CameraPath`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Normalized time in the range [0, 1]
+    pub time: f32,
+    #[serde(default)]
+    pub yaw: f32,
+    #[serde(default)]
+    pub pitch: f32,
+    #[serde(default)]
+    pub roll: f32,
+    /// Falls back to the descriptor's horizontal field of view when missing
+    pub h_fov: Option<f32>,
+    /// Falls back to the descriptor's vertical field of view when missing
+    pub v_fov: Option<f32>,
+    /// Easing applied to the transition from this keyframe to the next
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+/// A sequence of keyframes describing an arbitrary camera move
+///
+/// Yaw, pitch, and roll are interpolated as-is, so a yaw going from -180 to 540 spins the camera twice.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CameraPath {
+    /// When true, the final keyframe is treated as a duplicate of the first frame and is never rendered
+    #[serde(default, rename = "loop")]
+    pub looping: bool,
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn new(keyframes: Vec<Keyframe>) -> Result<Self> {
+        let mut path = Self {
+            looping: false,
+            keyframes,
+        };
+        path.validate()?;
+        Ok(path)
+    }
+
+    /// Loads a camera path from a JSON, TOML, or CSV file, depending on the file extension
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut camera_path: CameraPath = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&contents)?,
+            Some("toml") => toml::from_str(&contents)?,
+            Some("csv") => Self::from_csv(&contents)?,
+            _ => {
+                return Err(DragonflyError::InvalidCameraPath(format!(
+                    "{} must be a .json, .toml, or .csv file",
+                    path.display()
+                )))
+            }
+        };
+        camera_path.validate()?;
+        Ok(camera_path)
+    }
+
+    /// Parses rows of time, yaw, and optionally pitch, roll, h_fov, and v_fov samples
+    ///
+    /// Times may be in any unit, such as seconds, and are rescaled to span [0, 1]. A header row is skipped.
+    fn from_csv(contents: &str) -> Result<Self> {
+        let mut rows = vec![];
+        for (i, line) in contents.lines().enumerate() {
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            if fields.iter().all(|f| f.is_empty()) {
+                continue;
+            }
+            let values = fields
+                .iter()
+                .map(|f| f.parse::<f32>())
+                .collect::<std::result::Result<Vec<_>, _>>();
+            match values {
+                Ok(values) if values.len() >= 2 => rows.push(values),
+                Err(_) if i == 0 => continue,
+                _ => {
+                    return Err(DragonflyError::InvalidCameraPath(format!(
+                        "line {} must be time, yaw, and optionally pitch, roll, h_fov, and v_fov numbers",
+                        i + 1
+                    )))
+                }
+            }
+        }
+        let start = rows.iter().map(|row| row[0]).fold(f32::INFINITY, f32::min);
+        let end = rows
+            .iter()
+            .map(|row| row[0])
+            .fold(f32::NEG_INFINITY, f32::max);
+        let span = if end > start { end - start } else { 1.0 };
+        let keyframes = rows
+            .iter()
+            .map(|row| Keyframe {
+                time: (row[0] - start) / span,
+                yaw: row[1],
+                pitch: row.get(2).copied().unwrap_or(0.0),
+                roll: row.get(3).copied().unwrap_or(0.0),
+                h_fov: row.get(4).copied(),
+                v_fov: row.get(5).copied(),
+                easing: Easing::Linear,
+            })
+            .collect();
+        Ok(Self {
+            looping: false,
+            keyframes,
+        })
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.keyframes.is_empty() {
+            return Err(DragonflyError::InvalidCameraPath(
+                "camera path must contain at least one keyframe".to_string(),
+            ));
+        }
+        if let Some(keyframe) = self
+            .keyframes
+            .iter()
+            .find(|k| !(0.0..=1.0).contains(&k.time))
+        {
+            return Err(DragonflyError::InvalidCameraPath(format!(
+                "keyframe time {} must be in the range [0, 1]",
+                keyframe.time
+            )));
+        }
+        self.keyframes
+            .sort_by(|a, b| a.time.partial_cmp(&b.time).expect("finite keyframe times"));
+        Ok(())
+    }
+
+    /// Returns the normalized time of the given frame along the path
+    pub fn frame_time(&self, frame: usize, frame_count: usize) -> f32 {
+        if self.looping {
+            frame as f32 / frame_count as f32
+        } else if frame_count > 1 {
+            frame as f32 / (frame_count - 1) as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Samples the path at normalized time `t`, interpolating between the surrounding keyframes
+    pub fn sample(&self, t: f32, h_fov: f32, v_fov: f32) -> CameraPose {
+        let pose = |k: &Keyframe| CameraPose {
+            yaw: k.yaw,
+            pitch: k.pitch,
+            roll: k.roll,
+            h_fov: k.h_fov.unwrap_or(h_fov),
+            v_fov: k.v_fov.unwrap_or(v_fov),
+        };
+        let next = self.keyframes.iter().position(|k| k.time > t);
+        match next {
+            // Before the first keyframe, hold the first pose
+            Some(0) => pose(&self.keyframes[0]),
+            Some(i) => {
+                let a = &self.keyframes[i - 1];
+                let b = &self.keyframes[i];
+                let s = a.easing.apply((t - a.time) / (b.time - a.time));
+                pose(a).lerp(&pose(b), s)
+            }
+            // After the last keyframe, hold the last pose
+            None => pose(self.keyframes.last().expect("non-empty keyframes")),
+        }
+    }
+}
+
+/// Parses a camera path file for use as a command-line argument value
+pub fn parse_camera_path(path: &str) -> Result<CameraPath> {
+    CameraPath::from_file(Path::new(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses and validates a CSV camera path, as loading it from a file does
+    fn from_csv(contents: &str) -> Result<CameraPath> {
+        let mut camera_path = CameraPath::from_csv(contents)?;
+        camera_path.validate()?;
+        Ok(camera_path)
+    }
+
+    fn keyframe(time: f32, yaw: f32) -> Keyframe {
+        Keyframe {
+            time,
+            yaw,
+            pitch: 0.0,
+            roll: 0.0,
+            h_fov: None,
+            v_fov: None,
+            easing: Easing::Linear,
+        }
+    }
+
+    #[test]
+    fn skips_the_header_and_rescales_times() {
+        let camera_path = from_csv(
+            "time, yaw, pitch, roll, h_fov, v_fov\n\
+             2, 0, 10, 5, 90, 60\n\
+             \n\
+             6, 180\n\
+             4, 90, -10\n",
+        )
+        .unwrap();
+        let keyframes = &camera_path.keyframes;
+        let times = keyframes.iter().map(|k| k.time).collect::<Vec<_>>();
+        assert_eq!(times, [0.0, 0.5, 1.0]);
+        assert_eq!(keyframes[0].pitch, 10.0);
+        assert_eq!(keyframes[0].roll, 5.0);
+        assert_eq!(keyframes[0].h_fov, Some(90.0));
+        assert_eq!(keyframes[0].v_fov, Some(60.0));
+        // Missing columns fall back to level, with the descriptor's field of view
+        assert_eq!(keyframes[1].yaw, 90.0);
+        assert_eq!(keyframes[1].pitch, -10.0);
+        assert_eq!(keyframes[2].roll, 0.0);
+        assert_eq!(keyframes[2].h_fov, None);
+    }
+
+    #[test]
+    fn places_a_single_row_at_the_start() {
+        let camera_path = from_csv("3.5, 45").unwrap();
+        assert_eq!(camera_path.keyframes.len(), 1);
+        assert_eq!(camera_path.keyframes[0].time, 0.0);
+        assert_eq!(camera_path.keyframes[0].yaw, 45.0);
+    }
+
+    #[test]
+    fn rejects_rows_that_are_not_numbers() {
+        assert!(from_csv("0, 0\nlater, 90").is_err());
+        assert!(from_csv("0").is_err());
+        assert!(from_csv("time, yaw").is_err());
+    }
+
+    #[test]
+    fn rejects_times_outside_the_path() {
+        assert!(CameraPath::new(vec![keyframe(0.0, 0.0), keyframe(1.5, 90.0)]).is_err());
+        assert!(CameraPath::new(vec![keyframe(-0.1, 0.0)]).is_err());
+        assert!(CameraPath::new(vec![keyframe(f32::NAN, 0.0), keyframe(1.0, 90.0)]).is_err());
+        assert!(from_csv("0, 0\nnan, 90\n1, 180").is_err());
+    }
+
+    #[test]
+    fn sorts_keyframes_by_time() {
+        let camera_path = CameraPath::new(vec![keyframe(1.0, 90.0), keyframe(0.0, 0.0)]).unwrap();
+        assert_eq!(camera_path.keyframes[0].time, 0.0);
+    }
+
+    #[test]
+    fn samples_before_between_and_after_keyframes() {
+        let camera_path =
+            CameraPath::new(vec![keyframe(0.25, 0.0), keyframe(0.75, 100.0)]).unwrap();
+        let yaw = |t| camera_path.sample(t, 90.0, 60.0).yaw;
+        assert_eq!(yaw(0.0), 0.0);
+        assert_eq!(yaw(0.5), 50.0);
+        assert_eq!(yaw(0.625), 75.0);
+        assert_eq!(yaw(1.0), 100.0);
+        // Keyframes without a field of view use the descriptor's
+        let pose = camera_path.sample(0.5, 90.0, 60.0);
+        assert_eq!((pose.h_fov, pose.v_fov), (90.0, 60.0));
+    }
+}
@@ -0,0 +1,61 @@
+use crate::Process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Requests that a running extraction or encode stops early
+///
+/// Clones share the same state, so one clone can be handed to the job while another cancels it from a
+/// different thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops any job using this token, killing its ffmpeg processes and removing its partial output
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Returns true if there is a token and it was cancelled
+pub(crate) fn is_cancelled(cancellation_token: Option<&CancellationToken>) -> bool {
+    cancellation_token.is_some_and(CancellationToken::is_cancelled)
+}
+
+/// Runs `f`, such as reading the progress of the children, while killing the children as soon as the
+/// token is cancelled
+pub(crate) fn kill_on_cancel<T>(
+    children: &mut [&mut dyn Process],
+    cancellation_token: Option<&CancellationToken>,
+    f: impl FnOnce() -> T,
+) -> T {
+    let Some(cancellation_token) = cancellation_token else {
+        return f();
+    };
+    let done = AtomicBool::new(false);
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            while !done.load(Ordering::SeqCst) {
+                if cancellation_token.is_cancelled() {
+                    for child in children.iter_mut() {
+                        child.kill().ok();
+                    }
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        let result = f();
+        done.store(true, Ordering::SeqCst);
+        result
+    })
+}
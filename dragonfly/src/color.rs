@@ -0,0 +1,164 @@
+use crate::{EncodeFramesDescriptor, ExtractionManifest};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use strum::{Display, EnumString};
+
+/// Whether the encoded samples use the limited (tv) or the full (pc) range
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ColorRange {
+    /// Limited range, which players assume for untagged video
+    Tv,
+    /// Full range
+    Pc,
+}
+
+/// The color primaries of the encoded video, named as ffmpeg names them
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ColorPrimaries {
+    Bt709,
+    Bt2020,
+    /// PAL
+    Bt470bg,
+    /// NTSC
+    Smpte170m,
+    /// Display P3
+    Smpte432,
+}
+
+/// The transfer characteristics of the encoded video, named as ffmpeg names them
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorTransfer {
+    Bt709,
+    #[strum(serialize = "iec61966-2-1")]
+    Srgb,
+    /// PQ, for HDR10
+    Smpte2084,
+    /// HLG
+    AribStdB67,
+}
+
+/// The matrix coefficients converting RGB to the encoded YUV, named as ffmpeg names them
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMatrix {
+    Bt709,
+    Bt2020nc,
+    Bt470bg,
+    Smpte170m,
+}
+
+/// The bit depth and color tags of an input, which high bit depth and HDR inputs keep from extraction through
+/// to the encoded video
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SourceColor {
+    /// Bits per color component, 0 when unknown
+    pub bit_depth: u32,
+    /// Transfer characteristics as ffprobe names them, such as bt709, smpte2084 (PQ), or arib-std-b67 (HLG)
+    pub transfer: Option<String>,
+    pub primaries: Option<String>,
+    /// Matrix coefficients, such as bt709 or bt2020nc
+    pub space: Option<String>,
+    /// Whether the frames keep an alpha channel
+    #[serde(default)]
+    pub alpha: bool,
+}
+
+impl SourceColor {
+    /// Returns true for the PQ and HLG transfers of HDR video
+    pub fn is_hdr(&self) -> bool {
+        matches!(self.transfer.as_deref(), Some("smpte2084" | "arib-std-b67"))
+    }
+
+    /// Returns true if 8 bit frames would lose precision the input has
+    pub fn is_high_bit_depth(&self) -> bool {
+        self.bit_depth > 8 || self.is_hdr()
+    }
+
+    /// Returns the color of the frames extracted to `extraction_path`, as recorded in their manifest
+    pub(crate) fn of_extraction(extraction_path: &Path) -> Self {
+        ExtractionManifest::read(extraction_path)
+            .map(|manifest| manifest.report.color)
+            .unwrap_or_default()
+    }
+}
+
+/// The color tags of an encoded video
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OutputColor {
+    range: ColorRange,
+    primaries: String,
+    transfer: String,
+    matrix: String,
+}
+
+impl OutputColor {
+    /// Resolves the tags set on the descriptor, falling back to the tags of a high bit depth input and then
+    /// to bt709
+    pub(crate) fn new(descriptor: &EncodeFramesDescriptor, source: &SourceColor) -> Self {
+        // Unknown values are not tags worth copying
+        let source_tag = |value: &Option<String>| {
+            value
+                .as_deref()
+                .filter(|value| {
+                    source.is_high_bit_depth() && !value.is_empty() && *value != "unknown"
+                })
+                .map(String::from)
+        };
+        let resolve = |option: Option<String>, source_value: &Option<String>| {
+            option
+                .or_else(|| source_tag(source_value))
+                .unwrap_or_else(|| "bt709".to_string())
+        };
+        Self {
+            range: descriptor.color_range.unwrap_or(ColorRange::Tv),
+            primaries: resolve(
+                descriptor.color_primaries.map(|p| p.to_string()),
+                &source.primaries,
+            ),
+            transfer: resolve(
+                descriptor.color_transfer.map(|t| t.to_string()),
+                &source.transfer,
+            ),
+            matrix: resolve(
+                descriptor.color_matrix.map(|m| m.to_string()),
+                &source.space,
+            ),
+        }
+    }
+
+    /// Returns the scale filter that converts the frames to YUV with the tagged matrix and range, so the tags
+    /// describe the encoded pixels
+    pub(crate) fn scale_filter(&self) -> String {
+        let matrix = match self.matrix.as_str() {
+            "bt2020nc" | "bt2020c" => "bt2020",
+            "smpte170m" | "bt470bg" => "bt601",
+            matrix @ ("bt709" | "fcc" | "smpte240m") => matrix,
+            _ => return format!("scale=out_range={}", self.range),
+        };
+        format!("scale=out_color_matrix={matrix}:out_range={}", self.range)
+    }
+
+    /// Returns the arguments that tag the encoded video
+    pub(crate) fn args(&self) -> Vec<String> {
+        [
+            ("-color_range", self.range.to_string()),
+            ("-color_primaries", self.primaries.clone()),
+            ("-color_trc", self.transfer.clone()),
+            ("-colorspace", self.matrix.clone()),
+        ]
+        .into_iter()
+        .flat_map(|(flag, value)| [flag.to_string(), value])
+        .collect()
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    command_line, extracted_frame_count, ffprobe_info, frame_path, frame_path_template,
+    read_stderr, spawn, CameraPath, DragonflyError, Easing, ExtractFramesDescriptor, Eye,
+    IntermediateFormat, Keyframe, Result, SourceColor, StereoLayout, SystemExecutor,
+    FFMPEG_BINARY_PATH,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A single continuous rotation split across several panoramas, each dissolving into the next
+///
+/// The rotation of `descriptor` spans every panorama. Each panorama renders one segment of it, and
+/// consecutive segments overlap by `crossfade_frames` frames, which are blended when assembling the frames.
+#[derive(Clone, Debug)]
+pub struct CrossfadeSequence {
+    pub input_paths: Vec<PathBuf>,
+    /// The extraction of each input, posed so that the segments continue one another
+    pub segments: Vec<ExtractFramesDescriptor>,
+    pub crossfade_frames: usize,
+}
+
+impl CrossfadeSequence {
+    /// Splits the rotation of `descriptor` across the inputs, blending `crossfade_frames` frames at each handoff
+    pub fn new(
+        input_paths: &[PathBuf],
+        descriptor: &ExtractFramesDescriptor,
+        crossfade_frames: usize,
+    ) -> Result<Self> {
+        let count = input_paths.len();
+        if count < 2 {
+            return Err(DragonflyError::InvalidCrossfade(
+                "at least two inputs are needed".to_string(),
+            ));
+        }
+        // The frames of the whole rotation, less the overlapping ones, are shared between the inputs
+        let segment_frames = (descriptor.frame_count + (count - 1) * crossfade_frames) / count;
+        if crossfade_frames >= segment_frames {
+            return Err(DragonflyError::InvalidCrossfade(format!(
+                "{crossfade_frames} crossfade frames do not fit in segments of {segment_frames} frames"
+            )));
+        }
+        // Every segment renders at the resolution of the first input so that the frames can be blended
+        let first = ffprobe_info(&input_paths[0], &SystemExecutor)?;
+        if first.video.is_some() {
+            return Err(DragonflyError::InvalidCrossfade(
+                "only still image inputs can be crossfaded".to_string(),
+            ));
+        }
+        let (width, height) = descriptor.output_resolution(first.width, first.height);
+        let supersample = descriptor.supersample.max(1);
+        let (width, height) = match (descriptor.stereo, descriptor.eye) {
+            (StereoLayout::TopBottom, Eye::Both) => (width, height / 2),
+            (StereoLayout::SideBySide, Eye::Both) => (width / 2, height),
+            _ => (width, height),
+        };
+        let segments = (0..count)
+            .map(|i| {
+                let start = i * (segment_frames - crossfade_frames);
+                // Pin every pose of the segment with a keyframe, offset again by the descriptor
+                let keyframes = (0..segment_frames)
+                    .map(|j| {
+                        let pose = descriptor.pose(start + j);
+                        Keyframe {
+                            time: j as f32 / (segment_frames - 1).max(1) as f32,
+                            yaw: pose.yaw - descriptor.yaw_offset,
+                            pitch: pose.pitch,
+                            roll: pose.roll,
+                            h_fov: Some(pose.h_fov),
+                            v_fov: Some(pose.v_fov),
+                            easing: Easing::Linear,
+                        }
+                    })
+                    .collect();
+                Ok(ExtractFramesDescriptor {
+                    frame_count: segment_frames,
+                    camera_path: Some(CameraPath::new(keyframes)?),
+                    width: Some(width / supersample),
+                    height: Some(height / supersample),
+                    max_dimension: None,
+                    ..descriptor.clone()
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            input_paths: input_paths.to_vec(),
+            segments,
+            crossfade_frames,
+        })
+    }
+
+    /// Returns the number of frames in the assembled sequence
+    pub fn frame_count(&self) -> usize {
+        let segment_frames = self.segments[0].frame_count;
+        self.segments.len() * (segment_frames - self.crossfade_frames) + self.crossfade_frames
+    }
+
+    /// Assembles the frames extracted for each segment into `output_path`, blending the overlapping frames
+    pub fn assemble(&self, segment_paths: &[PathBuf], output_path: &Path) -> Result<()> {
+        let segment_frames = self.segments[0].frame_count;
+        let step = segment_frames - self.crossfade_frames;
+        for (i, segment_path) in segment_paths.iter().enumerate() {
+            if extracted_frame_count(segment_path)? < segment_frames {
+                return Err(DragonflyError::NoFrames(segment_path.clone()));
+            }
+            // The first frames of every segment but the first are blended with the previous segment
+            let own_frames = if i == 0 { 0 } else { self.crossfade_frames };
+            let end = if i + 1 == segment_paths.len() {
+                segment_frames
+            } else {
+                step
+            };
+            let format = IntermediateFormat::of_extraction(segment_path);
+            for j in own_frames..end {
+                fs::copy(
+                    frame_path(segment_path, j, format),
+                    frame_path(output_path, i * step + j, format),
+                )?;
+            }
+            if let Some(next_path) = segment_paths.get(i + 1) {
+                self.blend(segment_path, next_path, output_path, (i + 1) * step)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Dissolves the last frames of one segment into the first frames of the next
+    fn blend(
+        &self,
+        from_path: &Path,
+        to_path: &Path,
+        output_path: &Path,
+        first_frame: usize,
+    ) -> Result<()> {
+        if self.crossfade_frames == 0 {
+            return Ok(());
+        }
+        let segment_frames = self.segments[0].frame_count;
+        let steps = self.crossfade_frames + 1;
+        let descriptor = &self.segments[0];
+        let format = IntermediateFormat::of_extraction(from_path);
+        let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+        ffmpeg_cmd
+            .args(["-hide_banner", "-loglevel", "error", "-nostats"])
+            .args([
+                "-start_number",
+                &(segment_frames - self.crossfade_frames).to_string(),
+                "-i",
+            ])
+            .arg(frame_path_template(from_path, format))
+            .args(["-start_number", "0", "-i"])
+            .arg(frame_path_template(to_path, format))
+            .args([
+                "-filter_complex",
+                &format!("[0][1]blend=all_expr=A*(1-(N+1)/{steps})+B*(N+1)/{steps}"),
+                "-frames:v",
+                &self.crossfade_frames.to_string(),
+                "-start_number",
+                &first_frame.to_string(),
+                "-f",
+                "image2",
+            ])
+            .args(format.output_args(
+                descriptor.jpeg_quality,
+                &SourceColor::of_extraction(from_path),
+            ))
+            .arg("-y")
+            .arg(frame_path_template(output_path, format))
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        let mut ffmpeg_child = spawn(&SystemExecutor, &mut ffmpeg_cmd)?;
+        let stderr = read_stderr(ffmpeg_child.as_mut());
+        let status = ffmpeg_child.wait()?;
+        if !status.success() {
+            return Err(DragonflyError::FfmpegFailed {
+                command: command_line(&ffmpeg_cmd),
+                status,
+                stderr,
+            });
+        }
+        Ok(())
+    }
+}
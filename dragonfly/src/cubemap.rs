@@ -0,0 +1,180 @@
+use crate::{
+    check_input_exists, command_line, read_stderr, spawn, DragonflyError, Interpolation, Result,
+    SystemExecutor, FFMPEG_BINARY_PATH,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use strum::{Display, EnumString};
+
+/// The names of the cube faces, in the order v360 lays them out
+const FACES: [&str; 6] = ["right", "left", "up", "down", "front", "back"];
+
+/// How the six faces of a cubemap are written
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+pub enum CubemapLayout {
+    /// One image per face, named after the output with the face appended, such as sky_front.png
+    #[cfg_attr(feature = "clap", value(name = "faces"))]
+    #[strum(serialize = "faces")]
+    #[serde(rename = "faces")]
+    Faces,
+    /// A single image of three faces by two
+    #[cfg_attr(feature = "clap", value(name = "3x2"))]
+    #[strum(serialize = "3x2")]
+    #[serde(rename = "3x2")]
+    Strip3x2,
+    /// A single image of the six faces side by side
+    #[cfg_attr(feature = "clap", value(name = "6x1"))]
+    #[strum(serialize = "6x1")]
+    #[serde(rename = "6x1")]
+    Strip6x1,
+}
+
+/// The cube faces of an equirectangular image, such as for a game engine skybox
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct CubemapDescriptor {
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Width and height of each face in pixels",
+            long,
+            default_value = "1024"
+        )
+    )]
+    pub face_size: u32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Write one image per face, or a single 3x2 or 6x1 image",
+            long,
+            default_value = "faces"
+        )
+    )]
+    pub layout: CubemapLayout,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The yaw in degrees the front face looks towards",
+            long,
+            default_value = "0.0",
+            allow_negative_numbers = true
+        )
+    )]
+    pub yaw: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The interpolation method used to sample the input",
+            long,
+            default_value = "lanczos"
+        )
+    )]
+    pub interpolation: Interpolation,
+}
+
+impl Default for CubemapDescriptor {
+    fn default() -> Self {
+        Self {
+            face_size: 1024,
+            layout: CubemapLayout::Faces,
+            yaw: 0.0,
+            interpolation: Interpolation::Lanczos,
+        }
+    }
+}
+
+impl CubemapDescriptor {
+    /// Returns the paths the cubemap is written to for the given output path
+    pub fn output_paths(&self, output_path: &Path) -> Vec<PathBuf> {
+        match self.layout {
+            CubemapLayout::Faces => {
+                let stem = output_path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy())
+                    .unwrap_or_default();
+                let ext = output_path
+                    .extension()
+                    .map(|ext| format!(".{}", ext.to_string_lossy()))
+                    .unwrap_or_default();
+                FACES
+                    .iter()
+                    .map(|face| output_path.with_file_name(format!("{stem}_{face}{ext}")))
+                    .collect()
+            }
+            CubemapLayout::Strip3x2 | CubemapLayout::Strip6x1 => vec![output_path.to_path_buf()],
+        }
+    }
+}
+
+/// Reprojects the equirectangular image at `input_path` to a cubemap, returning the paths of the written images
+pub fn cubemap(
+    input_path: &Path,
+    output_path: &Path,
+    descriptor: &CubemapDescriptor,
+) -> Result<Vec<PathBuf>> {
+    check_input_exists(input_path)?;
+    let size = descriptor.face_size;
+    let (output, width, height) = match descriptor.layout {
+        CubemapLayout::Strip3x2 => ("c3x2", 3 * size, 2 * size),
+        CubemapLayout::Faces | CubemapLayout::Strip6x1 => ("c6x1", 6 * size, size),
+    };
+    let v360 = format!(
+        "v360=e:{output}:yaw={}:out_forder=rludfb:w={width}:h={height}:interp={}",
+        descriptor.yaw, descriptor.interpolation
+    );
+    let output_paths = descriptor.output_paths(output_path);
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd
+        .args(["-hide_banner", "-loglevel", "error", "-nostats", "-i"])
+        .arg(input_path);
+    if descriptor.layout == CubemapLayout::Faces {
+        // Render the strip once and crop every face out of it
+        let labels = (0..FACES.len())
+            .map(|i| format!("[f{i}]"))
+            .collect::<String>();
+        let crops = (0..FACES.len())
+            .map(|i| {
+                format!(
+                    ";[f{i}]crop={size}:{size}:{}:0[{}]",
+                    i as u32 * size,
+                    FACES[i]
+                )
+            })
+            .collect::<String>();
+        ffmpeg_cmd.args([
+            "-filter_complex",
+            &format!("[0]{v360},split={}{labels}{crops}", FACES.len()),
+        ]);
+        for (face, path) in FACES.iter().zip(&output_paths) {
+            ffmpeg_cmd
+                .args([
+                    "-map",
+                    &format!("[{face}]"),
+                    "-frames:v",
+                    "1",
+                    "-update",
+                    "1",
+                    "-y",
+                ])
+                .arg(path);
+        }
+    } else {
+        ffmpeg_cmd
+            .args(["-vf", &v360, "-frames:v", "1", "-update", "1", "-y"])
+            .arg(output_path);
+    }
+    ffmpeg_cmd.stdout(Stdio::null()).stderr(Stdio::piped());
+    let mut ffmpeg_child = spawn(&SystemExecutor, &mut ffmpeg_cmd)?;
+    let stderr = read_stderr(ffmpeg_child.as_mut());
+    let status = ffmpeg_child.wait()?;
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr,
+        });
+    }
+    Ok(output_paths)
+}
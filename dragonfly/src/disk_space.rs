@@ -0,0 +1,60 @@
+use crate::{DragonflyError, Result};
+use log::debug;
+use std::path::Path;
+
+/// Fails when the filesystem holding `path` has less than `required` bytes free, or does nothing when the free
+/// space cannot be found
+pub(crate) fn check_disk_space(path: &Path, required: u64) -> Result<()> {
+    let Some(available) = available_space(path) else {
+        return Ok(());
+    };
+    debug!(
+        "Extraction needs about {} of the {} free in {path:?}",
+        format_size(required),
+        format_size(available)
+    );
+    if required > available {
+        return Err(DragonflyError::InsufficientDiskSpace {
+            path: path.to_path_buf(),
+            required: format_size(required),
+            available: format_size(available),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the bytes free to unprivileged users on the filesystem holding `path`, which need not exist yet
+#[cfg(unix)]
+fn available_space(path: &Path) -> Option<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    let existing = path.ancestors().find(|ancestor| ancestor.exists())?;
+    let existing = if existing.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        existing
+    };
+    let c_path = CString::new(existing.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Formats bytes in the largest unit that keeps the number at least 1, such as 3.2 GB
+pub fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KB", "MB", "GB"] {
+        if size < 1000.0 {
+            return format!("{size:.1} {unit}");
+        }
+        size /= 1000.0;
+    }
+    format!("{size:.1} TB")
+}
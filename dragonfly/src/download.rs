@@ -0,0 +1,322 @@
+use crate::{DragonflyError, Result, FFMPEG_BINARY_PATH_DEFAULT, FFPROBE_BINARY_PATH_DEFAULT};
+use log::info;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// An archive of a static ffmpeg build, pinned to a version and to the SHA-256 of its contents
+struct Archive {
+    url: &'static str,
+    /// Lowercase hex digest the downloaded archive must match before it is unpacked, empty until one is pinned
+    sha256: &'static str,
+}
+
+/// Archives of known-good static builds of ffmpeg and ffprobe for the current platform
+fn release_archives() -> Option<&'static [Archive]> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some(&[Archive {
+            url: "https://johnvansickle.com/ffmpeg/old-releases/ffmpeg-7.0.2-amd64-static.tar.xz",
+            sha256: "",
+        }]),
+        ("linux", "aarch64") => Some(&[Archive {
+            url: "https://johnvansickle.com/ffmpeg/old-releases/ffmpeg-7.0.2-arm64-static.tar.xz",
+            sha256: "",
+        }]),
+        ("windows", "x86_64") => Some(&[Archive {
+            url: "https://github.com/GyanD/codexffmpeg/releases/download/7.0.2/ffmpeg-7.0.2-essentials_build.zip",
+            sha256: "",
+        }]),
+        ("macos", _) => Some(&[
+            Archive {
+                url: "https://evermeet.cx/ffmpeg/ffmpeg-7.0.2.zip",
+                sha256: "",
+            },
+            Archive {
+                url: "https://evermeet.cx/ffmpeg/ffprobe-7.0.2.zip",
+                sha256: "",
+            },
+        ]),
+        _ => None,
+    }
+}
+
+/// Returns `$DRAGONFLY_FFMPEG_DIR`, falling back to `dragonfly/ffmpeg` in the user's cache directory
+pub fn ffmpeg_cache_dir() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("DRAGONFLY_FFMPEG_DIR") {
+        return Some(path.into());
+    }
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .or_else(|| std::env::var_os("LOCALAPPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(cache_dir.join("dragonfly").join("ffmpeg"))
+}
+
+/// Returns the path of a binary downloaded by an earlier run, if any
+pub(crate) fn downloaded_binary(name: &str) -> Option<OsString> {
+    let path = ffmpeg_cache_dir()?.join(name);
+    path.is_file().then(|| path.into_os_string())
+}
+
+/// Downloads ffmpeg and ffprobe to the cache directory, unless they are already set through the environment,
+/// installed, or downloaded
+pub fn ensure_ffmpeg() -> Result<()> {
+    let installed = Command::new(FFMPEG_BINARY_PATH_DEFAULT)
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false);
+    if std::env::var_os("FFMPEG_BINARY_PATH").is_some()
+        || downloaded_binary(FFMPEG_BINARY_PATH_DEFAULT).is_some()
+        || installed
+    {
+        return Ok(());
+    }
+    download_ffmpeg().map(|_| ())
+}
+
+/// Downloads static builds of ffmpeg and ffprobe to the cache directory, returning the directory
+///
+/// The archives are fetched with curl and unpacked with tar, which ship with Windows 10 and newer, macOS, and
+/// most Linux distributions.
+pub fn download_ffmpeg() -> Result<PathBuf> {
+    let archives = release_archives().ok_or_else(|| {
+        DragonflyError::FfmpegDownload(format!(
+            "no static build is known for {} {}",
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ))
+    })?;
+    let cache_dir = ffmpeg_cache_dir().ok_or_else(|| {
+        DragonflyError::FfmpegDownload("no cache directory, set DRAGONFLY_FFMPEG_DIR".to_string())
+    })?;
+    let download_dir = cache_dir.join("download");
+    fs::remove_dir_all(&download_dir).ok();
+    fs::create_dir_all(&download_dir)?;
+    // The binaries are run once unpacked, so only the exact archives that were pinned are trusted
+    if let Some(Archive { url, .. }) = archives.iter().find(|archive| archive.sha256.is_empty()) {
+        return Err(DragonflyError::FfmpegDownload(format!(
+            "no checksum is pinned for {url}"
+        )));
+    }
+    for (i, Archive { url, sha256 }) in archives.iter().enumerate() {
+        info!("Downloading ffmpeg from {url} to {:?}", cache_dir);
+        let archive_path = download_dir.join(format!("archive_{i}"));
+        run(Command::new("curl")
+            .args([
+                "--fail",
+                "--silent",
+                "--show-error",
+                "--location",
+                "--output",
+            ])
+            .arg(&archive_path)
+            .arg(url))?;
+        let digest = file_sha256(&archive_path)?;
+        if digest != *sha256 {
+            return Err(DragonflyError::FfmpegDownload(format!(
+                "{url} has SHA-256 {digest}, expected {sha256}"
+            )));
+        }
+        run(Command::new("tar")
+            .arg("-xf")
+            .arg(&archive_path)
+            .arg("-C")
+            .arg(&download_dir))?;
+    }
+    for name in [FFMPEG_BINARY_PATH_DEFAULT, FFPROBE_BINARY_PATH_DEFAULT] {
+        let path = find_file(&download_dir, name).ok_or_else(|| {
+            DragonflyError::FfmpegDownload(format!("the downloaded archives contain no {name}"))
+        })?;
+        fs::rename(path, cache_dir.join(name))?;
+    }
+    fs::remove_dir_all(&download_dir).ok();
+    Ok(cache_dir)
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let output = command.stdout(Stdio::null()).output().map_err(|e| {
+        DragonflyError::FfmpegDownload(format!("could not run {:?}: {e}", command.get_program()))
+    })?;
+    if !output.status.success() {
+        return Err(DragonflyError::FfmpegDownload(format!(
+            "{:?} exited with {}: {}",
+            command.get_program(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the path of the first file named `name` under `directory`
+fn find_file(directory: &Path, name: &str) -> Option<PathBuf> {
+    for entry in fs::read_dir(directory).ok()?.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if let Some(path) = find_file(&path, name) {
+                return Some(path);
+            }
+        } else if entry.file_name() == name {
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// Returns the lowercase hex SHA-256 digest of the file contents
+fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut sha256 = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(sha256.finish()),
+            n => sha256.update(&buffer[..n]),
+        }
+    }
+}
+
+/// Incremental SHA-256 as specified in FIPS 180-4
+struct Sha256 {
+    state: [u32; 8],
+    block: Vec<u8>,
+    length: u64,
+}
+
+const SHA256_ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+impl Sha256 {
+    fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            block: Vec::with_capacity(64),
+            length: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let take = (64 - self.block.len()).min(data.len());
+            self.block.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.block.len() == 64 {
+                self.compress();
+                self.block.clear();
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let bit_length = self.length * 8;
+        // Pad with a one bit and zeros up to the last 8 bytes of a block, which hold the message length
+        self.block.push(0x80);
+        if self.block.len() > 56 {
+            self.block.resize(64, 0);
+            self.compress();
+            self.block.clear();
+        }
+        self.block.resize(56, 0);
+        self.block.extend_from_slice(&bit_length.to_be_bytes());
+        self.compress();
+        self.state
+            .iter()
+            .map(|word| format!("{word:08x}"))
+            .collect()
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, word) in self.block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_ROUND_CONSTANTS[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha256(data: &[u8]) -> String {
+        let mut sha256 = Sha256::new();
+        sha256.update(data);
+        sha256.finish()
+    }
+
+    #[test]
+    fn hashes_the_fips_test_vectors() {
+        assert_eq!(
+            sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn hashes_input_split_across_updates() {
+        let mut sha256 = Sha256::new();
+        for chunk in vec![b'a'; 1_000_000].chunks(37) {
+            sha256.update(chunk);
+        }
+        assert_eq!(
+            sha256.finish(),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use crate::DragonflyError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Maps normalized animation progress to eased progress
+///
+/// Parsed from `linear`, `ease-in`, `ease-out`, `ease-in-out`, or `cubic-bezier(x1,y1,x2,y2)`.
+/// The named easings use the same control points as their CSS counterparts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    CubicBezier(f32, f32, f32, f32),
+}
+
+impl Easing {
+    /// Applies the easing to `t` in the range [0, 1]
+    pub fn apply(&self, t: f32) -> f32 {
+        match *self {
+            Easing::Linear => t,
+            Easing::EaseIn => cubic_bezier(0.42, 0.0, 1.0, 1.0, t),
+            Easing::EaseOut => cubic_bezier(0.0, 0.0, 0.58, 1.0, t),
+            Easing::EaseInOut => cubic_bezier(0.42, 0.0, 0.58, 1.0, t),
+            Easing::CubicBezier(x1, y1, x2, y2) => cubic_bezier(x1, y1, x2, y2, t),
+        }
+    }
+}
+
+/// Evaluates a cubic bezier curve from (0, 0) to (1, 1) with control points (x1, y1) and (x2, y2) at x = `t`
+fn cubic_bezier(x1: f32, y1: f32, x2: f32, y2: f32, t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    let bezier = |a: f32, b: f32, s: f32| {
+        let u = 1.0 - s;
+        3.0 * u * u * s * a + 3.0 * u * s * s * b + s * s * s
+    };
+    let bezier_slope = |a: f32, b: f32, s: f32| {
+        let u = 1.0 - s;
+        3.0 * u * u * a + 6.0 * u * s * (b - a) + 3.0 * s * s * (1.0 - b)
+    };
+    // Solve x(s) = t for the curve parameter s. Newton's method converges quickly for well-behaved
+    // curves, and bisection takes over when the slope is too flat to make progress.
+    let mut s = t;
+    for _ in 0..8 {
+        let x = bezier(x1, x2, s) - t;
+        let slope = bezier_slope(x1, x2, s);
+        if x.abs() < 1e-6 {
+            return bezier(y1, y2, s);
+        }
+        if slope.abs() < 1e-6 {
+            break;
+        }
+        s = (s - x / slope).clamp(0.0, 1.0);
+    }
+    let (mut lo, mut hi) = (0.0_f32, 1.0_f32);
+    s = t;
+    for _ in 0..32 {
+        let x = bezier(x1, x2, s);
+        if (x - t).abs() < 1e-6 {
+            break;
+        }
+        if x < t {
+            lo = s;
+        } else {
+            hi = s;
+        }
+        s = (lo + hi) / 2.0;
+    }
+    bezier(y1, y2, s)
+}
+
+impl fmt::Display for Easing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Easing::Linear => write!(f, "linear"),
+            Easing::EaseIn => write!(f, "ease-in"),
+            Easing::EaseOut => write!(f, "ease-out"),
+            Easing::EaseInOut => write!(f, "ease-in-out"),
+            Easing::CubicBezier(x1, y1, x2, y2) => {
+                write!(f, "cubic-bezier({x1},{y1},{x2},{y2})")
+            }
+        }
+    }
+}
+
+impl FromStr for Easing {
+    type Err = DragonflyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DragonflyError::InvalidEasing(s.to_string());
+        match s.trim() {
+            "linear" => Ok(Easing::Linear),
+            "ease-in" => Ok(Easing::EaseIn),
+            "ease-out" => Ok(Easing::EaseOut),
+            "ease-in-out" => Ok(Easing::EaseInOut),
+            other => {
+                let points = other
+                    .strip_prefix("cubic-bezier(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .ok_or_else(invalid)?
+                    .split(',')
+                    .map(|p| p.trim().parse::<f32>().map_err(|_| invalid()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                match points[..] {
+                    // The x coordinates must stay within [0, 1] for the curve to be a function of time
+                    [x1, y1, x2, y2] if (0.0..=1.0).contains(&x1) && (0.0..=1.0).contains(&x2) => {
+                        Ok(Easing::CubicBezier(x1, y1, x2, y2))
+                    }
+                    _ => Err(invalid()),
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Easing {
+    type Error = DragonflyError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Easing> for String {
+    fn from(easing: Easing) -> Self {
+        easing.to_string()
+    }
+}
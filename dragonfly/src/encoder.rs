@@ -0,0 +1,494 @@
+use crate::{DragonflyError, EncodeFramesDescriptor, Result, SourceColor};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use strum::{Display, EnumIter, EnumString};
+
+/// The video compression format of the output video
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    H264,
+    /// H.265, tagged as hvc1 in mp4 output for playback on Apple devices
+    Hevc,
+    Vp9,
+    Av1,
+    /// Apple ProRes, an intra-frame codec for editing
+    Prores,
+    /// Avid DNxHR, an intra-frame codec for editing
+    Dnxhr,
+}
+
+/// The ProRes profile, from the smallest to the highest quality
+///
+/// See <https://trac.ffmpeg.org/wiki/Encode/VFX#Prores> for a description of each profile.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ProresProfile {
+    Proxy,
+    Lt,
+    Standard,
+    Hq,
+    #[cfg_attr(feature = "clap", value(name = "4444"))]
+    #[strum(serialize = "4444")]
+    #[serde(rename = "4444")]
+    P4444,
+    #[cfg_attr(feature = "clap", value(name = "4444xq"))]
+    #[strum(serialize = "4444xq")]
+    #[serde(rename = "4444xq")]
+    P4444Xq,
+}
+
+impl ProresProfile {
+    fn pix_fmt(&self) -> &'static str {
+        match self {
+            ProresProfile::P4444 | ProresProfile::P4444Xq => "yuv444p10le",
+            _ => "yuv422p10le",
+        }
+    }
+}
+
+/// The DNxHR profile, from the smallest to the highest quality
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Eq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DnxhrProfile {
+    Lb,
+    Sq,
+    Hq,
+    Hqx,
+    #[cfg_attr(feature = "clap", value(name = "444"))]
+    #[strum(serialize = "444")]
+    #[serde(rename = "444")]
+    P444,
+}
+
+impl DnxhrProfile {
+    fn pix_fmt(&self) -> &'static str {
+        match self {
+            DnxhrProfile::Lb | DnxhrProfile::Sq | DnxhrProfile::Hq => "yuv422p",
+            DnxhrProfile::Hqx => "yuv422p10le",
+            DnxhrProfile::P444 => "yuv444p10le",
+        }
+    }
+}
+
+impl Codec {
+    /// Returns the software encoder used for the codec when no encoder is specified
+    pub fn default_encoder(&self) -> Encoder {
+        match self {
+            Codec::H264 => Encoder::Libx264,
+            Codec::Hevc => Encoder::Libx265,
+            Codec::Vp9 => Encoder::LibvpxVp9,
+            // SVT-AV1 is many times faster than libaom at comparable quality
+            Codec::Av1 => Encoder::Libsvtav1,
+            Codec::Prores => Encoder::ProresKs,
+            Codec::Dnxhr => Encoder::Dnxhd,
+        }
+    }
+}
+
+/// The ffmpeg video encoder used to produce the output video
+///
+/// Hardware encoders are much faster than their software counterparts but are only available on
+/// machines with the matching GPU and an ffmpeg build that supports it.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(
+    Clone, Copy, Debug, Display, EnumIter, EnumString, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum Encoder {
+    #[cfg_attr(feature = "clap", value(name = "libx264"))]
+    #[strum(serialize = "libx264")]
+    #[serde(rename = "libx264")]
+    Libx264,
+    #[cfg_attr(feature = "clap", value(name = "libx265"))]
+    #[strum(serialize = "libx265")]
+    #[serde(rename = "libx265")]
+    Libx265,
+    /// Apple VideoToolbox H.264
+    #[cfg_attr(feature = "clap", value(name = "h264_videotoolbox"))]
+    #[strum(serialize = "h264_videotoolbox")]
+    #[serde(rename = "h264_videotoolbox")]
+    H264Videotoolbox,
+    /// NVIDIA NVENC HEVC
+    #[cfg_attr(feature = "clap", value(name = "hevc_nvenc"))]
+    #[strum(serialize = "hevc_nvenc")]
+    #[serde(rename = "hevc_nvenc")]
+    HevcNvenc,
+    /// VAAPI H.264, using the first render node
+    #[cfg_attr(feature = "clap", value(name = "h264_vaapi"))]
+    #[strum(serialize = "h264_vaapi")]
+    #[serde(rename = "h264_vaapi")]
+    H264Vaapi,
+    /// Intel Quick Sync H.264
+    #[cfg_attr(feature = "clap", value(name = "h264_qsv"))]
+    #[strum(serialize = "h264_qsv")]
+    #[serde(rename = "h264_qsv")]
+    H264Qsv,
+    #[cfg_attr(feature = "clap", value(name = "libvpx-vp9"))]
+    #[strum(serialize = "libvpx-vp9")]
+    #[serde(rename = "libvpx-vp9")]
+    LibvpxVp9,
+    #[cfg_attr(feature = "clap", value(name = "libaom-av1"))]
+    #[strum(serialize = "libaom-av1")]
+    #[serde(rename = "libaom-av1")]
+    LibaomAv1,
+    #[cfg_attr(feature = "clap", value(name = "libsvtav1"))]
+    #[strum(serialize = "libsvtav1")]
+    #[serde(rename = "libsvtav1")]
+    Libsvtav1,
+    #[cfg_attr(feature = "clap", value(name = "prores_ks"))]
+    #[strum(serialize = "prores_ks")]
+    #[serde(rename = "prores_ks")]
+    ProresKs,
+    /// Encodes DNxHR when used with a DNxHR profile
+    #[cfg_attr(feature = "clap", value(name = "dnxhd"))]
+    #[strum(serialize = "dnxhd")]
+    #[serde(rename = "dnxhd")]
+    Dnxhd,
+}
+
+impl Encoder {
+    /// Returns the encoder used when none is specified for the given output extension
+    pub fn default_for_container(extension: &str) -> Self {
+        match extension {
+            "webm" => Encoder::LibvpxVp9,
+            _ => Encoder::Libx264,
+        }
+    }
+
+    /// Returns the codec produced by the encoder
+    pub fn codec(&self) -> Codec {
+        match self {
+            Encoder::Libx264
+            | Encoder::H264Videotoolbox
+            | Encoder::H264Vaapi
+            | Encoder::H264Qsv => Codec::H264,
+            Encoder::Libx265 | Encoder::HevcNvenc => Codec::Hevc,
+            Encoder::LibvpxVp9 => Codec::Vp9,
+            Encoder::LibaomAv1 | Encoder::Libsvtav1 => Codec::Av1,
+            Encoder::ProresKs => Codec::Prores,
+            Encoder::Dnxhd => Codec::Dnxhr,
+        }
+    }
+
+    /// Returns true if the encoder's output can be muxed into the given output extension
+    pub fn supports_container(&self, extension: &str) -> bool {
+        match extension {
+            // WebM only allows VP8, VP9, and AV1 video
+            "webm" => matches!(self.codec(), Codec::Vp9 | Codec::Av1),
+            "mp4" => !matches!(self.codec(), Codec::Prores | Codec::Dnxhr),
+            "mov" | "mkv" => true,
+            _ => false,
+        }
+    }
+
+    /// Arguments placed before the inputs, such as the hardware device to encode with
+    pub(crate) fn global_args(&self) -> Vec<String> {
+        match self {
+            Encoder::H264Vaapi => vec![
+                "-vaapi_device".to_string(),
+                "/dev/dri/renderD128".to_string(),
+            ],
+            _ => vec![],
+        }
+    }
+
+    /// Filters appended to the end of the video filter graph, such as uploading frames to the GPU
+    pub(crate) fn filter_suffix(&self) -> Option<&'static str> {
+        match self {
+            Encoder::H264Vaapi => Some("format=nv12,hwupload"),
+            _ => None,
+        }
+    }
+
+    /// Returns the constant quality flag, its default, and its valid range, where lower values are
+    /// higher quality
+    fn quality_option(&self) -> Option<(&'static str, u8, RangeInclusive<u8>)> {
+        match self {
+            Encoder::Libx264 => Some(("-crf", 18, 0..=51)),
+            Encoder::Libx265 => Some(("-crf", 20, 0..=51)),
+            Encoder::HevcNvenc => Some(("-cq", 23, 0..=51)),
+            Encoder::H264Vaapi => Some(("-qp", 20, 0..=51)),
+            Encoder::H264Qsv => Some(("-global_quality", 20, 1..=51)),
+            Encoder::LibvpxVp9 | Encoder::LibaomAv1 => Some(("-crf", 30, 0..=63)),
+            Encoder::Libsvtav1 => Some(("-crf", 35, 0..=63)),
+            Encoder::H264Videotoolbox | Encoder::ProresKs | Encoder::Dnxhd => None,
+        }
+    }
+
+    /// Returns the speed preset flag, its default, and its allowed values
+    fn preset_option(&self) -> Option<(&'static str, &'static str, &'static [&'static str])> {
+        const X26X_PRESETS: &[&str] = &[
+            "ultrafast",
+            "superfast",
+            "veryfast",
+            "faster",
+            "fast",
+            "medium",
+            "slow",
+            "slower",
+            "veryslow",
+            "placebo",
+        ];
+        match self {
+            Encoder::Libx264 | Encoder::Libx265 => Some(("-preset", "slow", X26X_PRESETS)),
+            Encoder::HevcNvenc => Some((
+                "-preset",
+                "slow",
+                &[
+                    "p1", "p2", "p3", "p4", "p5", "p6", "p7", "fast", "medium", "slow",
+                ],
+            )),
+            Encoder::H264Qsv => Some((
+                "-preset",
+                "slow",
+                &[
+                    "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow",
+                ],
+            )),
+            Encoder::LibvpxVp9 => Some(("-deadline", "good", &["realtime", "good", "best"])),
+            Encoder::LibaomAv1 => Some((
+                "-cpu-used",
+                "4",
+                &["0", "1", "2", "3", "4", "5", "6", "7", "8"],
+            )),
+            Encoder::Libsvtav1 => Some((
+                "-preset",
+                "6",
+                &[
+                    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13",
+                ],
+            )),
+            Encoder::H264Videotoolbox | Encoder::H264Vaapi | Encoder::ProresKs | Encoder::Dnxhd => {
+                None
+            }
+        }
+    }
+
+    /// Returns the tune flag, its default, and its allowed values
+    fn tune_option(&self) -> Option<(&'static str, Option<&'static str>, &'static [&'static str])> {
+        match self {
+            Encoder::Libx264 => Some((
+                "-tune",
+                Some("stillimage"),
+                &[
+                    "film",
+                    "animation",
+                    "grain",
+                    "stillimage",
+                    "fastdecode",
+                    "zerolatency",
+                    "psnr",
+                    "ssim",
+                ],
+            )),
+            Encoder::Libx265 => Some((
+                "-tune",
+                None,
+                &[
+                    "animation",
+                    "grain",
+                    "fastdecode",
+                    "zerolatency",
+                    "psnr",
+                    "ssim",
+                ],
+            )),
+            Encoder::HevcNvenc => Some(("-tune", None, &["hq", "ll", "ull", "lossless"])),
+            _ => None,
+        }
+    }
+
+    /// Returns the quality, preset, and tune arguments, validating any values set on the descriptor
+    fn rate_control_args(&self, descriptor: &EncodeFramesDescriptor) -> Result<Vec<String>> {
+        let unsupported =
+            |option: &'static str, value: String| DragonflyError::UnsupportedEncoderOption {
+                encoder: *self,
+                option,
+                value,
+            };
+        let mut args = vec![];
+        match (self.quality_option(), descriptor.crf) {
+            (Some((flag, _, range)), Some(crf)) if range.contains(&crf) => {
+                args.extend([flag.to_string(), crf.to_string()])
+            }
+            (Some((flag, default, _)), None) => {
+                args.extend([flag.to_string(), default.to_string()])
+            }
+            (_, Some(crf)) => return Err(unsupported("crf", crf.to_string())),
+            (None, None) => {}
+        }
+        match (self.preset_option(), &descriptor.preset) {
+            (Some((flag, _, allowed)), Some(preset)) if allowed.contains(&preset.as_str()) => {
+                args.extend([flag.to_string(), preset.clone()])
+            }
+            (Some((flag, default, _)), None) => {
+                args.extend([flag.to_string(), default.to_string()])
+            }
+            (_, Some(preset)) => return Err(unsupported("preset", preset.clone())),
+            (None, None) => {}
+        }
+        match (self.tune_option(), &descriptor.tune) {
+            (Some((flag, _, allowed)), Some(tune)) if allowed.contains(&tune.as_str()) => {
+                args.extend([flag.to_string(), tune.clone()])
+            }
+            (Some((flag, Some(default), _)), None) => {
+                args.extend([flag.to_string(), default.to_string()])
+            }
+            (_, Some(tune)) => return Err(unsupported("tune", tune.clone())),
+            (_, None) => {}
+        }
+        Ok(args)
+    }
+
+    /// Returns the arguments that cap the bitrate of the constant quality encode
+    fn bitrate_args(&self, bitrate: &str) -> Result<Vec<String>> {
+        let args = match self {
+            Encoder::ProresKs | Encoder::Dnxhd => {
+                return Err(DragonflyError::UnsupportedEncoderOption {
+                    encoder: *self,
+                    option: "bitrate",
+                    value: bitrate.to_string(),
+                })
+            }
+            // Constrained quality, which replaces the unconstrained -b:v 0
+            // https://trac.ffmpeg.org/wiki/Encode/VP9#constrainedq
+            Encoder::LibvpxVp9 | Encoder::LibaomAv1 => vec!["-b:v", bitrate],
+            _ => vec!["-maxrate", bitrate, "-bufsize", bitrate],
+        };
+        Ok(args.into_iter().map(String::from).collect())
+    }
+
+    /// The 10 bit pixel format of the encoder, or None when it only encodes 8 bit video portably
+    fn high_bit_depth_pix_fmt(&self) -> Option<&'static str> {
+        match self {
+            Encoder::Libx265 | Encoder::Libsvtav1 | Encoder::LibaomAv1 | Encoder::LibvpxVp9 => {
+                Some("yuv420p10le")
+            }
+            Encoder::HevcNvenc => Some("p010le"),
+            _ => None,
+        }
+    }
+
+    /// Returns the arguments that have AV1 encoders model the grain and synthesize it at playback, which
+    /// spends far less bitrate than encoding the grain itself
+    fn film_grain_args(&self, grain: u8) -> Vec<String> {
+        match self {
+            Encoder::Libsvtav1 => vec!["-svtav1-params".to_string(), format!("film-grain={grain}")],
+            Encoder::LibaomAv1 => vec!["-denoise-noise-level".to_string(), grain.to_string()],
+            _ => vec![],
+        }
+    }
+
+    /// Returns the pixel format with an alpha plane, for the encoders that support one
+    fn alpha_pix_fmt(&self, descriptor: &EncodeFramesDescriptor) -> Option<&'static str> {
+        match (self, descriptor.prores_profile) {
+            (Encoder::LibvpxVp9, _) => Some("yuva420p"),
+            (Encoder::ProresKs, ProresProfile::P4444 | ProresProfile::P4444Xq) => {
+                Some("yuva444p10le")
+            }
+            _ => None,
+        }
+    }
+
+    /// Codec arguments, where `keyframe_interval` key frames the first and last frame
+    ///
+    /// Frames with alpha keep it and high bit depth inputs are encoded as 10 bit video, when the encoder supports
+    /// it.
+    pub(crate) fn codec_args(
+        &self,
+        descriptor: &EncodeFramesDescriptor,
+        keyframe_interval: usize,
+        color: &SourceColor,
+    ) -> Result<Vec<String>> {
+        let keyframe_interval = keyframe_interval.to_string();
+        let prores_profile = (descriptor.prores_profile as u8).to_string();
+        let dnxhr_profile = format!("dnxhr_{}", descriptor.dnxhr_profile);
+        let encoder_args: Vec<&str> = match self {
+            Encoder::Libx264 | Encoder::Libx265 | Encoder::Libsvtav1 => {
+                vec!["-pix_fmt", "yuv420p", "-g", &keyframe_interval]
+            }
+            Encoder::H264Videotoolbox => vec![
+                "-b:v",
+                "20M",
+                "-pix_fmt",
+                "yuv420p",
+                "-g",
+                &keyframe_interval,
+            ],
+            Encoder::HevcNvenc => vec![
+                // Constant quality variable bitrate
+                "-rc",
+                "vbr",
+                "-b:v",
+                "0",
+                "-pix_fmt",
+                "yuv420p",
+                "-g",
+                &keyframe_interval,
+            ],
+            Encoder::H264Vaapi => vec!["-g", &keyframe_interval],
+            Encoder::H264Qsv => vec!["-pix_fmt", "nv12", "-g", &keyframe_interval],
+            // https://trac.ffmpeg.org/wiki/Encode/VP9
+            Encoder::LibvpxVp9 => vec!["-b:v", "0"],
+            // https://trac.ffmpeg.org/wiki/Encode/AV1
+            Encoder::LibaomAv1 => vec!["-b:v", "0", "-row-mt", "1", "-pix_fmt", "yuv420p"],
+            // Intra-frame codecs key frame every frame
+            Encoder::ProresKs => vec![
+                "-profile:v",
+                &prores_profile,
+                // Identify as Apple's encoder so Final Cut Pro accepts the output
+                "-vendor",
+                "apl0",
+                "-pix_fmt",
+                descriptor.prores_profile.pix_fmt(),
+            ],
+            Encoder::Dnxhd => vec![
+                "-profile:v",
+                &dnxhr_profile,
+                "-pix_fmt",
+                descriptor.dnxhr_profile.pix_fmt(),
+            ],
+        };
+        let mut args = vec!["-c:v".to_string(), self.to_string()];
+        args.extend(self.rate_control_args(descriptor)?);
+        args.extend(encoder_args.into_iter().map(String::from));
+        if let Some(bitrate) = &descriptor.bitrate {
+            args.extend(self.bitrate_args(bitrate)?);
+        }
+        if let Some(grain) = descriptor.grain {
+            args.extend(self.film_grain_args(grain));
+        }
+        let alpha_pix_fmt = self.alpha_pix_fmt(descriptor).filter(|_| color.alpha);
+        if color.alpha && alpha_pix_fmt.is_none() {
+            warn!("{self} drops the alpha channel, use --codec vp9 or --codec prores --prores-profile 4444 to keep it");
+        }
+        let pix_fmt = match alpha_pix_fmt {
+            Some(pix_fmt) => Some(pix_fmt),
+            None if color.is_high_bit_depth() => {
+                let pix_fmt = self.high_bit_depth_pix_fmt();
+                if pix_fmt.is_none() && !matches!(self, Encoder::ProresKs | Encoder::Dnxhd) {
+                    warn!(
+                        "{self} encodes 8 bit video, use --codec hevc or av1 to keep the {} bit input",
+                        color.bit_depth.max(10)
+                    );
+                }
+                pix_fmt
+            }
+            None => None,
+        };
+        if let Some(pix_fmt) = pix_fmt {
+            match args.iter().position(|arg| arg == "-pix_fmt") {
+                Some(i) => args[i + 1] = pix_fmt.to_string(),
+                None => args.extend(["-pix_fmt".to_string(), pix_fmt.to_string()]),
+            }
+        }
+        Ok(args)
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{Encoder, FFMPEG_BINARY_PATH, FFPROBE_BINARY_PATH};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::process::{Command, Stdio};
+use strum::IntoEnumIterator;
+
+/// Filters used by dragonfly, of which only v360 is always required
+const FILTERS: [&str; 12] = [
+    "v360",
+    "sendcmd",
+    "drawtext",
+    "lut3d",
+    "minterpolate",
+    "tmix",
+    "palettegen",
+    "paletteuse",
+    "scale2ref",
+    "overlay",
+    "colorchannelmixer",
+    "reverse",
+];
+
+/// Encoders used for output formats other than video
+const IMAGE_ENCODERS: [&str; 3] = ["gif", "libwebp", "apng"];
+
+/// The ffmpeg features available on this machine
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EnvironmentReport {
+    /// First line of `ffmpeg -version`, missing when ffmpeg could not be run
+    pub ffmpeg_version: Option<String>,
+    /// First line of `ffprobe -version`, missing when ffprobe could not be run
+    pub ffprobe_version: Option<String>,
+    /// Whether each filter used by dragonfly is available
+    pub filters: BTreeMap<String, bool>,
+    /// Whether each encoder used by dragonfly is available
+    pub encoders: BTreeMap<String, bool>,
+    /// Whether v360 accepts runtime commands, which single pass extraction relies on
+    pub v360_commands: bool,
+}
+
+impl EnvironmentReport {
+    pub fn has_filter(&self, filter: &str) -> bool {
+        self.filters.get(filter).copied().unwrap_or(false)
+    }
+
+    pub fn has_encoder(&self, encoder: Encoder) -> bool {
+        self.encoders
+            .get(&encoder.to_string())
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Returns a description of each problem that prevents dragonfly from running, along with how to fix it
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors = vec![];
+        if self.ffmpeg_version.is_none() {
+            errors.push(format!(
+                "{:?} not found, please install it from https://ffmpeg.org/ or set FFMPEG_BINARY_PATH",
+                FFMPEG_BINARY_PATH.as_os_str()
+            ));
+        }
+        if self.ffprobe_version.is_none() {
+            errors.push(format!(
+                "{:?} not found, please install it from https://ffmpeg.org/ or set FFPROBE_BINARY_PATH",
+                FFPROBE_BINARY_PATH.as_os_str()
+            ));
+        }
+        if self.ffmpeg_version.is_some() && !self.has_filter("v360") {
+            errors.push(
+                "ffmpeg is missing the v360 filter, please upgrade to ffmpeg 4.3 or newer"
+                    .to_string(),
+            );
+        }
+        errors
+    }
+}
+
+/// Runs ffmpeg and ffprobe to find the filters and encoders available, so problems can be reported up front
+/// instead of partway through an extraction
+pub fn check_environment() -> EnvironmentReport {
+    let ffmpeg = FFMPEG_BINARY_PATH.as_os_str();
+    let ffmpeg_version = first_line(&run(ffmpeg, "-version"));
+    let ffprobe_version = first_line(&run(FFPROBE_BINARY_PATH.as_os_str(), "-version"));
+
+    // Filter lines look like " TSC v360              V->V       Convert 360 projection of video."
+    let filters_output = run(ffmpeg, "-filters").unwrap_or_default();
+    let available_filters: Vec<(&str, &str)> = filters_output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (flags, name, io) = (fields.next()?, fields.next()?, fields.next()?);
+            io.contains("->").then_some((flags, name))
+        })
+        .collect();
+    let filters = FILTERS
+        .iter()
+        .map(|filter| {
+            let available = available_filters.iter().any(|(_, name)| name == filter);
+            (filter.to_string(), available)
+        })
+        .collect();
+    let v360_commands = available_filters
+        .iter()
+        .any(|(flags, name)| *name == "v360" && flags.contains('C'));
+
+    // Encoder lines follow a dashed separator and look like " V....D libx264    libx264 H.264 / AVC"
+    let encoders_output = run(ffmpeg, "-encoders").unwrap_or_default();
+    let available_encoders: Vec<&str> = encoders_output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .collect();
+    let encoders = Encoder::iter()
+        .map(|encoder| encoder.to_string())
+        .chain(IMAGE_ENCODERS.iter().map(|encoder| encoder.to_string()))
+        .map(|encoder| {
+            let available = available_encoders.contains(&encoder.as_str());
+            (encoder, available)
+        })
+        .collect();
+
+    EnvironmentReport {
+        ffmpeg_version,
+        ffprobe_version,
+        filters,
+        encoders,
+        v360_commands,
+    }
+}
+
+/// Returns the stdout of a successful run of the binary with a single argument
+fn run(binary: &OsStr, arg: &str) -> Option<String> {
+    let output = Command::new(binary)
+        .args(["-hide_banner", arg])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn first_line(output: &Option<String>) -> Option<String> {
+    output
+        .as_deref()
+        .and_then(|output| output.lines().next())
+        .map(|line| line.trim().to_string())
+}
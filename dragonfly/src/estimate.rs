@@ -0,0 +1,175 @@
+use crate::{
+    command_line, encode_frames, ffprobe_info, read_stderr, spawn, Backend, DragonflyError,
+    EncodeFramesDescriptor, ExtractFramesDescriptor, Extraction, ProgressEvent, Result,
+    SystemExecutor,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// What extracting and encoding an input is projected to cost, from rendering a few sample frames
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Estimate {
+    /// Frames the extraction would render
+    pub frame_count: usize,
+    pub width: u32,
+    pub height: u32,
+    /// Sample frames rendered to make the estimate
+    pub sample_count: usize,
+    /// Average time to render a sample frame
+    pub frame_duration: Duration,
+    /// Time to render every frame, in one ffmpeg process or as many at a time as the extraction would run
+    pub extract_duration: Duration,
+    /// Bytes the extracted frames take on disk
+    pub frames_size: u64,
+    /// Bytes of the encoded video, usually an overestimate as the sample frames are far apart and compress
+    /// worse than consecutive frames
+    pub output_size: u64,
+}
+
+/// Renders `sample_count` frames spread over the extraction to `extraction_path`, timed the way the extraction
+/// renders them, then encodes them with the encode settings to project the extraction time, the space the frames
+/// take, and the size of the video at `output_path`, whose extension picks the container
+pub fn estimate(
+    input_path: &Path,
+    output_path: &Path,
+    extraction_path: &Path,
+    extract_descriptor: &ExtractFramesDescriptor,
+    encode_descriptor: &EncodeFramesDescriptor,
+    sample_count: usize,
+) -> Result<Estimate> {
+    if extract_descriptor.backend == Backend::Native {
+        return Err(DragonflyError::UnsupportedByBackend(
+            Backend::Native,
+            "estimates".to_string(),
+        ));
+    }
+    let input = ffprobe_info(input_path, &SystemExecutor)?;
+    let extraction = Extraction::new(input_path, extract_descriptor, &input)?;
+    let descriptor = &extraction.descriptor;
+    let sample_count = sample_count.clamp(1, descriptor.frame_count);
+    fs::create_dir_all(extraction_path)?;
+    // Views across the rotation differ in how long they take to render and how well they compress
+    let frames: Vec<usize> = (0..sample_count)
+        .map(|sample| sample * descriptor.frame_count / sample_count)
+        .collect();
+    let frame_count = descriptor.frame_count;
+    // Time the samples the way the extraction renders its frames, falling back to one process per frame
+    // when v360 does not accept runtime commands
+    let single_pass_duration = if extraction.is_single_pass() {
+        time_single_pass(&extraction, extraction_path, &frames).ok()
+    } else {
+        None
+    };
+    let (frame_duration, extract_duration) = match single_pass_duration {
+        Some(duration) => {
+            let frame_duration = duration / sample_count as u32;
+            (frame_duration, frame_duration * frame_count as u32)
+        }
+        None => {
+            let frame_duration = time_per_frame(&extraction, extraction_path, &frames)?;
+            let extract_duration =
+                frame_duration * frame_count as u32 / descriptor.thread_count().max(1) as u32;
+            (frame_duration, extract_duration)
+        }
+    };
+    let mut frames_size = 0;
+    for sample in 0..sample_count {
+        frames_size += fs::metadata(extraction.frame_path(extraction_path, sample))?.len();
+    }
+
+    // Encode one output frame per sample, without anything added around the rotation
+    let encode_descriptor = encode_descriptor.with_preset()?.into_owned();
+    let output_frame_count = encode_descriptor.output_frame_count();
+    let sample_descriptor = EncodeFramesDescriptor {
+        length: sample_count as f32 / encode_descriptor.fps,
+        boomerang: false,
+        loop_blend: None,
+        frame_interpolation: None,
+        motion_blur: None,
+        audio: None,
+        fade_in: 0.0,
+        fade_out: 0.0,
+        title_card: None,
+        end_card: None,
+        spherical: false,
+        poster: None,
+        sprite_sheet: None,
+        ..encode_descriptor
+    };
+    let extension = output_path
+        .extension()
+        .ok_or_else(|| DragonflyError::InvalidPathString(output_path.to_path_buf()))?;
+    let sample_output_path = extraction_path.join("sample").with_extension(extension);
+    encode_frames(
+        &sample_output_path,
+        extraction_path,
+        &sample_descriptor,
+        None::<fn(ProgressEvent)>,
+        None,
+    )?;
+    let sample_output_size = fs::metadata(&sample_output_path)?.len();
+    let output_size = sample_output_size * output_frame_count as u64 / sample_count as u64;
+    let (width, height) = extraction.output_resolution;
+    Ok(Estimate {
+        frame_count,
+        width,
+        height,
+        sample_count,
+        frame_duration,
+        extract_duration,
+        frames_size: frames_size * frame_count as u64 / sample_count as u64,
+        output_size,
+    })
+}
+
+/// Times rendering the sampled frames in one ffmpeg process, as a single pass extraction would
+fn time_single_pass(
+    extraction: &Extraction,
+    extraction_path: &Path,
+    frames: &[usize],
+) -> Result<Duration> {
+    let (mut ffmpeg_cmd, _script) =
+        extraction.single_pass_sample_command(extraction_path, frames)?;
+    let start = Instant::now();
+    let mut ffmpeg_child = spawn(&SystemExecutor, &mut ffmpeg_cmd)?;
+    let stderr = read_stderr(ffmpeg_child.as_mut());
+    let status = ffmpeg_child.wait()?;
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr,
+        });
+    }
+    Ok(start.elapsed())
+}
+
+/// Renders each sampled frame in its own ffmpeg process, returning the average time a frame took
+fn time_per_frame(
+    extraction: &Extraction,
+    extraction_path: &Path,
+    frames: &[usize],
+) -> Result<Duration> {
+    let mut frame_durations = vec![];
+    for (sample, &frame) in frames.iter().enumerate() {
+        let start = Instant::now();
+        // The samples are numbered consecutively so the encoder reads them as a video
+        let sample_path = extraction.frame_path(extraction_path, sample);
+        let mut ffmpeg_cmd =
+            extraction.render_frame_command(extraction_path, frame, &sample_path)?;
+        let mut ffmpeg_child = spawn(&SystemExecutor, &mut ffmpeg_cmd)?;
+        let stderr = read_stderr(ffmpeg_child.as_mut());
+        let status = ffmpeg_child.wait()?;
+        if !status.success() {
+            return Err(DragonflyError::FfmpegFailed {
+                command: command_line(&ffmpeg_cmd),
+                status,
+                stderr,
+            });
+        }
+        frame_durations.push(start.elapsed());
+    }
+    Ok(frame_durations.iter().sum::<Duration>() / frames.len() as u32)
+}
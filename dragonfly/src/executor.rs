@@ -0,0 +1,216 @@
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, ExitStatus};
+
+/// Starts the ffmpeg and ffprobe processes run by dragonfly
+///
+/// [`SystemExecutor`] spawns real processes. Tests and downstream crates can substitute an executor that
+/// records the commands and returns canned output instead.
+pub trait Executor: Sync {
+    /// Starts the command, whose stdio has already been configured
+    fn spawn(&self, command: &mut Command) -> io::Result<Box<dyn Process>>;
+}
+
+/// A process started by an [`Executor`]
+///
+/// The streams are only available when the command piped them, and can each be taken once.
+pub trait Process: Send {
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>>;
+    fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>>;
+    fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>>;
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>>;
+    fn wait(&mut self) -> io::Result<ExitStatus>;
+    fn kill(&mut self) -> io::Result<()>;
+}
+
+/// Spawns commands with [`std::process::Command`]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemExecutor;
+
+impl Executor for SystemExecutor {
+    fn spawn(&self, command: &mut Command) -> io::Result<Box<dyn Process>> {
+        Ok(Box::new(command.spawn()?))
+    }
+}
+
+impl Process for Child {
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+        self.stdin
+            .take()
+            .map(|stdin| Box::new(stdin) as Box<dyn Write + Send>)
+    }
+
+    fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+        self.stdout
+            .take()
+            .map(|stdout| Box::new(stdout) as Box<dyn Read + Send>)
+    }
+
+    fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+        self.stderr
+            .take()
+            .map(|stderr| Box::new(stderr) as Box<dyn Read + Send>)
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        Child::try_wait(self)
+    }
+
+    fn wait(&mut self) -> io::Result<ExitStatus> {
+        Child::wait(self)
+    }
+
+    fn kill(&mut self) -> io::Result<()> {
+        Child::kill(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        command_line, extract_frames_with_executor, Backend, ExtractFramesDescriptor, ProgressEvent,
+    };
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    const FFPROBE_STDOUT: &str = r#"{"streams":[{"index":0,"codec_type":"video","codec_name":"mjpeg","width":4000,"height":2000,"pix_fmt":"yuvj420p","r_frame_rate":"25/1"}],"format":{"format_name":"image2","duration":"0.040000"}}"#;
+
+    /// Records the command line of every process it is asked to start, answering ffprobe with a still image
+    #[derive(Default)]
+    struct MockExecutor {
+        commands: Mutex<Vec<String>>,
+    }
+
+    impl Executor for MockExecutor {
+        fn spawn(&self, command: &mut Command) -> io::Result<Box<dyn Process>> {
+            let is_ffprobe = command.get_program().to_string_lossy().contains("ffprobe");
+            // ffmpeg would fail to open a remap map that was not written before it started
+            if let Some(map) = command
+                .get_args()
+                .map(Path::new)
+                .find(|arg| arg.extension().is_some_and(|ext| ext == "pgm") && !arg.is_file())
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{map:?} does not exist"),
+                ));
+            }
+            self.commands.lock().unwrap().push(command_line(command));
+            Ok(Box::new(MockProcess {
+                stdout: is_ffprobe.then(|| FFPROBE_STDOUT.as_bytes().to_vec()),
+            }))
+        }
+    }
+
+    /// A process that has already exited successfully, having written `stdout`
+    struct MockProcess {
+        stdout: Option<Vec<u8>>,
+    }
+
+    impl Process for MockProcess {
+        fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+            None
+        }
+
+        fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+            self.stdout
+                .take()
+                .map(|stdout| Box::new(io::Cursor::new(stdout)) as Box<dyn Read + Send>)
+        }
+
+        fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+            Some(Box::new(io::empty()))
+        }
+
+        fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+            self.wait().map(Some)
+        }
+
+        fn wait(&mut self) -> io::Result<ExitStatus> {
+            #[cfg(unix)]
+            use std::os::unix::process::ExitStatusExt;
+            #[cfg(windows)]
+            use std::os::windows::process::ExitStatusExt;
+            Ok(ExitStatus::from_raw(0))
+        }
+
+        fn kill(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Extracts with the mock executor to a new directory, returning the commands it was asked to start
+    fn extract_commands(name: &str, descriptor: &ExtractFramesDescriptor) -> Vec<String> {
+        let dir = std::env::temp_dir().join(format!(
+            "com.jshrake.dragonfly-test-{name}-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let input_path = dir.join("input.jpg");
+        fs::write(&input_path, [0xff, 0xd8, 0xff, 0xd9]).unwrap();
+        let executor = MockExecutor::default();
+        let result = extract_frames_with_executor(
+            &input_path,
+            &dir.join("frames"),
+            descriptor,
+            None::<fn(ProgressEvent)>,
+            None,
+            &executor,
+        );
+        fs::remove_dir_all(&dir).ok();
+        result.unwrap();
+        executor.commands.into_inner().unwrap()
+    }
+
+    #[test]
+    fn extracts_a_still_image_in_a_single_pass() {
+        let descriptor = ExtractFramesDescriptor {
+            frame_count: 4,
+            ..Default::default()
+        };
+        let commands = extract_commands("single-pass", &descriptor);
+        assert_eq!(commands.len(), 2, "{commands:#?}");
+        assert!(commands[0].contains("ffprobe"));
+        assert!(commands[0].contains("-show_streams"));
+        assert!(commands[1].contains("sendcmd="));
+        assert!(commands[1].contains("v360"));
+        assert!(commands[1].contains("-frames:v 4"));
+    }
+
+    #[test]
+    fn extracts_one_process_per_frame() {
+        let descriptor = ExtractFramesDescriptor {
+            frame_count: 3,
+            per_frame: true,
+            ..Default::default()
+        };
+        let commands = extract_commands("per-frame", &descriptor);
+        assert_eq!(commands.len(), 4, "{commands:#?}");
+        assert!(commands[0].contains("ffprobe"));
+        assert!(commands[1..].iter().all(|command| command.contains("v360")));
+        for frame in 0..3 {
+            let frame_file = format!("frame_{frame:08}.jpg");
+            assert!(
+                commands[1..]
+                    .iter()
+                    .any(|command| command.ends_with(&frame_file)),
+                "no command renders {frame_file}: {commands:#?}"
+            );
+        }
+    }
+
+    #[test]
+    fn writes_the_remap_maps_before_each_frame() {
+        let descriptor = ExtractFramesDescriptor {
+            frame_count: 2,
+            backend: Backend::Remap,
+            ..Default::default()
+        };
+        let commands = extract_commands("remap", &descriptor);
+        assert_eq!(commands.len(), 3, "{commands:#?}");
+        assert!(commands[1..]
+            .iter()
+            .all(|command| command.contains("remap")));
+    }
+}
@@ -0,0 +1,77 @@
+use crate::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The file name prefix of every extraction directory created by [`ExtractionDir`]
+pub const EXTRACTION_DIR_PREFIX: &str = "com.jshrake.dragonfly-";
+
+/// A directory for extracted frames, removed along with its contents when dropped unless it is kept
+#[derive(Debug)]
+pub struct ExtractionDir {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl ExtractionDir {
+    /// Creates a directory in the system temp directory
+    pub fn new() -> Result<Self> {
+        Self::new_in(&std::env::temp_dir())
+    }
+
+    /// Creates a directory in `parent`, named after the current time so old directories can be found later
+    pub fn new_in(parent: &Path) -> Result<Self> {
+        static DIR_COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = parent.join(format!(
+            "{EXTRACTION_DIR_PREFIX}{}-{}-{}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            std::process::id(),
+            DIR_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&path)?;
+        Ok(Self { path, keep: false })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Leaves the directory and its frames in place, returning its path
+    pub fn keep(mut self) -> PathBuf {
+        self.keep = true;
+        std::mem::take(&mut self.path)
+    }
+}
+
+impl Drop for ExtractionDir {
+    fn drop(&mut self) {
+        if !self.keep {
+            fs::remove_dir_all(&self.path).ok();
+        }
+    }
+}
+
+/// Removes the extraction directories in `parent` that were last modified more than `older_than` ago, returning
+/// the removed paths
+pub fn remove_extraction_dirs(parent: &Path, older_than: Duration) -> Result<Vec<PathBuf>> {
+    let mut removed = vec![];
+    for entry in fs::read_dir(parent)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let is_extraction_dir = metadata.is_dir()
+            && entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(EXTRACTION_DIR_PREFIX);
+        let age = metadata.modified()?.elapsed().unwrap_or(Duration::ZERO);
+        if is_extraction_dir && age >= older_than {
+            fs::remove_dir_all(entry.path())?;
+            removed.push(entry.path());
+        }
+    }
+    Ok(removed)
+}
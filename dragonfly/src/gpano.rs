@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+/// How far into a file to look for XMP, which cameras and stitchers write near the start
+const XMP_SEARCH_LIMIT: u64 = 1 << 20;
+
+/// The Photo Sphere (GPano) XMP metadata that cameras and stitching software embed in panoramas
+///
+/// See <https://developers.google.com/streetview/spherical-metadata>.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct GPano {
+    pub projection_type: Option<String>,
+    pub full_pano_width_pixels: Option<u32>,
+    pub full_pano_height_pixels: Option<u32>,
+    pub cropped_area_image_width_pixels: Option<u32>,
+    pub cropped_area_image_height_pixels: Option<u32>,
+    pub cropped_area_left_pixels: Option<u32>,
+    pub cropped_area_top_pixels: Option<u32>,
+    /// Pitch in degrees of the camera when the panorama was captured
+    pub pose_pitch_degrees: Option<f32>,
+    /// Roll in degrees of the camera when the panorama was captured
+    pub pose_roll_degrees: Option<f32>,
+}
+
+impl GPano {
+    /// Returns true if the image covers only part of the full panorama
+    pub fn is_cropped(&self) -> bool {
+        matches!(
+            (
+                self.full_pano_width_pixels,
+                self.full_pano_height_pixels,
+                self.cropped_area_image_width_pixels,
+                self.cropped_area_image_height_pixels,
+            ),
+            (Some(full_w), Some(full_h), Some(w), Some(h)) if w < full_w || h < full_h
+        )
+    }
+
+    /// Returns the horizontal and vertical field of view in degrees covered by a cropped equirectangular panorama
+    pub fn input_fov(&self) -> Option<(f32, f32)> {
+        if self.projection_type.as_deref() != Some("equirectangular") || !self.is_cropped() {
+            return None;
+        }
+        Some((
+            360.0 * self.cropped_area_image_width_pixels? as f32
+                / self.full_pano_width_pixels? as f32,
+            180.0 * self.cropped_area_image_height_pixels? as f32
+                / self.full_pano_height_pixels? as f32,
+        ))
+    }
+
+    /// Returns true if the cropped area is centered in the full panorama, which v360 assumes of a partial input
+    pub fn is_centered(&self) -> bool {
+        let centered = |full: Option<u32>, size: Option<u32>, offset: Option<u32>| match (
+            full, size, offset,
+        ) {
+            (Some(full), Some(size), Some(offset)) => {
+                (2 * offset as i64 + size as i64 - full as i64).abs() <= 2
+            }
+            _ => true,
+        };
+        centered(
+            self.full_pano_width_pixels,
+            self.cropped_area_image_width_pixels,
+            self.cropped_area_left_pixels,
+        ) && centered(
+            self.full_pano_height_pixels,
+            self.cropped_area_image_height_pixels,
+            self.cropped_area_top_pixels,
+        )
+    }
+}
+
+/// Reads the GPano metadata of an image, returning None when it has none
+pub fn read_gpano(path: &Path) -> Option<GPano> {
+    let mut contents = vec![];
+    fs::File::open(path)
+        .ok()?
+        .take(XMP_SEARCH_LIMIT)
+        .read_to_end(&mut contents)
+        .ok()?;
+    let contents = String::from_utf8_lossy(&contents);
+    let start = contents.find("<x:xmpmeta")?;
+    let end = contents[start..]
+        .find("</x:xmpmeta>")
+        .map_or(contents.len(), |end| start + end);
+    let xmp = &contents[start..end];
+    let number = |name| xmp_property(xmp, name).and_then(|value| value.parse().ok());
+    let degrees = |name| xmp_property(xmp, name).and_then(|value| value.parse().ok());
+    let gpano = GPano {
+        projection_type: xmp_property(xmp, "ProjectionType"),
+        full_pano_width_pixels: number("FullPanoWidthPixels"),
+        full_pano_height_pixels: number("FullPanoHeightPixels"),
+        cropped_area_image_width_pixels: number("CroppedAreaImageWidthPixels"),
+        cropped_area_image_height_pixels: number("CroppedAreaImageHeightPixels"),
+        cropped_area_left_pixels: number("CroppedAreaLeftPixels"),
+        cropped_area_top_pixels: number("CroppedAreaTopPixels"),
+        pose_pitch_degrees: degrees("PosePitchDegrees"),
+        pose_roll_degrees: degrees("PoseRollDegrees"),
+    };
+    (gpano != GPano::default()).then_some(gpano)
+}
+
+/// Returns a GPano property, written either as an attribute or as an element
+fn xmp_property(xmp: &str, name: &str) -> Option<String> {
+    let attribute = format!("GPano:{name}=");
+    if let Some(start) = xmp.find(&attribute) {
+        let value = &xmp[start + attribute.len()..];
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        return Some(value[..value.find(quote)?].trim().to_string());
+    }
+    let element = format!("<GPano:{name}>");
+    let start = xmp.find(&element)? + element.len();
+    let value = &xmp[start..];
+    Some(value[..value.find('<')?].trim().to_string())
+}
@@ -0,0 +1,77 @@
+use crate::progress::{self, ProgressEvent, ProgressHandler};
+use crate::{
+    cancel, extract_frame, frame_path, CancellationToken, DragonflyError, ExtractFramesDescriptor,
+    Result,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// A time-ordered sequence of 360 stills, such as one captured every minute, rendered as a single rotation
+///
+/// Each frame of the rotation renders the still at the same point in the sequence, so time and yaw advance
+/// together. Stills are held for several frames when there are more frames than stills, and skipped when there
+/// are fewer.
+#[derive(Clone, Debug)]
+pub struct HyperlapseSequence {
+    pub input_paths: Vec<PathBuf>,
+}
+
+impl HyperlapseSequence {
+    /// The stills must already be in time order
+    pub fn new(input_paths: &[PathBuf]) -> Result<Self> {
+        if input_paths.len() < 2 {
+            return Err(DragonflyError::InvalidHyperlapse(
+                "at least two stills are needed".to_string(),
+            ));
+        }
+        Ok(Self {
+            input_paths: input_paths.to_vec(),
+        })
+    }
+
+    /// Returns the still rendered by the given frame of a rotation of `frame_count` frames
+    pub fn input_path(&self, frame: usize, frame_count: usize) -> &Path {
+        let index = frame * self.input_paths.len() / frame_count.max(1);
+        &self.input_paths[index.min(self.input_paths.len() - 1)]
+    }
+
+    /// Renders every frame of the rotation to `extraction_path`
+    pub fn extract(
+        &self,
+        extraction_path: &Path,
+        descriptor: &ExtractFramesDescriptor,
+        progress_handler: Option<impl ProgressHandler>,
+        cancellation_token: Option<&CancellationToken>,
+    ) -> Result<()> {
+        fs::create_dir_all(extraction_path)?;
+        let frame_count = descriptor.frame_count;
+        progress::report(
+            progress_handler.as_ref(),
+            ProgressEvent::ExtractStarted { frame_count },
+        );
+        for frame in 0..frame_count {
+            if cancel::is_cancelled(cancellation_token) {
+                return Err(DragonflyError::Cancelled);
+            }
+            let start = Instant::now();
+            let path = frame_path(extraction_path, frame, descriptor.intermediate_format);
+            extract_frame(
+                self.input_path(frame, frame_count),
+                &path,
+                descriptor,
+                frame,
+            )?;
+            progress::report(
+                progress_handler.as_ref(),
+                ProgressEvent::FrameExtracted {
+                    index: frame,
+                    path: Some(path),
+                    duration: start.elapsed(),
+                },
+            );
+        }
+        progress::report(progress_handler.as_ref(), ProgressEvent::Finished);
+        Ok(())
+    }
+}
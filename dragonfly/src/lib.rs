@@ -1,12 +1,97 @@
-use log::debug;
+use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::ffi::OsString;
 use std::fs;
+use std::io::{self, Read, Seek};
 use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 use strum::{Display, EnumString};
 use thiserror::Error;
 
+#[cfg(feature = "async")]
+mod asynchronous;
+mod builder;
+mod camera_path;
+mod cancel;
+mod color;
+mod crossfade;
+mod cubemap;
+mod disk_space;
+#[cfg(feature = "download")]
+mod download;
+mod easing;
+mod encoder;
+mod environment;
+mod estimate;
+mod executor;
+mod extraction_dir;
+mod gpano;
+mod hyperlapse;
+mod loop_quality;
+mod manifest;
+mod montage;
+mod native;
+mod overlay;
+mod pan;
+mod plan;
+mod poster;
+mod preset;
+mod probe;
+mod progress;
+mod project;
+mod remap;
+mod rendition;
+mod report;
+mod retry;
+mod speed;
+mod spherical;
+mod sprites;
+mod tour;
+
+#[cfg(feature = "async")]
+pub use asynchronous::{encode_frames_async, extract_frames_async};
+pub use builder::Dragonfly;
+pub use camera_path::{parse_camera_path, CameraPath, Keyframe};
+pub use cancel::CancellationToken;
+pub use color::{ColorMatrix, ColorPrimaries, ColorRange, ColorTransfer, SourceColor};
+pub use crossfade::CrossfadeSequence;
+pub use cubemap::{cubemap, CubemapDescriptor, CubemapLayout};
+pub use disk_space::format_size;
+#[cfg(feature = "download")]
+pub use download::{download_ffmpeg, ensure_ffmpeg, ffmpeg_cache_dir};
+pub use easing::Easing;
+pub use encoder::{Codec, DnxhrProfile, Encoder, ProresProfile};
+pub use environment::{check_environment, EnvironmentReport};
+pub use estimate::{estimate, Estimate};
+pub use executor::{Executor, Process, SystemExecutor};
+pub use extraction_dir::{remove_extraction_dirs, ExtractionDir, EXTRACTION_DIR_PREFIX};
+pub use gpano::{read_gpano, GPano};
+pub use hyperlapse::HyperlapseSequence;
+pub use loop_quality::{loop_quality, verify_loop, LoopQuality};
+pub use manifest::{ExtractionManifest, MANIFEST_FILE};
+pub use montage::{montage, MontageDescriptor};
+pub use native::{Backend, RgbImage};
+pub use overlay::{Anchor, NadirPatchDescriptor, TextOverlay, WatermarkDescriptor};
+pub use pan::{extract_pan_frames, PanDescriptor};
+pub use plan::{plan_encode_frames, plan_extract_frames, PlannedCommand, PlannedFile};
+pub use preset::{EncodePreset, BUILTIN_PRESETS};
+pub use probe::{
+    probe, probe_input, DetectedProjection, InputProbe, MediaInfo, StreamInfo, StreamKind,
+};
+pub use progress::{ProgressEvent, ProgressHandler};
+pub use project::Project;
+pub use rendition::{encode_renditions, Rendition};
+pub use report::{ExtractReport, ExtractedFrame};
+pub use retry::{process_policy, set_process_policy, ProcessPolicy};
+pub use speed::SpeedRamp;
+pub use spherical::inject_spherical_metadata;
+pub use sprites::sprite_vtt_path;
+pub use tour::{parse_tour, Tour, Waypoint};
+
 static FFMPEG_BINARY_PATH_DEFAULT: &str = if cfg!(target_os = "windows") {
     "ffmpeg.exe"
 } else {
@@ -20,38 +105,150 @@ static FFPROBE_BINARY_PATH_DEFAULT: &str = if cfg!(target_os = "windows") {
 };
 
 lazy_static::lazy_static! {
-    pub static ref FFMPEG_BINARY_PATH: OsString = std::env::var("FFMPEG_BINARY_PATH")
-        .unwrap_or_else(|_| FFMPEG_BINARY_PATH_DEFAULT.to_string()).into();
-    pub static ref FFPROBE_BINARY_PATH: OsString = std::env::var("FFPROBE_BINARY_PATH")
-        .unwrap_or_else(|_| FFPROBE_BINARY_PATH_DEFAULT.to_string()).into();
+    pub static ref FFMPEG_BINARY_PATH: OsString = binary_path("FFMPEG_BINARY_PATH", FFMPEG_BINARY_PATH_DEFAULT);
+    pub static ref FFPROBE_BINARY_PATH: OsString = binary_path("FFPROBE_BINARY_PATH", FFPROBE_BINARY_PATH_DEFAULT);
+}
+
+/// Returns the binary set by the environment variable `var`, falling back to a downloaded build when the
+/// download feature is enabled, and then to `name` on the PATH
+fn binary_path(var: &str, name: &str) -> OsString {
+    let downloaded = || {
+        #[cfg(feature = "download")]
+        return download::downloaded_binary(name);
+        #[cfg(not(feature = "download"))]
+        None
+    };
+    std::env::var_os(var)
+        .or_else(downloaded)
+        .unwrap_or_else(|| name.into())
 }
 
 #[derive(Debug, Error)]
 pub enum DragonflyError {
     #[error("Source input contains no streams")]
     SourceContainsNoStream,
+    #[error("Input {0} does not exist")]
+    InputNotFound(PathBuf),
+    #[error("ffmpeg cannot decode {path}: {}", reason.trim())]
+    UndecodableInput { path: PathBuf, reason: String },
+    #[error("Frame count must be greater than 0")]
+    InvalidFrameCount,
+    #[error("{name} of {value} degrees is out of range, must be greater than 0 and at most {max}")]
+    InvalidFieldOfView {
+        name: &'static str,
+        value: f32,
+        max: f32,
+    },
+    #[error("The nadir patch can only be applied to a single eye of a stereo input")]
+    StereoNadirPatch,
+    #[error(
+        "A flat projection cannot show a {0} degree field of view, which must be less than 180"
+    )]
+    FlatFieldOfViewTooWide(f32),
     #[error("Error while running ffprobe: {0}")]
     Command(#[from] std::io::Error),
     #[error("Error serializing JSON: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("Error deserializing TOML: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("Error serializing TOML: {0}")]
+    TomlSerialize(#[from] toml::ser::Error),
+    #[error("Invalid camera path: {0}")]
+    InvalidCameraPath(String),
+    #[error("Invalid crossfade: {0}")]
+    InvalidCrossfade(String),
+    #[error("Invalid hyperlapse: {0}")]
+    InvalidHyperlapse(String),
+    #[error("Invalid speed {0}")]
+    InvalidSpeed(String),
+    #[error("Invalid tour: {0}")]
+    InvalidTour(String),
+    #[error("Invalid easing {0}. Must be linear, ease-in, ease-out, ease-in-out, or cubic-bezier(x1,y1,x2,y2)")]
+    InvalidEasing(String),
     #[error("Error converting path to str: {0}")]
     InvalidPathString(PathBuf),
-    #[error("Output extension {0} not supported. Must be mp4, webm, or gif")]
+    #[error("Output extension {0} not supported. Must be mp4, mov, mkv, webm, gif, webp, or apng")]
     UnsupportedOutputFormat(String),
-    #[error("Error extracting images with ffmpeg")]
-    FfmpegExtractFailed,
+    #[error("Encoder {0} cannot be used with {1} output")]
+    IncompatibleEncoder(Encoder, String),
+    #[error("Encoder {0} does not produce {1} video")]
+    EncoderCodecMismatch(Encoder, Codec),
+    #[error("Error writing spherical metadata: {0}")]
+    SphericalMetadata(String),
+    #[error("Invalid metadata {0}. Must be key=value")]
+    InvalidMetadata(String),
+    #[error("Invalid rendition {0}. Must be HEIGHT[p][:BITRATE], such as 1080p:6M")]
+    InvalidRendition(String),
+    #[error("Invalid muxer option {0}. Must be key=value")]
+    InvalidMuxerOption(String),
+    #[error("Output extension {0} does not support audio")]
+    AudioNotSupported(String),
+    #[error("Unknown encode preset {0}. Must be youtube-4k, youtube-1080p, instagram, instagram-square, twitter, gif-small, or a preset defined in presets.toml")]
+    UnknownPreset(String),
+    #[error("Encoder {encoder} does not support {option} {value}")]
+    UnsupportedEncoderOption {
+        encoder: Encoder,
+        option: &'static str,
+        value: String,
+    },
+    #[error("ffmpeg exited with {status}: {}\nCommand: {command}", stderr.trim())]
+    FfmpegFailed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+    #[error("Streaming frames to the encoder is only supported for still image inputs")]
+    StreamingVideoInput,
+    #[error("Frame {0} listed in the extraction manifest is missing")]
+    MissingFrame(PathBuf),
+    #[error("No extracted frames found in {0}")]
+    NoFrames(PathBuf),
+    #[error("Choosing the poster by yaw requires the manifest that extract_frames writes to {0}")]
+    PosterYawWithoutManifest(PathBuf),
+    #[error("Poster frame {index} is out of range, only {frame_count} frames were extracted")]
+    PosterFrameOutOfRange { index: usize, frame_count: usize },
+    #[error("A {aspect} window does not fit in a {width}x{height} panorama, which must be wider than it")]
+    PanoramaTooNarrow {
+        width: u32,
+        height: u32,
+        aspect: Aspect,
+    },
+    #[error("Could not download ffmpeg: {0}")]
+    FfmpegDownload(String),
+    #[error("The {0} backend does not support {1}, use the ffmpeg backend instead")]
+    UnsupportedByBackend(Backend, String),
+    #[error("Cannot blend {frames} frames into the start of a loop of only {frame_count} frames")]
+    LoopBlendTooLong { frames: usize, frame_count: usize },
+    #[error("{0} is not a video of at least 3 frames")]
+    NotAVideo(PathBuf),
+    #[error("Failed after {} attempts:{}", .0.len(), retry::attempts_message(.0))]
+    AttemptsFailed(Vec<DragonflyError>),
+    #[error("Extracting needs about {required} of space in {path:?}, but only {available} is free. Set no_disk_check to extract anyway")]
+    InsufficientDiskSpace {
+        path: PathBuf,
+        required: String,
+        available: String,
+    },
+    #[error("Cancelled")]
+    Cancelled,
+    #[error("No output path was given")]
+    MissingOutputPath,
     #[error("Unknown error")]
     Unknown,
 }
 
 pub type Result<T> = std::result::Result<T, DragonflyError>;
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[cfg_attr(feature = "clap", derive(clap::Args))]
 pub struct ExtractFramesDescriptor {
     #[cfg_attr(
         feature = "clap",
-        arg(help = "Number of frames to extract", long, default_value = "360")
+        arg(
+            help = "Number of frames to extract, ignored for video inputs which extract one frame per source frame",
+            long,
+            default_value = "360"
+        )
     )]
     pub frame_count: usize,
     #[cfg_attr(
@@ -72,11 +269,50 @@ pub struct ExtractFramesDescriptor {
         )
     )]
     pub iv_fov: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Assume a full 360x180 input instead of reading the field of view of a partial panorama from its GPano metadata",
+            long
+        )
+    )]
+    pub ignore_gpano: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Level the horizon of a tilted panorama, using the camera pitch and roll from its GPano metadata unless --horizon-pitch or --horizon-roll are given",
+            long
+        )
+    )]
+    pub level_horizon: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Pitch in degrees the camera was tilted by, which --level-horizon corrects",
+            long,
+            requires = "level_horizon",
+            allow_negative_numbers = true
+        )
+    )]
+    pub horizon_pitch: Option<f32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Roll in degrees the camera was tilted by, which --level-horizon corrects",
+            long,
+            requires = "level_horizon",
+            allow_negative_numbers = true
+        )
+    )]
+    pub horizon_roll: Option<f32>,
+    #[cfg_attr(feature = "clap", command(flatten))]
+    pub nadir: NadirPatchDescriptor,
     #[cfg_attr(
         feature = "clap",
         arg(
             help = "The horizontal field of view in degrees of the extracted output images",
             long,
+            alias = "h-fov-start",
             default_value = "60.0"
         )
     )]
@@ -86,130 +322,2537 @@ pub struct ExtractFramesDescriptor {
         arg(
             help = "The vertical field of view in degrees of the extracted output images",
             long,
+            alias = "v-fov-start",
             default_value = "45.0"
         )
     )]
     pub v_fov: f32,
     #[cfg_attr(
         feature = "clap",
-        arg(help = "Number of CPU threads to use", long, default_value = "4")
+        arg(
+            help = "The horizontal field of view in degrees at the end of the rotation, defaults to --h-fov",
+            long
+        )
+    )]
+    pub h_fov_end: Option<f32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The vertical field of view in degrees at the end of the rotation, defaults to --v-fov",
+            long
+        )
+    )]
+    pub v_fov_end: Option<f32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The yaw in degrees of the camera at the start of the rotation",
+            long,
+            default_value = "-180.0",
+            allow_negative_numbers = true
+        )
+    )]
+    pub yaw_start: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The yaw in degrees of the camera at the end of the rotation",
+            long,
+            default_value = "180.0",
+            allow_negative_numbers = true
+        )
+    )]
+    pub yaw_end: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Degrees added to every yaw, so the video starts facing a chosen direction",
+            long,
+            default_value = "0.0",
+            allow_negative_numbers = true
+        )
+    )]
+    pub yaw_offset: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Number of times to sweep from the start yaw to the end yaw",
+            long,
+            default_value = "1.0"
+        )
+    )]
+    pub revolutions: f32,
+    #[cfg_attr(feature = "clap", arg(help = "Direction of the rotation", long, default_value_t = Direction::Clockwise))]
+    pub direction: Direction,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The pitch in degrees of the camera at the start of the rotation",
+            long,
+            default_value = "0.0",
+            allow_negative_numbers = true
+        )
+    )]
+    pub pitch_start: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The pitch in degrees of the camera at the end of the rotation",
+            long,
+            default_value = "0.0",
+            allow_negative_numbers = true
+        )
+    )]
+    pub pitch_end: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Fixed pitch in degrees of the camera for the whole rotation, overrides --pitch-start and --pitch-end",
+            long,
+            allow_negative_numbers = true
+        )
+    )]
+    pub pitch: Option<f32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Fixed roll in degrees of the camera for the whole rotation",
+            long,
+            default_value = "0.0",
+            allow_negative_numbers = true
+        )
+    )]
+    pub roll: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Amplitude in degrees of a gentle pitch oscillation, like a floating drone",
+            long,
+            default_value = "0.0"
+        )
+    )]
+    pub bob_pitch: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Amplitude in degrees of a roll oscillation, a quarter cycle behind the pitch oscillation",
+            long,
+            default_value = "0.0"
+        )
+    )]
+    pub bob_roll: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Number of pitch and roll oscillations per revolution, whole numbers keep looping videos seamless",
+            long,
+            default_value = "2.0"
+        )
+    )]
+    pub bob_cycles: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Path to a JSON, TOML, or CSV camera path file, overrides the yaw rotation and pitch sweep",
+            long,
+            value_parser = parse_camera_path
+        )
+    )]
+    pub camera_path: Option<CameraPath>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Easing applied to the rotation speed: linear, ease-in, ease-out, ease-in-out, or cubic-bezier(x1,y1,x2,y2)",
+            long,
+            default_value_t = Easing::Linear
+        )
+    )]
+    pub easing: Easing,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Relative rotation speed as an expression of the normalized time t, such as 0.2+t^2 for a slow reveal that speeds into a fast spin, replaces --easing",
+            long,
+            allow_hyphen_values = true
+        )
+    )]
+    pub speed: Option<SpeedRamp>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Number of CPU threads to use, 0 uses every available core",
+            long,
+            default_value = "0"
+        )
     )]
     pub j: usize,
     #[cfg_attr(feature = "clap", arg(help = "Interpolation method to use", long, default_value_t = Interpolation::Linear))]
     pub interpolation: Interpolation,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[cfg_attr(feature = "clap", derive(clap::Args))]
-pub struct EncodeFramesDescriptor {
     #[cfg_attr(
         feature = "clap",
         arg(
-            help = "The desired length in seconds of the video",
+            help = "Render frames at this multiple of the output resolution to reduce aliasing, downscaled again when encoding",
             long,
-            default_value = "10"
+            default_value = "1",
+            value_parser = clap::value_parser!(u32).range(1..=4)
         )
     )]
-    pub length: f32,
+    pub supersample: u32,
     #[cfg_attr(
         feature = "clap",
-        arg(help = "The FPS of the output video", long, default_value = "60")
+        arg(
+            help = "Width of the extracted frames, preserving the aspect ratio unless the height is also set",
+            long
+        )
     )]
-    pub fps: f32,
+    pub width: Option<u32>,
     #[cfg_attr(
         feature = "clap",
-        arg(help = "The scale of the output video", long, default_value = "1.0")
+        arg(
+            help = "Height of the extracted frames, preserving the aspect ratio unless the width is also set",
+            long
+        )
     )]
-    pub scale: String,
+    pub height: Option<u32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Limit the longest side of the extracted frames, preserving the aspect ratio",
+            long
+        )
+    )]
+    pub max_dimension: Option<u32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Aspect ratio of the extracted frames, deriving the vertical field of view from the horizontal one",
+            long
+        )
+    )]
+    pub aspect: Option<Aspect>,
+    #[cfg_attr(feature = "clap", arg(help = "Projection of the extracted output images", long, default_value_t = OutputProjection::Flat))]
+    pub projection: OutputProjection,
+    #[cfg_attr(feature = "clap", arg(help = "Stereo layout of the input image", long, default_value_t = StereoLayout::Mono))]
+    pub stereo: StereoLayout,
+    #[cfg_attr(feature = "clap", arg(help = "Eye to extract from a stereo input, both produces a stereo output in the same layout", long, default_value_t = Eye::Left))]
+    pub eye: Eye,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Spawn one ffmpeg process per frame instead of rendering all frames in a single pass",
+            long
+        )
+    )]
+    pub per_frame: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Render with ffmpeg's v360 filter, in-process with the native backend, or with ffmpeg's remap filter and pixel maps computed once per camera orientation. The native and remap backends support flat, stereographic, and equirectangular frames of mono still images",
+            long,
+            default_value = "ffmpeg"
+        )
+    )]
+    pub backend: Backend,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Image format of the extracted frames, where png, tiff, and webp are lossless but larger and slower to write than jpeg",
+            long,
+            default_value = "jpeg"
+        )
+    )]
+    pub intermediate_format: IntermediateFormat,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Quality of jpeg frames on ffmpeg's scale from 1, the best, to 31, the worst, defaults to ffmpeg's choice",
+            long,
+            value_parser = clap::value_parser!(u8).range(1..=31)
+        )
+    )]
+    pub jpeg_quality: Option<u8>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Keep the transparency of the input, and leave what a partial panorama does not cover transparent, for vp9 or prores 4444 output",
+            long
+        )
+    )]
+    pub alpha: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Extra ffmpeg filters appended after the reprojection, such as eq=saturation=1.2",
+            id = "extract_vf",
+            long = "extract-vf"
+        )
+    )]
+    pub extra_vf: Option<String>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Extra ffmpeg argument placed before the input when extracting. May be repeated",
+            id = "extract_input_arg",
+            long = "extract-input-arg",
+            allow_hyphen_values = true
+        )
+    )]
+    pub extra_input_args: Vec<String>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Extra ffmpeg argument placed before the output when extracting. May be repeated",
+            id = "extract_output_arg",
+            long = "extract-output-arg",
+            allow_hyphen_values = true
+        )
+    )]
+    pub extra_output_args: Vec<String>,
+    /// Text drawn on every extracted frame
+    #[cfg_attr(feature = "clap", arg(skip))]
+    pub text_overlay: Option<TextOverlay>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Stamp each extracted frame with its index, yaw, pitch, roll, and field of view, to diagnose camera paths and seams",
+            long
+        )
+    )]
+    pub debug_overlay: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Extract even when the frames are estimated to need more space than the disk has free",
+            long
+        )
+    )]
+    pub no_disk_check: bool,
 }
 
-#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
-#[derive(Clone, Debug, Display, EnumString)]
-#[strum(serialize_all = "lowercase")]
-pub enum Interpolation {
-    Near,
-    Linear,
-    Cubic,
-    Lanczos,
-    Spline16,
-    Lagrange9,
-    Gaussian,
-    Mitchell,
+impl Default for ExtractFramesDescriptor {
+    fn default() -> Self {
+        Self {
+            frame_count: 360,
+            ih_fov: 360.0,
+            iv_fov: 180.0,
+            ignore_gpano: false,
+            level_horizon: false,
+            horizon_pitch: None,
+            horizon_roll: None,
+            nadir: NadirPatchDescriptor::default(),
+            h_fov: 60.0,
+            v_fov: 45.0,
+            h_fov_end: None,
+            v_fov_end: None,
+            yaw_start: -180.0,
+            yaw_end: 180.0,
+            yaw_offset: 0.0,
+            revolutions: 1.0,
+            direction: Direction::Clockwise,
+            pitch_start: 0.0,
+            pitch_end: 0.0,
+            pitch: None,
+            roll: 0.0,
+            bob_pitch: 0.0,
+            bob_roll: 0.0,
+            bob_cycles: 2.0,
+            camera_path: None,
+            easing: Easing::Linear,
+            speed: None,
+            j: 0,
+            interpolation: Interpolation::Linear,
+            projection: OutputProjection::Flat,
+            stereo: StereoLayout::Mono,
+            eye: Eye::Left,
+            per_frame: false,
+            backend: Backend::Ffmpeg,
+            intermediate_format: IntermediateFormat::Jpeg,
+            jpeg_quality: None,
+            alpha: false,
+            supersample: 1,
+            width: None,
+            height: None,
+            max_dimension: None,
+            aspect: None,
+            extra_vf: None,
+            extra_input_args: vec![],
+            extra_output_args: vec![],
+            text_overlay: None,
+            debug_overlay: false,
+            no_disk_check: false,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct FfprobeOutput {
-    streams: Vec<FfprobeStreamOutput>,
-}
+impl ExtractFramesDescriptor {
+    /// Returns the number of CPU threads to use, detecting the available parallelism when `j` is 0
+    pub fn thread_count(&self) -> usize {
+        match self.j {
+            0 => std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            j => j,
+        }
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct FfprobeStreamOutput {
-    width: i32,
-    height: i32,
-}
+    /// Returns a descriptor for the classic rotating tiny planet video
+    pub fn little_planet() -> Self {
+        Self::default().with_little_planet()
+    }
 
-#[allow(dead_code)]
-fn ffprobe_info(input_path: &Path) -> Result<FfprobeOutput> {
-    let input_path_str = input_path
-        .to_str()
-        .ok_or_else(|| DragonflyError::InvalidPathString(input_path.to_path_buf()))?;
-    // Fetch the input pixel resolution
-    let ffprobe_child = Command::new(FFPROBE_BINARY_PATH.as_os_str())
-        .args([
-            "-v",
-            "error",
-            "-select_streams",
-            "v:0",
-            "-show_entries",
-            "stream=width,height",
-            "-of",
-            "json=compact=1",
-            input_path_str,
-        ])
-        .stdout(Stdio::piped())
-        .spawn()?;
-    let ffprobe_output = ffprobe_child.wait_with_output()?;
-    let ffprobe_output = serde_json::from_slice::<FfprobeOutput>(&ffprobe_output.stdout)?;
-    Ok(ffprobe_output)
-}
+    /// Points a stereographic camera straight down so the yaw rotation spins a tiny planet
+    ///
+    /// Overrides the projection, pitch, and field of view while keeping every other setting.
+    pub fn with_little_planet(self) -> Self {
+        Self {
+            projection: OutputProjection::Stereographic,
+            pitch_start: -90.0,
+            pitch_end: -90.0,
+            pitch: None,
+            roll: 0.0,
+            h_fov: 270.0,
+            v_fov: 270.0,
+            h_fov_end: None,
+            v_fov_end: None,
+            ..self
+        }
+    }
 
-pub fn extract_frames(
-    input_path: &Path,
-    extraction_path: &Path,
-    descriptor: &ExtractFramesDescriptor,
-    progress_callback: Option<impl Fn(usize, usize)>,
-) -> Result<()> {
-    let input_path_str = input_path
-        .to_str()
-        .ok_or_else(|| DragonflyError::InvalidPathString(input_path.to_path_buf()))?;
-    let ih_fov = descriptor.ih_fov;
-    let iv_fov = descriptor.iv_fov;
-    let oh_fov = descriptor.h_fov;
-    let ov_fov = descriptor.v_fov;
-    /*
-    let ffprobe_output = ffprobe_info(input_path)?;
-    let ffprobe_stream_output = ffprobe_output
-        .streams
-        .first()
-        .ok_or(DragonflyError::SourceContainsNoStream)?;
-    let h_ratio = oh_fov / ih_fov;
-    let v_ratio = ov_fov / iv_fov;
-    let output_width = (ffprobe_stream_output.width as f32 * h_ratio) as i32;
-    let output_height = (ffprobe_stream_output.height as f32 * v_ratio) as i32;
-    */
-
-    let mut tasks = Vec::with_capacity(descriptor.j);
-    // Extract frames
-    for frame in 0..descriptor.frame_count {
-        //let yaw = -180.0 + 360.0 * (frame as f32 / (descriptor.frame_count - 1) as f32);
-        // Want to exclude +180.0 from the yaw calculation to avoid having duplicate starting and ending frames
-        let yaw = -180.0 + 360.0 * (frame as f32 / descriptor.frame_count as f32);
-        let pitch = 0.0;
-        let roll = 0.0;
-        let output_path = extraction_path.join(format!("frame_{:08}.jpg", frame));
+    /// Returns a descriptor for a 360 video of the whole panorama slowly spinning
+    pub fn rotating_panorama() -> Self {
+        Self::default().with_rotating_panorama()
+    }
+
+    /// Keeps the equirectangular projection and levels the camera, so the yaw rotation spins the whole panorama
+    ///
+    /// Overrides the projection, pitch, and field of view while keeping every other setting.
+    pub fn with_rotating_panorama(self) -> Self {
+        Self {
+            projection: OutputProjection::Equirectangular,
+            pitch_start: 0.0,
+            pitch_end: 0.0,
+            pitch: None,
+            roll: 0.0,
+            h_fov: 360.0,
+            v_fov: 180.0,
+            h_fov_end: None,
+            v_fov_end: None,
+            ..self
+        }
+    }
+
+    /// Takes the input field of view from the GPano metadata of a partial panorama, and the camera tilt
+    /// when leveling the horizon
+    ///
+    /// An input field of view other than the full 360x180 or a tilt given explicitly is kept.
+    pub fn with_gpano(mut self, gpano: &GPano) -> Self {
+        if self.level_horizon && !self.ignore_gpano {
+            self.horizon_pitch = self.horizon_pitch.or(gpano.pose_pitch_degrees);
+            self.horizon_roll = self.horizon_roll.or(gpano.pose_roll_degrees);
+        }
+        let is_full = self.ih_fov == 360.0 && self.iv_fov == 180.0;
+        match gpano.input_fov() {
+            Some((ih_fov, iv_fov)) if is_full && !self.ignore_gpano => {
+                debug!("Input field of view {ih_fov}x{iv_fov} from GPano metadata");
+                if !gpano.is_centered() {
+                    warn!("The panorama is not centered in its full GPano extent, so the yaw and pitch are offset");
+                }
+                Self {
+                    ih_fov,
+                    iv_fov,
+                    ..self
+                }
+            }
+            _ => self,
+        }
+    }
+
+    /// Checks the frame count and every field of view, including those of a camera path
+    pub fn validate(&self) -> Result<()> {
+        if self.frame_count == 0 {
+            return Err(DragonflyError::InvalidFrameCount);
+        }
+        let check = |name, value: f32, max| {
+            if value > 0.0 && value <= max {
+                Ok(())
+            } else {
+                Err(DragonflyError::InvalidFieldOfView { name, value, max })
+            }
+        };
+        check("ih_fov", self.ih_fov, 360.0)?;
+        check("iv_fov", self.iv_fov, 180.0)?;
+        if self.nadir.is_enabled() {
+            check("nadir_size", self.nadir.nadir_size, 180.0)?;
+            if self.eye == Eye::Both && self.stereo != StereoLayout::Mono {
+                return Err(DragonflyError::StereoNadirPatch);
+            }
+        }
+        // The pannini projection has a fixed field of view
+        if self.projection == OutputProjection::Pannini {
+            return Ok(());
+        }
+        for frame in 0..self.frame_count {
+            let pose = self.pose(frame);
+            check("h_fov", pose.h_fov, 360.0)?;
+            check("v_fov", pose.v_fov, 360.0)?;
+            if self.projection == OutputProjection::Flat && pose.h_fov.max(pose.v_fov) >= 180.0 {
+                return Err(DragonflyError::FlatFieldOfViewTooWide(
+                    pose.h_fov.max(pose.v_fov),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Warns when the input's aspect ratio does not match its field of view, which warps the extracted frames
+    fn check_input_aspect(&self, input_width: u32, input_height: u32) {
+        let (eye_width, eye_height) = self.eye_size(input_width, input_height);
+        let aspect = eye_width / eye_height.max(1.0);
+        let expected = self.ih_fov / self.iv_fov;
+        if (aspect / expected - 1.0).abs() > 0.02 {
+            warn!(
+                "The input is {input_width}x{input_height}, an aspect ratio of {aspect:.3}, but a {}x{} degree equirectangular input has an aspect ratio of {expected:.3}. Set --ih-fov and --iv-fov to the field of view the input covers",
+                self.ih_fov, self.iv_fov
+            );
+        }
+    }
+
+    /// Returns the camera pose used to render the given frame
+    pub fn pose(&self, frame: usize) -> CameraPose {
+        let pose = if let Some(camera_path) = &self.camera_path {
+            let t = camera_path.frame_time(frame, self.frame_count);
+            camera_path.sample(t, self.h_fov, self.v_fov)
+        } else {
+            let t = match &self.speed {
+                Some(speed) => speed.apply(self.progress(frame)),
+                None => self.easing.apply(self.progress(frame)),
+            };
+            // The oscillation follows the yaw, repeating the same number of times every revolution
+            let phase =
+                std::f32::consts::TAU * self.bob_cycles * t * self.yaw_sweep().abs() / 360.0;
+            CameraPose {
+                yaw: self.yaw_start + self.yaw_sweep() * t,
+                // Sweep the pitch over the same normalized time as the yaw
+                pitch: self
+                    .pitch
+                    .unwrap_or(self.pitch_start + (self.pitch_end - self.pitch_start) * t)
+                    + self.bob_pitch * phase.sin(),
+                roll: self.roll + self.bob_roll * (phase - std::f32::consts::FRAC_PI_2).sin(),
+                h_fov: self.h_fov + (self.h_fov_end.unwrap_or(self.h_fov) - self.h_fov) * t,
+                v_fov: self.v_fov + (self.v_fov_end.unwrap_or(self.v_fov) - self.v_fov) * t,
+            }
+        };
+        let pose = CameraPose {
+            yaw: pose.yaw + self.yaw_offset,
+            ..pose
+        };
+        match (self.projection, self.aspect) {
+            // Recent v360 builds crop an equirectangular output to the field of view, so always render the full sphere
+            (OutputProjection::Equirectangular, _) => CameraPose {
+                h_fov: 360.0,
+                v_fov: 180.0,
+                ..pose
+            },
+            (_, Some(aspect)) => CameraPose {
+                v_fov: aspect.v_fov(pose.h_fov, self.projection),
+                ..pose
+            },
+            (_, None) => pose,
+        }
+    }
+
+    /// Returns the signed number of degrees the yaw travels over the whole extraction
+    pub fn yaw_sweep(&self) -> f32 {
+        let sweep = (self.yaw_end - self.yaw_start) * self.revolutions;
+        match self.direction {
+            Direction::Clockwise => sweep,
+            Direction::CounterClockwise => -sweep,
+        }
+    }
+
+    /// Returns true when the yaw sweep ends facing the same direction it started
+    pub fn is_loop(&self) -> bool {
+        let revolutions = self.yaw_sweep() / 360.0;
+        (revolutions - revolutions.round()).abs() < 1e-4
+    }
+
+    /// Describes each setting that keeps the extracted frames from looping seamlessly, if any
+    pub fn loop_issues(&self) -> Vec<String> {
+        let mut issues = vec![];
+        if let Some(camera_path) = &self.camera_path {
+            if !camera_path.looping {
+                issues.push("the camera path is not marked as a loop".to_string());
+            }
+            return issues;
+        }
+        if !self.is_loop() {
+            issues.push(format!(
+                "the yaw sweeps {} degrees, which does not end facing the starting direction",
+                self.yaw_sweep()
+            ));
+        }
+        if self.pitch.is_none() && self.pitch_start != self.pitch_end {
+            issues.push("the pitch sweeps and jumps back to its start at the seam".to_string());
+        }
+        if self.h_fov_end.is_some_and(|end| end != self.h_fov)
+            || self.v_fov_end.is_some_and(|end| end != self.v_fov)
+        {
+            issues.push(
+                "the field of view zooms and jumps back to its start at the seam".to_string(),
+            );
+        }
+        if (self.bob_pitch != 0.0 || self.bob_roll != 0.0) && self.bob_cycles.fract() != 0.0 {
+            issues.push(format!(
+                "{} bob cycles per revolution is not a whole number",
+                self.bob_cycles
+            ));
+        }
+        // Neighboring frames that share little of their view judder, which is most visible at the seam
+        let step = self.yaw_sweep().abs() / self.frame_count.max(1) as f32;
+        let h_fov = self.pose(0).h_fov;
+        if step > h_fov / 8.0 {
+            issues.push(format!(
+                "each of the {} frames turns {step:.1} degrees, too far for a {h_fov} degree field of view to move smoothly",
+                self.frame_count
+            ));
+        }
+        issues
+    }
+
+    /// Returns the normalized progress of the given frame through the rotation
+    fn progress(&self, frame: usize) -> f32 {
+        if self.is_loop() {
+            // Want to exclude the end of a full revolution to avoid having duplicate starting and ending frames
+            frame as f32 / self.frame_count as f32
+        } else if self.frame_count > 1 {
+            // A partial arc should land exactly on its final yaw
+            frame as f32 / (self.frame_count - 1) as f32
+        } else {
+            0.0
+        }
+    }
+
+    /// Builds the v360 filter that renders the given pose
+    fn v360_filter_string(
+        &self,
+        pose: &CameraPose,
+        output_width: u32,
+        output_height: u32,
+    ) -> Result<String> {
+        let fov_options = match self.projection {
+            // The pannini projection reuses h_fov as its compression parameter and has a fixed field of view
+            OutputProjection::Pannini => "h_fov=1".to_string(),
+            _ => format!("h_fov={}:v_fov={}", pose.h_fov, pose.v_fov),
+        };
+        // Select a single eye of a stereo input before reprojecting it, or keep both eyes in the output
+        let (crop_filter, stereo_options) = match (self.stereo, self.eye) {
+            (StereoLayout::Mono, _) => ("", String::new()),
+            (StereoLayout::TopBottom, Eye::Left) => ("crop=iw:ih/2:0:0,", String::new()),
+            (StereoLayout::TopBottom, Eye::Right) => ("crop=iw:ih/2:0:ih/2,", String::new()),
+            (StereoLayout::SideBySide, Eye::Left) => ("crop=iw/2:ih:0:0,", String::new()),
+            (StereoLayout::SideBySide, Eye::Right) => ("crop=iw/2:ih:iw/2:0,", String::new()),
+            (layout, Eye::Both) => (
+                "",
+                format!(":in_stereo={0}:out_stereo={0}", layout.v360_name()),
+            ),
+        };
+        // Leveling is a separate equirectangular to equirectangular v360, so the runtime commands that pose
+        // the camera only reach the instance named camera
+        let level_filter = match (self.horizon_pitch, self.horizon_roll) {
+            (None, None) => String::new(),
+            _ if !self.level_horizon => String::new(),
+            (pitch, roll) => format!(
+                "v360@level=e:e:pitch={}:roll={}:rorder=rpy:ih_fov={2}:iv_fov={3}:h_fov={2}:v_fov={3}:interp={4}{5},",
+                wrap_degrees(-pitch.unwrap_or(0.0)),
+                wrap_degrees(-roll.unwrap_or(0.0)),
+                self.ih_fov,
+                self.iv_fov,
+                self.interpolation,
+                stereo_options,
+            ),
+        };
+        // v360 keeps the pixel format of its input, so it only fills an alpha plane when given one
+        let (alpha_filter, alpha_options) = if self.alpha {
+            ("format=gbrap16le,", ":alpha_mask=1")
+        } else {
+            ("", "")
+        };
+        // The tripod sits at the nadir of the camera, so it is patched before leveling
+        Ok(format!(
+            "{}{}{}{}v360@camera=e:{}:yaw={}:pitch={}:roll={}:ih_fov={}:iv_fov={}:{}:w={}:h={}:interp={}{}{}",
+            crop_filter,
+            alpha_filter,
+            self.nadir.filter_string()?,
+            level_filter,
+            self.projection.v360_name(),
+            wrap_degrees(pose.yaw),
+            wrap_degrees(pose.pitch),
+            wrap_degrees(pose.roll),
+            self.ih_fov,
+            self.iv_fov,
+            fov_options,
+            output_width,
+            output_height,
+            self.interpolation,
+            stereo_options,
+            alpha_options,
+        ))
+    }
+
+    /// Returns the filters that render a frame of the input named `input_filename`: the v360
+    /// reprojection followed by any text overlay
+    fn frame_filter_string(
+        &self,
+        frame: usize,
+        input_filename: &str,
+        output_width: u32,
+        output_height: u32,
+    ) -> Result<String> {
+        let pose = self.pose(frame);
+        let filter_string = self.v360_filter_string(&pose, output_width, output_height)?;
+        self.with_overlay_filters(filter_string, frame, input_filename, &pose)
+    }
+
+    /// Appends the filters that follow the reprojection of a frame to `filter_string`
+    fn with_overlay_filters(
+        &self,
+        mut filter_string: String,
+        frame: usize,
+        input_filename: &str,
+        pose: &CameraPose,
+    ) -> Result<String> {
+        if let Some(extra_vf) = &self.extra_vf {
+            filter_string = format!("{filter_string},{extra_vf}");
+        }
+        for (instance, text_overlay, text) in self.text_overlays(frame, input_filename, pose) {
+            filter_string = format!(
+                "{filter_string},{}",
+                text_overlay.drawtext_filter_string(&text, instance)?
+            );
+        }
+        Ok(filter_string)
+    }
+
+    /// Returns the drawtext instance, overlay, and rendered text of each text drawn on a frame: the text
+    /// overlay followed by the debug overlay
+    fn text_overlays(
+        &self,
+        frame: usize,
+        input_filename: &str,
+        pose: &CameraPose,
+    ) -> Vec<(&'static str, TextOverlay, String)> {
+        let text_overlay = self.text_overlay.as_ref().map(|text_overlay| {
+            let text = text_overlay.render(input_filename, frame, pose);
+            ("text", text_overlay.clone(), text)
+        });
+        let debug_overlay = self.debug_overlay.then(|| {
+            (
+                "debug",
+                TextOverlay::debug(),
+                TextOverlay::debug_text(frame, pose),
+            )
+        });
+        text_overlay
+            .into_iter()
+            .chain(debug_overlay)
+            .map(|(instance, text_overlay, text)| {
+                // Supersampled frames are downscaled when encoding, so scale the text up to match
+                let text_overlay = TextOverlay {
+                    font_size: text_overlay.font_size * self.supersample.max(1),
+                    margin: text_overlay.margin * self.supersample.max(1),
+                    ..text_overlay
+                };
+                (instance, text_overlay, text)
+            })
+            .collect()
+    }
+
+    /// Applies the requested width, height, and maximum dimension to the resolution derived from the input
+    fn fit_resolution(&self, width: f32, height: f32) -> (f32, f32) {
+        let (height, aspect) = match self.aspect {
+            Some(aspect) => (width / aspect.ratio(), aspect.ratio()),
+            None => (height, width / height),
+        };
+        let (width, height) = match (self.width, self.height) {
+            (Some(w), Some(h)) => (w as f32, h as f32),
+            (Some(w), None) => (w as f32, w as f32 / aspect),
+            (None, Some(h)) => (h as f32 * aspect, h as f32),
+            (None, None) => (width, height),
+        };
+        match self.max_dimension {
+            Some(max) if width.max(height) > max as f32 => {
+                let scale = max as f32 / width.max(height);
+                (width * scale, height * scale)
+            }
+            _ => (width, height),
+        }
+    }
+
+    /// Returns the size of a single eye of the input
+    fn eye_size(&self, input_width: u32, input_height: u32) -> (f32, f32) {
+        // Each eye of a stereo input only covers half of the image
+        match self.stereo {
+            StereoLayout::Mono => (input_width as f32, input_height as f32),
+            StereoLayout::TopBottom => (input_width as f32, input_height as f32 / 2.0),
+            StereoLayout::SideBySide => (input_width as f32 / 2.0, input_height as f32),
+        }
+    }
+
+    /// Returns the pixel resolution of the extracted frames for an input of the given size
+    ///
+    /// The resolution is derived from the largest field of view used by any frame so that all frames share the same size.
+    /// Any requested width, height, or maximum dimension applies to each eye of a stereo output.
+    pub fn output_resolution(&self, input_width: u32, input_height: u32) -> (u32, u32) {
+        let (max_h_fov, max_v_fov) = match self.projection {
+            // The field of view v360 renders with a pannini compression parameter of 1
+            OutputProjection::Pannini => (106.26, 77.32),
+            OutputProjection::Equirectangular => (360.0, 180.0),
+            _ => (0..self.frame_count)
+                .map(|frame| self.pose(frame))
+                .fold((0.0_f32, 0.0_f32), |(h, v), pose| {
+                    (h.max(pose.h_fov), v.max(pose.v_fov))
+                }),
+        };
+        let (eye_width, eye_height) = self.eye_size(input_width, input_height);
+        let h_ratio = max_h_fov / self.ih_fov;
+        let v_ratio = max_v_fov / self.iv_fov;
+        let (width, height) = self.fit_resolution(eye_width * h_ratio, eye_height * v_ratio);
+        let supersample = self.supersample.max(1) as f32;
+        let (width, height) = (width * supersample, height * supersample);
+        // A stereo output stacks both eyes in the same layout as the input
+        match (self.stereo, self.eye) {
+            (StereoLayout::TopBottom, Eye::Both) => {
+                (even_dimension(width), 2 * even_dimension(height))
+            }
+            (StereoLayout::SideBySide, Eye::Both) => {
+                (2 * even_dimension(width), even_dimension(height))
+            }
+            _ => (even_dimension(width), even_dimension(height)),
+        }
+    }
+}
+
+/// Parses a `key=value` metadata pair for use as a command-line argument value
+pub fn parse_metadata(metadata: &str) -> Result<(String, String)> {
+    match metadata.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(DragonflyError::InvalidMetadata(metadata.to_string())),
+    }
+}
+
+/// Parses a `key=value` muxer option for use as a command-line argument value
+pub fn parse_muxer_option(option: &str) -> Result<(String, String)> {
+    match option.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(DragonflyError::InvalidMuxerOption(option.to_string())),
+    }
+}
+
+/// Rounds a pixel dimension to the nearest even number, as required by most yuv420p encoders
+fn even_dimension(dimension: f32) -> u32 {
+    ((dimension / 2.0).round() as u32 * 2).max(2)
+}
+
+/// The orientation and field of view in degrees of the camera for a single frame
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub roll: f32,
+    pub h_fov: f32,
+    pub v_fov: f32,
+}
+
+impl CameraPose {
+    /// Linearly interpolates between two poses
+    pub fn lerp(&self, other: &CameraPose, t: f32) -> CameraPose {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        CameraPose {
+            yaw: lerp(self.yaw, other.yaw),
+            pitch: lerp(self.pitch, other.pitch),
+            roll: lerp(self.roll, other.roll),
+            h_fov: lerp(self.h_fov, other.h_fov),
+            v_fov: lerp(self.v_fov, other.v_fov),
+        }
+    }
+}
+
+/// Wraps an angle in degrees to the [-180, 180) range accepted by the v360 filter
+pub(crate) fn wrap_degrees(degrees: f32) -> f32 {
+    (degrees + 180.0).rem_euclid(360.0) - 180.0
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct EncodeFramesDescriptor {
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The desired length in seconds of the video",
+            long,
+            default_value = "10"
+        )
+    )]
+    pub length: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "The FPS of the output video", long, default_value = "60")
+    )]
+    pub fps: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "The scale of the output video", long, default_value = "1.0")
+    )]
+    pub scale: String,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Factor the frames were supersampled by when extracting, which the encode scales back down",
+            long,
+            default_value = "1",
+            value_parser = clap::value_parser!(u32).range(1..=4)
+        )
+    )]
+    pub downsample: u32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Append the reversed frames so the video plays forward then backward",
+            long
+        )
+    )]
+    pub boomerang: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Number of frames at the end dissolved into the first frames, hiding any pop where the video loops",
+            long,
+            conflicts_with = "boomerang",
+            value_parser = clap::value_parser!(u16).range(1..)
+        )
+    )]
+    pub loop_blend: Option<u16>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Synthesize in-between frames up to the output FPS, so fewer frames need to be extracted",
+            long
+        )
+    )]
+    pub frame_interpolation: Option<FrameInterpolation>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Number of neighboring frames blended together to simulate motion blur",
+            long,
+            value_parser = clap::value_parser!(u16).range(2..=128)
+        )
+    )]
+    pub motion_blur: Option<u16>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Number of neighboring frames whose brightness is averaged to remove flicker, such as between the stills of a hyperlapse",
+            long,
+            value_parser = clap::value_parser!(u16).range(2..=129)
+        )
+    )]
+    pub deflicker: Option<u16>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Path to a 3D LUT (.cube, .3dl, .dat, .m3d, or .csp) used to color grade the video",
+            long
+        )
+    )]
+    pub lut: Option<PathBuf>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Reduce noise, such as the grain of low light panoramas, which otherwise costs bitrate at high CRF",
+            long
+        )
+    )]
+    pub denoise: Option<Denoise>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Filter used by --denoise, where nlmeans keeps more detail but is much slower",
+            long,
+            default_value = "hqdn3d"
+        )
+    )]
+    pub denoise_filter: DenoiseFilter,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Smooth the banding of gradients such as clear skies", long)
+    )]
+    pub deband: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Sharpen the frames by this amount, from 0 to 1.5, or blur them with a negative amount",
+            long,
+            allow_negative_numbers = true
+        )
+    )]
+    pub sharpen: Option<f32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Add film grain of this strength, from 1 to 50, so denoised or synthetic panoramas do not look plasticky. AV1 encoders resynthesize it at playback rather than spend bitrate on it",
+            long,
+            value_parser = clap::value_parser!(u8).range(1..=50)
+        )
+    )]
+    pub grain: Option<u8>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Extra ffmpeg filters appended after scaling and before any overlays, such as unsharp",
+            id = "encode_vf",
+            long = "encode-vf"
+        )
+    )]
+    pub extra_vf: Option<String>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Extra ffmpeg argument placed before the frames input when encoding. May be repeated",
+            id = "encode_input_arg",
+            long = "encode-input-arg",
+            allow_hyphen_values = true
+        )
+    )]
+    pub extra_input_args: Vec<String>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Extra ffmpeg argument placed before the output when encoding. May be repeated",
+            id = "encode_output_arg",
+            long = "encode-output-arg",
+            allow_hyphen_values = true
+        )
+    )]
+    pub extra_output_args: Vec<String>,
+    #[cfg_attr(feature = "clap", command(flatten))]
+    pub watermark: WatermarkDescriptor,
+    /// Text drawn on every encoded frame
+    #[cfg_attr(feature = "clap", arg(skip))]
+    pub text_overlay: Option<TextOverlay>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The video encoder, defaults to libx264 for mp4 and mkv, and libvpx-vp9 for webm",
+            long
+        )
+    )]
+    pub encoder: Option<Encoder>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The video codec, encoded with libx264, libx265, libvpx-vp9, or libsvtav1 unless an encoder is specified",
+            long
+        )
+    )]
+    pub codec: Option<Codec>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Constant quality of the encoder, where lower is better. Defaults to a high quality value for each encoder",
+            long
+        )
+    )]
+    pub crf: Option<u8>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Speed preset of the encoder, such as slow for libx264 or 6 for libsvtav1",
+            long
+        )
+    )]
+    pub preset: Option<String>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Tuning of the encoder, defaults to stillimage for libx264",
+            long
+        )
+    )]
+    pub tune: Option<String>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Maximum bitrate of the video, such as 12M, which caps the constant quality",
+            long
+        )
+    )]
+    pub bitrate: Option<String>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Named encoding settings for a destination: youtube-4k, youtube-1080p, instagram, instagram-square, twitter, gif-small, or a preset defined in presets.toml",
+            long
+        )
+    )]
+    pub encode_preset: Option<String>,
+    /// Largest width of the video, which is scaled down to fit
+    #[cfg_attr(feature = "clap", arg(skip))]
+    pub max_width: Option<u32>,
+    /// Largest height of the video, which is scaled down to fit
+    #[cfg_attr(feature = "clap", arg(skip))]
+    pub max_height: Option<u32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The profile used by the prores codec",
+            long,
+            default_value = "hq"
+        )
+    )]
+    pub prores_profile: ProresProfile,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "The profile used by the dnxhr codec",
+            long,
+            default_value = "hq"
+        )
+    )]
+    pub dnxhr_profile: DnxhrProfile,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Color range of the video [default: tv]", long)
+    )]
+    pub color_range: Option<ColorRange>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Color primaries of the video [default: bt709, or those of a 10 bit or HDR input]",
+            long
+        )
+    )]
+    pub color_primaries: Option<ColorPrimaries>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Transfer characteristics of the video [default: bt709, or those of a 10 bit or HDR input]",
+            long
+        )
+    )]
+    pub color_transfer: Option<ColorTransfer>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Matrix coefficients of the video [default: bt709, or those of a 10 bit or HDR input]",
+            long
+        )
+    )]
+    pub color_matrix: Option<ColorMatrix>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Maximum number of colors in the gif palette",
+            long,
+            default_value = "256",
+            value_parser = clap::value_parser!(u16).range(2..=256)
+        )
+    )]
+    pub gif_colors: u16,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Dithering applied when reducing a gif to its palette",
+            long,
+            default_value = "sierra2_4a"
+        )
+    )]
+    pub gif_dither: Dither,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Quality of webp output, from 0 (smallest) to 100 (best)",
+            long,
+            default_value = "80",
+            value_parser = clap::value_parser!(u8).range(0..=100)
+        )
+    )]
+    pub webp_quality: u8,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Path to an audio track to mux into the video, looped or trimmed to the video length",
+            long
+        )
+    )]
+    pub audio: Option<PathBuf>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Length in seconds of the audio fade out at the end of the video",
+            long,
+            default_value = "2"
+        )
+    )]
+    pub audio_fade_out: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Length in seconds of the fade in from black at the start of the video",
+            long,
+            default_value = "0"
+        )
+    )]
+    pub fade_in: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Length in seconds of the fade out to black at the end of the video",
+            long,
+            default_value = "0"
+        )
+    )]
+    pub fade_out: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Path to an image shown before the rotation, fit to the video on a black background",
+            long
+        )
+    )]
+    pub title_card: Option<PathBuf>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Seconds the title card is held for", long, default_value = "3")
+    )]
+    pub title_card_duration: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Path to an image shown after the rotation, fit to the video on a black background",
+            long
+        )
+    )]
+    pub end_card: Option<PathBuf>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Seconds the end card is held for", long, default_value = "3")
+    )]
+    pub end_card_duration: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Mark the output as 360 video so YouTube and Facebook recognize it, requires equirectangular frames and mp4 or mov output",
+            long
+        )
+    )]
+    pub spherical: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Metadata to write to the output as key=value, such as title, artist, or comment. May be repeated",
+            long,
+            value_parser = parse_metadata
+        )
+    )]
+    pub metadata: Vec<(String, String)>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Leave the index of mp4 and mov output at the end, where players must download the whole file before playing it",
+            long
+        )
+    )]
+    pub no_faststart: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Option passed to the output container as key=value, such as movflags=+frag_keyframe. May be repeated",
+            long = "muxer-option",
+            value_parser = parse_muxer_option
+        )
+    )]
+    pub muxer_options: Vec<(String, String)>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Also export an extracted frame as a JPEG or PNG poster image, scaled like the video",
+            long
+        )
+    )]
+    pub poster: Option<PathBuf>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Index of the extracted frame used as the poster, defaults to the first frame",
+            long,
+            conflicts_with = "poster_yaw"
+        )
+    )]
+    pub poster_frame: Option<usize>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Use the extracted frame facing closest to this yaw in degrees as the poster",
+            long,
+            allow_negative_numbers = true
+        )
+    )]
+    pub poster_yaw: Option<f32>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Also export a JPEG sprite sheet of scrub thumbnails, with the WebVTT file web players read to show them next to it",
+            long
+        )
+    )]
+    pub sprite_sheet: Option<PathBuf>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Seconds of video between sprite sheet thumbnails",
+            long,
+            default_value = "1"
+        )
+    )]
+    pub sprite_interval: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Width of each sprite sheet thumbnail in pixels",
+            long,
+            default_value = "160"
+        )
+    )]
+    pub sprite_width: u32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Number of thumbnails in each row of the sprite sheet",
+            long,
+            default_value = "10"
+        )
+    )]
+    pub sprite_columns: usize,
+}
+
+impl Default for EncodeFramesDescriptor {
+    fn default() -> Self {
+        Self {
+            length: 10.0,
+            fps: 60.0,
+            scale: "1.0".to_string(),
+            downsample: 1,
+            boomerang: false,
+            loop_blend: None,
+            frame_interpolation: None,
+            motion_blur: None,
+            deflicker: None,
+            lut: None,
+            denoise: None,
+            denoise_filter: DenoiseFilter::Hqdn3d,
+            deband: false,
+            sharpen: None,
+            grain: None,
+            extra_vf: None,
+            extra_input_args: vec![],
+            extra_output_args: vec![],
+            watermark: WatermarkDescriptor::default(),
+            text_overlay: None,
+            encoder: None,
+            codec: None,
+            crf: None,
+            preset: None,
+            tune: None,
+            bitrate: None,
+            encode_preset: None,
+            max_width: None,
+            max_height: None,
+            prores_profile: ProresProfile::Hq,
+            dnxhr_profile: DnxhrProfile::Hq,
+            color_range: None,
+            color_primaries: None,
+            color_transfer: None,
+            color_matrix: None,
+            gif_colors: 256,
+            gif_dither: Dither::Sierra2_4a,
+            webp_quality: 80,
+            audio: None,
+            audio_fade_out: 2.0,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            title_card: None,
+            title_card_duration: 3.0,
+            end_card: None,
+            end_card_duration: 3.0,
+            spherical: false,
+            metadata: vec![],
+            no_faststart: false,
+            muxer_options: vec![],
+            poster: None,
+            poster_frame: None,
+            poster_yaw: None,
+            sprite_sheet: None,
+            sprite_interval: 1.0,
+            sprite_width: 160,
+            sprite_columns: 10,
+        }
+    }
+}
+
+impl EncodeFramesDescriptor {
+    /// Returns the descriptor with its encode preset applied, if it names one
+    fn with_preset(&self) -> Result<Cow<'_, EncodeFramesDescriptor>> {
+        match &self.encode_preset {
+            Some(name) => Ok(Cow::Owned(EncodePreset::load(name)?.apply(self))),
+            None => Ok(Cow::Borrowed(self)),
+        }
+    }
+
+    /// Returns the encoder used for a video output, or None for gif, webp, and apng outputs
+    pub fn video_encoder(&self, output_path: &Path) -> Result<Option<Encoder>> {
+        let descriptor = self.with_preset()?;
+        match output_path.extension().and_then(|e| e.to_str()) {
+            Some(ext @ ("mp4" | "mov" | "mkv" | "webm")) => {
+                descriptor.container_encoder(ext).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the encoder for a video container, from the encoder or codec options if given
+    fn container_encoder(&self, ext: &str) -> Result<Encoder> {
+        let encoder = match (self.encoder, self.codec) {
+            (Some(encoder), Some(codec)) if encoder.codec() != codec => {
+                return Err(DragonflyError::EncoderCodecMismatch(encoder, codec));
+            }
+            (Some(encoder), _) => encoder,
+            (None, Some(codec)) => codec.default_encoder(),
+            (None, None) => Encoder::default_for_container(ext),
+        };
+        if !encoder.supports_container(ext) {
+            return Err(DragonflyError::IncompatibleEncoder(
+                encoder,
+                ext.to_string(),
+            ));
+        }
+        Ok(encoder)
+    }
+
+    /// Returns the number of frames the encoder writes at the output frame rate
+    fn output_frame_count(&self) -> usize {
+        (self.total_length() * self.fps).round() as usize
+    }
+
+    /// Returns the length of the video in seconds, including the title and end cards
+    fn total_length(&self) -> f32 {
+        self.cards()
+            .iter()
+            .flatten()
+            .fold(self.length, |length, (_, duration)| length + duration)
+    }
+
+    /// Returns the title card and the end card, when shown, with the seconds each is held for
+    fn cards(&self) -> [Option<(&Path, f32)>; 2] {
+        [
+            (&self.title_card, self.title_card_duration),
+            (&self.end_card, self.end_card_duration),
+        ]
+        .map(|(path, duration)| Some((path.as_deref()?, duration)).filter(|_| duration > 0.0))
+    }
+
+    /// Places the title and end cards around the rotation, then fades the whole video in and out
+    fn with_cards_and_fades(&self, filter_string: String) -> Result<String> {
+        let mut filter_string = filter_string;
+        let cards = self.cards();
+        let shown = cards.iter().flatten().count();
+        if shown > 0 {
+            // Each card is drawn on a blacked out copy of the first frame, so it matches the size of the video
+            let mut graph = format!("{filter_string},split={}[body]", shown + 1);
+            for (i, card) in cards.iter().enumerate() {
+                if card.is_some() {
+                    graph.push_str(&format!("[base{i}]"));
+                }
+            }
+            let mut segments = vec!["[body]".to_string()];
+            for (i, card) in cards.into_iter().enumerate() {
+                let Some((path, duration)) = card else {
+                    continue;
+                };
+                let path_str = path
+                    .to_str()
+                    .ok_or_else(|| DragonflyError::InvalidPathString(path.to_path_buf()))?;
+                let frames = (duration * self.fps).round().max(1.0) as usize;
+                graph.push_str(&format!(
+                    ";movie={}[image{i}];\
+                    [base{i}]trim=end_frame=1,drawbox=t=fill:c=black[blank{i}];\
+                    [image{i}][blank{i}]scale2ref=w='min(main_w,main_h*iw/ih)':h='min(main_h,main_w*ih/iw)'[image{i}][blank{i}];\
+                    [blank{i}][image{i}]overlay=x=(W-w)/2:y=(H-h)/2,loop=loop={}:size=1,setpts=N/({}*TB)[card{i}]",
+                    escape_filter_value(path_str),
+                    frames - 1,
+                    self.fps,
+                ));
+                if i == 0 {
+                    segments.insert(0, format!("[card{i}]"));
+                } else {
+                    segments.push(format!("[card{i}]"));
+                }
+            }
+            filter_string = format!(
+                "{graph};{}concat=n={}:v=1:a=0",
+                segments.concat(),
+                segments.len()
+            );
+        }
+        if self.fade_in > 0.0 {
+            filter_string = format!("{filter_string},fade=t=in:st=0:d={}", self.fade_in);
+        }
+        if self.fade_out > 0.0 {
+            let fade_out = self.fade_out.min(self.total_length());
+            filter_string = format!(
+                "{filter_string},fade=t=out:st={}:d={fade_out}",
+                self.total_length() - fade_out
+            );
+        }
+        Ok(filter_string)
+    }
+
+    /// Returns the number of frames in the encoded video for the given number of extracted frames
+    fn played_frame_count(&self, total_frame_count: usize) -> usize {
+        if self.boomerang && total_frame_count > 2 {
+            // The reversed sequence drops its first and last frames to avoid stuttering at the turnarounds
+            2 * total_frame_count - 2
+        } else if let Some(frames) = self.loop_blend.filter(|_| !self.boomerang) {
+            // The blended frames replace the first frames, so the last frames are not played on their own
+            total_frame_count.saturating_sub(frames as usize)
+        } else {
+            total_frame_count
+        }
+    }
+
+    /// Returns the filters that scale and grade each frame, which the poster shares with the video
+    fn frame_filters(&self) -> Result<Vec<String>> {
+        let mut filters = vec![];
+        // If the user passed in a scale factor, use that. Otherwise, use the scale string as-is
+        match (self.scale.parse::<f32>(), self.downsample) {
+            (Ok(scale), 0 | 1) => filters.push(format!("scale=iw*{scale}:ih*{scale}")),
+            // Downscale supersampled frames with a high quality filter to keep the anti-aliasing
+            (Ok(scale), downsample) => filters.push(format!(
+                "scale=iw*{scale}/{downsample}:ih*{scale}/{downsample}:flags=lanczos"
+            )),
+            (Err(_), 0 | 1) => filters.push(format!("scale={}", &self.scale)),
+            (Err(_), downsample) => {
+                filters.push(format!(
+                    "scale=iw/{downsample}:ih/{downsample}:flags=lanczos"
+                ));
+                filters.push(format!("scale={}", &self.scale));
+            }
+        }
+        // Shrink to fit the maximum resolution without ever upscaling
+        match (self.max_width, self.max_height) {
+            (Some(width), Some(height)) => filters.push(format!(
+                "scale=w='min({width},iw)':h='min({height},ih)':force_original_aspect_ratio=decrease:force_divisible_by=2"
+            )),
+            (Some(width), None) => filters.push(format!("scale=w='min({width},iw)':h=-2")),
+            (None, Some(height)) => filters.push(format!("scale=w=-2:h='min({height},ih)'")),
+            (None, None) => {}
+        }
+        // Clean up the scaled frames before grading, which would amplify the noise and banding
+        if let Some(denoise) = self.denoise {
+            filters.push(self.denoise_filter.filter_string(denoise));
+        }
+        if self.deband {
+            filters.push("deband".to_string());
+        }
+        // Grade before any overlays so the watermark and text keep their own colors
+        if let Some(lut) = &self.lut {
+            let lut_str = lut
+                .to_str()
+                .ok_or_else(|| DragonflyError::InvalidPathString(lut.clone()))?;
+            filters.push(format!(
+                "lut3d=file={}:interp=tetrahedral",
+                escape_filter_value(lut_str)
+            ));
+        }
+        if let Some(amount) = self.sharpen {
+            filters.push(format!("unsharp=5:5:{}", amount.clamp(-1.5, 1.5)));
+        }
+        Ok(filters)
+    }
+
+    /// Builds the video filter graph shared by all output formats
+    fn video_filter_string(&self, total_frame_count: usize) -> Result<String> {
+        let mut filters = self.frame_filters()?;
+        // Even out the exposure before interpolating, so the synthesized frames do not flicker either
+        if let Some(size) = self.deflicker {
+            filters.push(format!("deflicker=size={size}"));
+        }
+        // Synthesize in-between frames after scaling, since motion estimation is expensive
+        if let Some(mode) = self.frame_interpolation {
+            filters.push(mode.minterpolate_filter_string(self.fps));
+        }
+        // Average each frame with the frames before it to simulate an open shutter
+        if let Some(frames) = self.motion_blur.filter(|&frames| frames > 1) {
+            filters.push(format!("tmix=frames={frames}"));
+        }
+        // Grain that changes every frame, like film
+        if let Some(grain) = self.grain {
+            filters.push(format!("noise=alls={grain}:allf=t+u"));
+        }
+        if let Some(extra_vf) = &self.extra_vf {
+            filters.push(extra_vf.clone());
+        }
+        let filter_string = filters.join(",");
+        let filter_string = if let Some(frames) = self.loop_blend.filter(|_| !self.boomerang) {
+            let frames = frames as usize;
+            if 2 * frames >= total_frame_count {
+                return Err(DragonflyError::LoopBlendTooLong {
+                    frames,
+                    frame_count: total_frame_count,
+                });
+            }
+            let steps = frames + 1;
+            format!(
+                "split=3[head][middle][tail];\
+                [tail]trim=start_frame={}:end_frame={total_frame_count},setpts=PTS-STARTPTS[ending];\
+                [head]trim=end_frame={frames},setpts=PTS-STARTPTS[beginning];\
+                [ending][beginning]blend=all_expr=A*(1-(N+1)/{steps})+B*(N+1)/{steps}[seam];\
+                [middle]trim=start_frame={frames}:end_frame={},setpts=PTS-STARTPTS[body];\
+                [seam][body]concat=n=2:v=1:a=0,{filter_string}",
+                total_frame_count - frames,
+                total_frame_count - frames,
+            )
+        } else if self.played_frame_count(total_frame_count) == total_frame_count {
+            filter_string
+        } else {
+            format!(
+                "split[forward][backward];\
+                [backward]reverse,trim=start_frame=1:end_frame={},setpts=PTS-STARTPTS[reversed];\
+                [forward][reversed]concat=n=2:v=1:a=0,{}",
+                total_frame_count - 1,
+                filter_string
+            )
+        };
+        let filter_string = self.watermark.apply(filter_string)?;
+        let filter_string = match &self.text_overlay {
+            Some(text_overlay) => {
+                format!("{filter_string},{}", text_overlay.encode_filter_string()?)
+            }
+            None => filter_string,
+        };
+        self.with_cards_and_fades(filter_string)
+    }
+
+    /// Returns the ffmpeg arguments that write the metadata to the output
+    fn metadata_args(&self) -> Vec<String> {
+        self.metadata
+            .iter()
+            .flat_map(|(key, value)| ["-metadata".to_string(), format!("{key}={value}")])
+            .collect()
+    }
+
+    /// Returns the muxer options for `output_path`, moving the index of mp4 and mov output to the start of the
+    /// file so web playback begins before the download ends
+    fn muxer_args(&self, output_path: &Path) -> Vec<String> {
+        let is_quicktime = output_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| ["mp4", "mov", "m4v"].contains(&e.to_ascii_lowercase().as_str()));
+        let sets_movflags = self.muxer_options.iter().any(|(key, _)| key == "movflags");
+        let faststart = (is_quicktime && !self.no_faststart && !sets_movflags)
+            .then(|| ("movflags".to_string(), "+faststart".to_string()));
+        faststart
+            .iter()
+            .chain(&self.muxer_options)
+            .flat_map(|(key, value)| [format!("-{key}"), value.clone()])
+            .collect()
+    }
+
+    /// Returns the filter graph that generates an optimal palette from all frames and then maps
+    /// each frame to it
+    fn gif_filter_string(&self, total_frame_count: usize) -> Result<String> {
+        Ok(format!(
+            "{},split[palette_in][frames];\
+            [palette_in]palettegen=max_colors={}:stats_mode=full[palette];\
+            [frames][palette]paletteuse=dither={}",
+            self.video_filter_string(total_frame_count)?,
+            self.gif_colors.clamp(2, 256),
+            self.gif_dither
+        ))
+    }
+}
+
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Debug, Display, EnumString, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    Near,
+    Linear,
+    Cubic,
+    Lanczos,
+    Spline16,
+    Lagrange9,
+    Gaussian,
+    Mitchell,
+}
+
+/// Projection of the extracted frames
+///
+/// See <https://ffmpeg.org/ffmpeg-filters.html#v360> for a description of each projection.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OutputProjection {
+    /// Regular rectilinear video
+    Flat,
+    /// Stereographic projection, also known as "little planet" when looking straight down
+    Stereographic,
+    /// Equidistant fisheye
+    Fisheye,
+    /// Pannini projection, which ignores the output field of view
+    Pannini,
+    /// Cylindrical projection
+    Cylindrical,
+    /// Equirectangular projection, which rotates the full sphere and always renders a 360x180 field of view
+    Equirectangular,
+}
+
+impl OutputProjection {
+    /// Returns the name of the projection as understood by the v360 filter
+    pub fn v360_name(&self) -> &'static str {
+        match self {
+            OutputProjection::Flat => "flat",
+            OutputProjection::Stereographic => "sg",
+            OutputProjection::Fisheye => "fisheye",
+            OutputProjection::Pannini => "pannini",
+            OutputProjection::Cylindrical => "cylindrical",
+            OutputProjection::Equirectangular => "e",
+        }
+    }
+}
+
+/// Aspect ratio presets for social media formats
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+pub enum Aspect {
+    /// Landscape video
+    #[cfg_attr(feature = "clap", value(name = "16:9"))]
+    #[strum(serialize = "16:9")]
+    #[serde(rename = "16:9")]
+    Landscape,
+    /// Vertical video for TikTok, Reels, and Shorts
+    #[cfg_attr(feature = "clap", value(name = "9:16"))]
+    #[strum(serialize = "9:16")]
+    #[serde(rename = "9:16")]
+    Vertical,
+    /// Square video
+    #[cfg_attr(feature = "clap", value(name = "1:1"))]
+    #[strum(serialize = "1:1")]
+    #[serde(rename = "1:1")]
+    Square,
+    /// Portrait video for the Instagram feed
+    #[cfg_attr(feature = "clap", value(name = "4:5"))]
+    #[strum(serialize = "4:5")]
+    #[serde(rename = "4:5")]
+    Portrait,
+}
+
+impl Aspect {
+    /// Returns the width divided by the height
+    pub fn ratio(&self) -> f32 {
+        match self {
+            Aspect::Landscape => 16.0 / 9.0,
+            Aspect::Vertical => 9.0 / 16.0,
+            Aspect::Square => 1.0,
+            Aspect::Portrait => 4.0 / 5.0,
+        }
+    }
+
+    /// Returns the vertical field of view that matches the aspect ratio for the given horizontal field of view
+    pub fn v_fov(&self, h_fov: f32, projection: OutputProjection) -> f32 {
+        match projection {
+            // Rectilinear images scale with the tangent of the half angle rather than the angle itself
+            OutputProjection::Flat => {
+                2.0 * ((h_fov / 2.0).to_radians().tan() / self.ratio())
+                    .atan()
+                    .to_degrees()
+            }
+            _ => h_fov / self.ratio(),
+        }
+    }
+}
+
+/// Arrangement of the two eyes in a stereo 360 image
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum StereoLayout {
+    /// A regular, monoscopic image
+    Mono,
+    /// Left eye on top, right eye on the bottom
+    TopBottom,
+    /// Left eye on the left, right eye on the right
+    SideBySide,
+}
+
+impl StereoLayout {
+    /// Returns the name of the layout as understood by the v360 filter
+    pub fn v360_name(&self) -> &'static str {
+        match self {
+            StereoLayout::Mono => "2d",
+            StereoLayout::TopBottom => "tb",
+            StereoLayout::SideBySide => "sbs",
+        }
+    }
+}
+
+/// The eye to extract from a stereo input
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Eye {
+    Left,
+    Right,
+    Both,
+}
+
+/// Direction of the camera rotation, where clockwise increases the yaw
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    Clockwise,
+    CounterClockwise,
+}
+
+/// Method used to synthesize in-between frames with the minterpolate filter
+///
+/// See <https://ffmpeg.org/ffmpeg-filters.html#minterpolate> for a description of each mode.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum FrameInterpolation {
+    /// Blend neighboring frames, which is fast but ghosts on fast motion
+    Blend,
+    /// Motion compensated interpolation, which is slow but produces true in-between frames
+    Mci,
+}
+
+impl FrameInterpolation {
+    fn minterpolate_filter_string(&self, fps: f32) -> String {
+        match self {
+            FrameInterpolation::Blend => format!("minterpolate=fps={fps}:mi_mode=blend"),
+            FrameInterpolation::Mci => {
+                format!("minterpolate=fps={fps}:mi_mode=mci:mc_mode=aobmc:me_mode=bidir:vsbmc=1")
+            }
+        }
+    }
+}
+
+/// How strongly frames are denoised
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Denoise {
+    Light,
+    Medium,
+    Strong,
+}
+
+/// Filter used to denoise frames
+///
+/// See <https://ffmpeg.org/ffmpeg-filters.html#hqdn3d> and <https://ffmpeg.org/ffmpeg-filters.html#nlmeans>.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DenoiseFilter {
+    /// Fast spatial and temporal smoothing
+    #[default]
+    Hqdn3d,
+    /// Non-local means, which keeps edges and texture but is much slower
+    Nlmeans,
+}
+
+impl DenoiseFilter {
+    fn filter_string(&self, denoise: Denoise) -> String {
+        match (self, denoise) {
+            (DenoiseFilter::Hqdn3d, Denoise::Light) => "hqdn3d=2:1.5:3:2.25".to_string(),
+            (DenoiseFilter::Hqdn3d, Denoise::Medium) => "hqdn3d=4:3:6:4.5".to_string(),
+            (DenoiseFilter::Hqdn3d, Denoise::Strong) => "hqdn3d=8:6:12:9".to_string(),
+            (DenoiseFilter::Nlmeans, Denoise::Light) => "nlmeans=s=2".to_string(),
+            (DenoiseFilter::Nlmeans, Denoise::Medium) => "nlmeans=s=4".to_string(),
+            (DenoiseFilter::Nlmeans, Denoise::Strong) => "nlmeans=s=8".to_string(),
+        }
+    }
+}
+
+/// Dithering algorithm used by the paletteuse filter
+///
+/// See <https://ffmpeg.org/ffmpeg-filters.html#paletteuse> for a description of each algorithm.
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "clap", value(rename_all = "snake_case"))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Dither {
+    None,
+    Bayer,
+    Heckbert,
+    FloydSteinberg,
+    Sierra2,
+    #[cfg_attr(feature = "clap", value(name = "sierra2_4a"))]
+    #[strum(serialize = "sierra2_4a")]
+    #[serde(rename = "sierra2_4a")]
+    Sierra2_4a,
+}
+
+/// Image format of the extracted frames, which the encoder reads back
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum IntermediateFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Tiff,
+    /// Lossless WebP, the smallest of the lossless formats but the slowest to write
+    Webp,
+}
+
+impl IntermediateFormat {
+    const ALL: [IntermediateFormat; 4] = [
+        IntermediateFormat::Jpeg,
+        IntermediateFormat::Png,
+        IntermediateFormat::Tiff,
+        IntermediateFormat::Webp,
+    ];
+
+    /// The extension of the frame files, which picks the image2 encoder
+    pub fn extension(&self) -> &'static str {
+        match self {
+            IntermediateFormat::Jpeg => "jpg",
+            IntermediateFormat::Png => "png",
+            IntermediateFormat::Tiff => "tiff",
+            IntermediateFormat::Webp => "webp",
+        }
+    }
+
+    /// Returns the ffmpeg output arguments that write frames in this format, keeping 16 bits per component
+    /// of high bit depth inputs in the formats that can hold them
+    fn output_args(&self, jpeg_quality: Option<u8>, color: &SourceColor) -> Vec<String> {
+        let args: &[&str] = match (self, jpeg_quality) {
+            (IntermediateFormat::Jpeg, Some(quality)) => {
+                return vec!["-q:v".into(), quality.to_string()]
+            }
+            (IntermediateFormat::Png, _) => match (color.is_high_bit_depth(), color.alpha) {
+                (true, true) => &["-pix_fmt", "rgba64be"],
+                (true, false) => &["-pix_fmt", "rgb48be"],
+                (false, true) => &["-pix_fmt", "rgba"],
+                (false, false) => &[],
+            },
+            (IntermediateFormat::Tiff, _) => match (color.is_high_bit_depth(), color.alpha) {
+                (true, true) => &["-pix_fmt", "rgba64le"],
+                (true, false) => &["-pix_fmt", "rgb48le"],
+                (false, true) => &["-pix_fmt", "rgba"],
+                (false, false) => &[],
+            },
+            (IntermediateFormat::Webp, _) => &["-lossless", "1"],
+            _ => &[],
+        };
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Returns the format of the frames extracted to `extraction_path`, defaulting to jpeg when there are none
+    pub(crate) fn of_extraction(extraction_path: &Path) -> Self {
+        IntermediateFormat::ALL
+            .into_iter()
+            .find(|format| frame_path(extraction_path, 0, *format).is_file())
+            .unwrap_or_default()
+    }
+
+    /// Returns a rough estimate of the bytes one extracted frame of the given size takes on disk
+    pub fn estimated_frame_size(&self, width: u32, height: u32, color: &SourceColor) -> u64 {
+        let components = if color.alpha { 4.0 } else { 3.0 };
+        // Only the lossless formats keep 16 bits per component of high bit depth inputs
+        let component_bytes = match self {
+            IntermediateFormat::Png | IntermediateFormat::Tiff if color.is_high_bit_depth() => 2.0,
+            _ => 1.0,
+        };
+        // Typical compressed sizes of a rendered panorama, relative to the raw pixels
+        let compression = match self {
+            IntermediateFormat::Jpeg => 0.15,
+            IntermediateFormat::Png => 0.6,
+            IntermediateFormat::Tiff => 1.0,
+            IntermediateFormat::Webp => 0.45,
+        };
+        (width as f64 * height as f64 * components * component_bytes * compression) as u64
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStreamOutput>,
+    format: Option<FfprobeFormatOutput>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FfprobeStreamOutput {
+    index: usize,
+    codec_type: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    pub(crate) codec_name: Option<String>,
+    pub(crate) pix_fmt: Option<String>,
+    pub(crate) bits_per_raw_sample: Option<String>,
+    r_frame_rate: Option<String>,
+    nb_frames: Option<String>,
+    duration: Option<String>,
+    color_transfer: Option<String>,
+    color_primaries: Option<String>,
+    color_space: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<u32>,
+    tags: Option<FfprobeTagsOutput>,
+    #[serde(default)]
+    side_data_list: Vec<FfprobeSideDataOutput>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FfprobeTagsOutput {
+    rotate: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FfprobeSideDataOutput {
+    rotation: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FfprobeFormatOutput {
+    format_name: Option<String>,
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+/// The properties of an input that shape its extraction, as reported by ffprobe
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Frame timing when the input is a video rather than a still image
+    pub video: Option<SourceVideo>,
+    pub color: SourceColor,
+}
+
+/// Frame timing of a video input
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SourceVideo {
+    pub frame_rate: f64,
+    pub frame_count: usize,
+}
+
+impl FfprobeOutput {
+    /// Returns the first video stream, which is the one dragonfly extracts
+    pub(crate) fn video_stream(&self) -> Option<&FfprobeStreamOutput> {
+        self.streams
+            .iter()
+            .find(|stream| stream.codec_type.as_deref() == Some("video"))
+    }
+
+    pub(crate) fn input_info(&self) -> Result<InputInfo> {
+        let stream = self
+            .video_stream()
+            .ok_or(DragonflyError::SourceContainsNoStream)?;
+        Ok(InputInfo {
+            width: stream.width.unwrap_or(0),
+            height: stream.height.unwrap_or(0),
+            video: self.source_video(),
+            color: SourceColor {
+                bit_depth: stream.bit_depth().unwrap_or(0),
+                transfer: stream.color_transfer.clone(),
+                primaries: stream.color_primaries.clone(),
+                space: stream.color_space.clone(),
+                alpha: false,
+            },
+        })
+    }
+
+    /// Returns the frame timing of the input when it is a video rather than a still image
+    fn source_video(&self) -> Option<SourceVideo> {
+        let format_name = self.format.as_ref()?.format_name.as_deref()?;
+        // Still images are demuxed by image2 or one of the single image pipe demuxers
+        if format_name == "image2" || format_name.ends_with("_pipe") {
+            return None;
+        }
+        let stream = self.video_stream()?;
+        let frame_rate = stream.r_frame_rate.as_deref().and_then(parse_frame_rate)?;
+        let frame_count = stream
+            .nb_frames
+            .as_deref()
+            .and_then(|n| n.parse::<usize>().ok())
+            .or_else(|| {
+                let duration = stream
+                    .duration
+                    .as_deref()
+                    .or(self.format.as_ref()?.duration.as_deref())?
+                    .parse::<f64>()
+                    .ok()?;
+                Some((duration * frame_rate).round() as usize)
+            })?;
+        (frame_count > 1).then_some(SourceVideo {
+            frame_rate,
+            frame_count,
+        })
+    }
+}
+
+/// Parses an ffprobe rational frame rate such as 30000/1001
+fn parse_frame_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/').unwrap_or((rate, "1"));
+    let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+    (num > 0.0 && den > 0.0).then_some(num / den)
+}
+
+/// Builds the ffprobe command that reports every stream of the input along with its container format
+fn ffprobe_command(input_path: &Path) -> Result<Command> {
+    let input_path_str = input_path
+        .to_str()
+        .ok_or_else(|| DragonflyError::InvalidPathString(input_path.to_path_buf()))?;
+    let mut ffprobe_cmd = Command::new(FFPROBE_BINARY_PATH.as_os_str());
+    ffprobe_cmd
+        .args([
+            "-v",
+            "error",
+            "-show_streams",
+            "-show_format",
+            "-of",
+            "json=compact=1",
+            input_path_str,
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    Ok(ffprobe_cmd)
+}
+
+/// Returns an error if the input is a local file that does not exist, leaving URLs for ffmpeg to open
+fn check_input_exists(input_path: &Path) -> Result<()> {
+    let is_url = input_path.to_str().is_some_and(|path| path.contains("://"));
+    if !is_url && !input_path.exists() {
+        return Err(DragonflyError::InputNotFound(input_path.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Parses what ffprobe wrote to stdout, explaining why the input cannot be decoded when ffprobe failed
+fn parse_ffprobe_output(
+    input_path: &Path,
+    status: ExitStatus,
+    stdout: &[u8],
+    stderr: &str,
+) -> Result<FfprobeOutput> {
+    if !status.success() {
+        return Err(DragonflyError::UndecodableInput {
+            path: input_path.to_path_buf(),
+            reason: stderr.to_string(),
+        });
+    }
+    let output: FfprobeOutput = serde_json::from_slice(stdout)?;
+    // ffprobe succeeds on files without a video stream, such as audio
+    if output.video_stream().is_none() {
+        return Err(DragonflyError::UndecodableInput {
+            path: input_path.to_path_buf(),
+            reason: "no image or video stream".to_string(),
+        });
+    }
+    Ok(output)
+}
+
+pub(crate) fn ffprobe(input_path: &Path, executor: &dyn Executor) -> Result<FfprobeOutput> {
+    check_input_exists(input_path)?;
+    let mut ffprobe_child = spawn(executor, &mut ffprobe_command(input_path)?)?;
+    let mut ffprobe_output = vec![];
+    if let Some(mut stdout) = ffprobe_child.take_stdout() {
+        stdout.read_to_end(&mut ffprobe_output)?;
+    }
+    let stderr = read_stderr(ffprobe_child.as_mut());
+    let status = ffprobe_child.wait()?;
+    parse_ffprobe_output(input_path, status, &ffprobe_output, &stderr)
+}
+
+fn ffprobe_info(input_path: &Path, executor: &dyn Executor) -> Result<InputInfo> {
+    ffprobe(input_path, executor)?.input_info()
+}
+
+fn spawn(executor: &dyn Executor, command: &mut Command) -> Result<Box<dyn Process>> {
+    debug!("Spawning command: {:?}", command);
+    Ok(retry::with_timeout(executor.spawn(command)?))
+}
+
+/// Returns the path of an extracted frame
+pub(crate) fn frame_path(
+    extraction_path: &Path,
+    frame: usize,
+    format: IntermediateFormat,
+) -> PathBuf {
+    extraction_path.join(format!("frame_{:08}.{}", frame, format.extension()))
+}
+
+/// Returns the frame_%08d pattern of the extracted frames that ffmpeg reads and writes
+pub(crate) fn frame_path_template(extraction_path: &Path, format: IntermediateFormat) -> PathBuf {
+    extraction_path.join(format!("frame_%08d.{}", format.extension()))
+}
+
+/// Returns the format of the file if its name matches the frame_%08d pattern of extracted frames
+fn frame_file_format(file_name: &str) -> Option<IntermediateFormat> {
+    let (index, extension) = file_name.strip_prefix("frame_")?.split_once('.')?;
+    if index.len() != 8 || !index.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    IntermediateFormat::ALL
+        .into_iter()
+        .find(|format| format.extension() == extension)
+}
+
+fn is_frame_file_name(file_name: &str) -> bool {
+    frame_file_format(file_name).is_some()
+}
+
+/// Removes the extracted frames whose format matches the predicate
+fn remove_frames(extraction_path: &Path, predicate: impl Fn(IntermediateFormat) -> bool) {
+    let frames = fs::read_dir(extraction_path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            frame_file_format(&entry.file_name().to_string_lossy()).is_some_and(&predicate)
+        });
+    for frame in frames {
+        fs::remove_file(frame.path()).ok();
+    }
+}
+
+/// The file in an extraction directory that lists a fingerprint of the parameters of each frame, so an
+/// interrupted extraction can be resumed
+const FRAME_FINGERPRINTS_FILE: &str = ".dragonfly-fingerprints";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+
+/// Continues a 64 bit FNV-1a hash, which unlike the std hashers is stable across releases
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn fingerprint(s: &str) -> String {
+    format!("{:016x}", fnv1a(FNV_OFFSET_BASIS, s.as_bytes()))
+}
+
+/// Returns true if the frame was written to the end, rather than cut short by an interruption
+///
+/// TIFF has no trailer, so a TIFF frame only has to start with a TIFF header.
+fn is_complete_frame(path: &Path) -> bool {
+    let is_complete = || -> io::Result<bool> {
+        let mut file = fs::File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut start = [0u8; 8];
+        file.read_exact(&mut start)?;
+        let mut end = [0u8; 8];
+        file.seek(io::SeekFrom::End(-8))?;
+        file.read_exact(&mut end)?;
+        Ok(match start {
+            [0xff, 0xd8, ..] => end[6..] == [0xff, 0xd9],
+            // The IEND chunk, followed by its CRC
+            [0x89, b'P', b'N', b'G', ..] => end == *b"IEND\xaeB`\x82",
+            // RIFF files start with the length of the rest of the file
+            [b'R', b'I', b'F', b'F', a, b, c, d] => {
+                u32::from_le_bytes([a, b, c, d]) as u64 + 8 == length
+            }
+            [b'I', b'I', 42, 0, ..] | [b'M', b'M', 0, 42, ..] => true,
+            _ => false,
+        })
+    };
+    is_complete().unwrap_or(false)
+}
+
+/// The frames to render from an input, settled once the input has been probed
+struct Extraction<'a> {
+    input_path_str: &'a str,
+    input_filename: String,
+    descriptor: Cow<'a, ExtractFramesDescriptor>,
+    source_video: Option<SourceVideo>,
+    color: SourceColor,
+    input_resolution: (u32, u32),
+    output_resolution: (u32, u32),
+}
+
+impl<'a> Extraction<'a> {
+    fn new(
+        input_path: &'a Path,
+        descriptor: &'a ExtractFramesDescriptor,
+        input: &InputInfo,
+    ) -> Result<Self> {
+        let input_path_str = input_path
+            .to_str()
+            .ok_or_else(|| DragonflyError::InvalidPathString(input_path.to_path_buf()))?;
+        // Video inputs render one rotated frame per source frame, advancing the yaw and source time together
+        let source_video = input.video;
+        let descriptor = match source_video {
+            Some(video) => {
+                debug!(
+                    "Video input with {} frames at {} fps",
+                    video.frame_count, video.frame_rate
+                );
+                Cow::Owned(ExtractFramesDescriptor {
+                    frame_count: video.frame_count,
+                    ..descriptor.clone()
+                })
+            }
+            None => Cow::Borrowed(descriptor),
+        };
+        let descriptor = match read_gpano(input_path) {
+            Some(gpano) => Cow::Owned(descriptor.into_owned().with_gpano(&gpano)),
+            None => descriptor,
+        };
+        // Jpeg frames have no alpha channel
+        let descriptor = match descriptor.intermediate_format {
+            IntermediateFormat::Jpeg | IntermediateFormat::Webp if descriptor.alpha => {
+                info!("Extracting png frames to keep the alpha channel");
+                Cow::Owned(ExtractFramesDescriptor {
+                    intermediate_format: IntermediateFormat::Png,
+                    ..descriptor.into_owned()
+                })
+            }
+            _ => descriptor,
+        };
+        // 8 bit frames would crush high bit depth and HDR inputs before they reach the encoder
+        let descriptor = match descriptor.intermediate_format {
+            IntermediateFormat::Jpeg | IntermediateFormat::Webp
+                if input.color.is_high_bit_depth() =>
+            {
+                info!(
+                    "Extracting 16 bit png frames to keep the {} bit input",
+                    input.color.bit_depth.max(10)
+                );
+                Cow::Owned(ExtractFramesDescriptor {
+                    intermediate_format: IntermediateFormat::Png,
+                    ..descriptor.into_owned()
+                })
+            }
+            _ => descriptor,
+        };
+        descriptor.validate()?;
+        descriptor.check_input_aspect(input.width, input.height);
+        let output_resolution = descriptor.output_resolution(input.width, input.height);
+        debug!(
+            "Output resolution {}x{}",
+            output_resolution.0, output_resolution.1
+        );
+        let color = SourceColor {
+            alpha: descriptor.alpha,
+            ..input.color.clone()
+        };
+        Ok(Self {
+            input_path_str,
+            input_filename: input_filename(input_path),
+            descriptor,
+            source_video,
+            color,
+            input_resolution: (input.width, input.height),
+            output_resolution,
+        })
+    }
+
+    /// Fails early when the disk holding `extraction_path` lacks the space `frame_count` more frames are
+    /// estimated to take, rather than once it fills up partway through
+    fn check_disk_space(&self, extraction_path: &Path, frame_count: usize) -> Result<()> {
+        if self.descriptor.no_disk_check {
+            return Ok(());
+        }
+        let (width, height) = self.output_resolution;
+        let frame_size =
+            self.descriptor
+                .intermediate_format
+                .estimated_frame_size(width, height, &self.color);
+        disk_space::check_disk_space(extraction_path, frame_size * frame_count as u64)
+    }
+
+    /// Prepares `extraction_path` and picks how to render the frames, once the backend is known to support the
+    /// input and there is room for the frames. The blocking and async extractions both render this way.
+    fn render_strategy(
+        &self,
+        input: &InputInfo,
+        extraction_path: &Path,
+        progress_handler: Option<&impl ProgressHandler>,
+    ) -> Result<RenderStrategy> {
+        let descriptor = &self.descriptor;
+        if descriptor.backend == Backend::Native {
+            // Every frame is rendered again, so none can be resumed by a later extraction
+            fs::create_dir_all(extraction_path)?;
+            fs::remove_file(extraction_path.join(FRAME_FINGERPRINTS_FILE)).ok();
+            remove_frames(extraction_path, |_| true);
+            self.check_disk_space(extraction_path, descriptor.frame_count)?;
+            return Ok(RenderStrategy::Native);
+        }
+        if descriptor.backend == Backend::Remap {
+            native::check_supported(Backend::Remap, descriptor, input)?;
+        }
+        let remaining = self.resume(extraction_path, progress_handler)?;
+        self.check_disk_space(extraction_path, remaining.len())?;
+        // A resumed extraction renders its remaining frames one process per frame
+        let single_pass = self.is_single_pass() && remaining.len() == descriptor.frame_count;
+        Ok(RenderStrategy::Ffmpeg {
+            remaining,
+            single_pass,
+        })
+    }
+
+    /// Builds the command that renders a frame in its own ffmpeg process to `output_path`, first writing the maps
+    /// of its pose to `extraction_path` for the remap backend
+    fn render_frame_command(
+        &self,
+        extraction_path: &Path,
+        frame: usize,
+        output_path: &Path,
+    ) -> Result<Command> {
+        if self.descriptor.backend == Backend::Remap {
+            remap::write_maps(
+                extraction_path,
+                &self.descriptor,
+                &self.descriptor.pose(frame),
+                self.input_resolution,
+                self.output_resolution,
+            )?;
+        }
+        self.frame_command(frame, output_path)
+    }
+
+    /// Rendering every frame in a single ffmpeg process avoids paying the process startup and input decoding
+    /// cost per frame, but relies on v360 accepting runtime commands, so callers fall back to one process per
+    /// frame when it fails
+    fn is_single_pass(&self) -> bool {
+        self.source_video.is_none()
+            && !self.descriptor.per_frame
+            && self.descriptor.backend == Backend::Ffmpeg
+    }
+
+    /// Builds the report of the frames extracted to `extraction_path`, given the time spent rendering each frame
+    fn report(
+        &self,
+        extraction_path: &Path,
+        mut frame_durations: Vec<Duration>,
+        single_pass: bool,
+        duration: Duration,
+    ) -> ExtractReport {
+        frame_durations.resize(self.descriptor.frame_count, Duration::ZERO);
+        let frames = frame_durations
+            .into_iter()
+            .enumerate()
+            .map(|(index, duration)| ExtractedFrame {
+                index,
+                path: self.frame_path(extraction_path, index),
+                pose: self.descriptor.pose(index),
+                duration,
+            })
+            .collect();
+        let (width, height) = self.output_resolution;
+        ExtractReport {
+            frames,
+            width,
+            height,
+            single_pass,
+            duration,
+            color: self.color.clone(),
+        }
+    }
+
+    /// Returns a fingerprint of each frame, which changes whenever the frame would render differently
+    fn frame_fingerprints(&self) -> Result<Vec<String>> {
+        // Replacing the input in place changes its size or modification time
+        let input_stamp = fs::metadata(self.input_path_str)
+            .map(|metadata| format!("{} {:?}", metadata.len(), metadata.modified().ok()))
+            .unwrap_or_default();
+        (0..self.descriptor.frame_count)
+            .map(|frame| {
+                let ffmpeg_cmd = self.frame_command(frame, &self.frame_path(Path::new(""), 0))?;
+                Ok(fingerprint(&format!(
+                    "{input_stamp} {}",
+                    command_line(&ffmpeg_cmd)
+                )))
+            })
+            .collect()
+    }
+
+    /// Prepares the extraction directory and returns the frames left to render
+    ///
+    /// Complete frames rendered with the same parameters by an earlier, interrupted extraction are kept and
+    /// reported as extracted. Every other frame that extraction left behind is removed.
+    fn resume(
+        &self,
+        extraction_path: &Path,
+        progress_handler: Option<&impl ProgressHandler>,
+    ) -> Result<Vec<usize>> {
+        let fingerprints = self.frame_fingerprints()?;
+        fs::create_dir_all(extraction_path)?;
+        let fingerprints_path = extraction_path.join(FRAME_FINGERPRINTS_FILE);
+        let previous = fs::read_to_string(&fingerprints_path).unwrap_or_default();
+        let previous: Vec<&str> = previous.lines().collect();
+        remove_frames(extraction_path, |format| {
+            format != self.descriptor.intermediate_format
+        });
+        let mut remaining = vec![];
+        for (frame, fingerprint) in fingerprints.iter().enumerate() {
+            let path = self.frame_path(extraction_path, frame);
+            if previous.get(frame) == Some(&fingerprint.as_str()) && is_complete_frame(&path) {
+                progress::report(
+                    progress_handler,
+                    ProgressEvent::FrameExtracted {
+                        index: frame,
+                        path: Some(path),
+                        duration: Duration::ZERO,
+                    },
+                );
+            } else {
+                fs::remove_file(path).ok();
+                remaining.push(frame);
+            }
+        }
+        for frame in fingerprints.len()..previous.len() {
+            fs::remove_file(self.frame_path(extraction_path, frame)).ok();
+        }
+        if remaining.len() < fingerprints.len() {
+            info!(
+                "Resuming extraction with {} of {} frames already extracted",
+                fingerprints.len() - remaining.len(),
+                fingerprints.len()
+            );
+        }
+        // Written before rendering, so every frame in the directory always matches the fingerprints
+        fs::write(fingerprints_path, fingerprints.join("\n"))?;
+        Ok(remaining)
+    }
+
+    /// Returns the path the frame is extracted to, in the intermediate format
+    fn frame_path(&self, extraction_path: &Path, frame: usize) -> PathBuf {
+        frame_path(extraction_path, frame, self.descriptor.intermediate_format)
+    }
+
+    /// Builds the command that renders a single frame to `output_path`
+    fn frame_command(&self, frame: usize, output_path: &Path) -> Result<Command> {
         let output_path_str = output_path
             .to_str()
-            .ok_or_else(|| DragonflyError::InvalidPathString(output_path.clone()))?;
+            .ok_or_else(|| DragonflyError::InvalidPathString(output_path.to_path_buf()))?;
+        let (output_width, output_height) = self.output_resolution;
         let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
         ffmpeg_cmd.args([
             // Quiet output
@@ -217,23 +2860,47 @@ pub fn extract_frames(
             "-loglevel",
             "error",
             "-nostats",
-            // Input file
-            "-i",
-            input_path_str,
-            // Video filter arguments
-            // See https://ffmpeg.org/ffmpeg-filters.html#v360
-            "-vf",
-            &format!(
-                "v360=e:flat:yaw={}:pitch={}:roll={}:ih_fov={}:iv_fov={}:h_fov={}:v_fov={}:interp={}",
-                yaw,
-                pitch,
-                roll,
-                ih_fov,
-                iv_fov,
-                oh_fov,
-                ov_fov,
-                descriptor.interpolation,
-            ),
+        ]);
+        if let Some(video) = self.source_video {
+            // Seek the input to the source frame matching this output frame
+            ffmpeg_cmd.args(["-ss", &(frame as f64 / video.frame_rate).to_string()]);
+        }
+        ffmpeg_cmd.args(&self.descriptor.extra_input_args);
+        ffmpeg_cmd.args(["-i", self.input_path_str]);
+        if self.descriptor.backend == Backend::Remap {
+            // The maps are cached next to the frames
+            let pose = self.descriptor.pose(frame);
+            let (x_map, y_map) = remap::map_paths(
+                output_path.parent().unwrap_or(Path::new("")),
+                &self.descriptor,
+                &pose,
+                self.input_resolution,
+                self.output_resolution,
+            );
+            ffmpeg_cmd.arg("-i").arg(x_map).arg("-i").arg(y_map).args([
+                // See https://ffmpeg.org/ffmpeg-filters.html#remap
+                "-filter_complex",
+                &self.descriptor.with_overlay_filters(
+                    "[0][1][2]remap".to_string(),
+                    frame,
+                    &self.input_filename,
+                    &pose,
+                )?,
+            ]);
+        } else {
+            ffmpeg_cmd.args([
+                // Video filter arguments
+                // See https://ffmpeg.org/ffmpeg-filters.html#v360
+                "-vf",
+                &self.descriptor.frame_filter_string(
+                    frame,
+                    &self.input_filename,
+                    output_width,
+                    output_height,
+                )?,
+            ]);
+        }
+        ffmpeg_cmd.args([
             // Output file
             // https://ffmpeg.org/ffmpeg-formats.html#image2-1
             "-f",
@@ -242,241 +2909,1216 @@ pub fn extract_frames(
             "1",
             "-update",
             "1",
-            "-y",
-            output_path_str,
         ]);
-        debug!("Spawning command: {:?}", &ffmpeg_cmd);
-        let ffmpeg_child = ffmpeg_cmd.stdout(Stdio::piped()).spawn()?;
-        tasks.push(ffmpeg_child);
-        // Wait for tasks to finish if we have reached the maximum number of concurrent tasks
-        if tasks.len() == tasks.capacity() {
-            for task in tasks.iter_mut() {
-                let status = task.wait()?;
+        // Previews written in other formats get the default settings of their encoder
+        let format = self.descriptor.intermediate_format;
+        if output_path.extension() == Some(format.extension().as_ref()) {
+            ffmpeg_cmd.args(format.output_args(self.descriptor.jpeg_quality, &self.color));
+        }
+        ffmpeg_cmd.args(&self.descriptor.extra_output_args);
+        ffmpeg_cmd.args(["-y", output_path_str]);
+        ffmpeg_cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        Ok(ffmpeg_cmd)
+    }
+
+    /// Builds the command that renders every frame to the extraction directory, along with the script that
+    /// drives it, which must outlive the command
+    fn single_pass_command(&self, extraction_path: &Path) -> Result<(Command, SendCmdScript)> {
+        let script = SendCmdScript::new(&self.descriptor, &self.input_filename)?;
+        let ffmpeg_cmd = self.single_pass_command_with_script(extraction_path, &script.path)?;
+        Ok((ffmpeg_cmd, script))
+    }
+
+    /// Builds the command that renders the given frames in one process, numbered consecutively in the extraction
+    /// directory, along with the script that drives it
+    fn single_pass_sample_command(
+        &self,
+        extraction_path: &Path,
+        frames: &[usize],
+    ) -> Result<(Command, SendCmdScript)> {
+        let script = SendCmdScript::with_frames(&self.descriptor, &self.input_filename, frames)?;
+        let descriptor = ExtractFramesDescriptor {
+            frame_count: frames.len(),
+            ..self.descriptor.as_ref().clone()
+        };
+        let mut ffmpeg_cmd = single_pass_command(
+            self.input_path_str,
+            SinglePassOutput::Directory(extraction_path, &self.color),
+            &descriptor,
+            self.output_resolution,
+            &script.path,
+        )?;
+        ffmpeg_cmd.stderr(Stdio::piped());
+        Ok((ffmpeg_cmd, script))
+    }
+
+    /// Builds the command that renders every frame to the extraction directory, driven by an existing script
+    fn single_pass_command_with_script(
+        &self,
+        extraction_path: &Path,
+        script_path: &Path,
+    ) -> Result<Command> {
+        let mut ffmpeg_cmd = single_pass_command(
+            self.input_path_str,
+            SinglePassOutput::Directory(extraction_path, &self.color),
+            &self.descriptor,
+            self.output_resolution,
+            script_path,
+        )?;
+        ffmpeg_cmd.stderr(Stdio::piped());
+        Ok(ffmpeg_cmd)
+    }
+}
+
+pub fn extract_frames(
+    input_path: &Path,
+    extraction_path: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<ExtractReport> {
+    extract_frames_with_executor(
+        input_path,
+        extraction_path,
+        descriptor,
+        progress_handler,
+        cancellation_token,
+        &SystemExecutor,
+    )
+}
+
+/// Renders a single frame of the extraction to `output_path`, whose extension picks the image format
+///
+/// Rendering one frame is a quick way to dial in the field of view and pitch before extracting every frame.
+pub fn extract_frame(
+    input_path: &Path,
+    output_path: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    frame: usize,
+) -> Result<()> {
+    retry::with_retries(|| {
+        let input = ffprobe_info(input_path, &SystemExecutor)?;
+        let extraction = Extraction::new(input_path, descriptor, &input)?;
+        let mut ffmpeg_cmd = extraction.frame_command(frame, output_path)?;
+        let mut ffmpeg_child = spawn(&SystemExecutor, &mut ffmpeg_cmd)?;
+        let stderr = read_stderr(ffmpeg_child.as_mut());
+        let status = ffmpeg_child.wait()?;
+        if !status.success() {
+            return Err(DragonflyError::FfmpegFailed {
+                command: command_line(&ffmpeg_cmd),
+                status,
+                stderr,
+            });
+        }
+        Ok(())
+    })
+}
+
+/// Extracts frames like [`extract_frames`], starting ffmpeg and ffprobe with the given executor
+pub fn extract_frames_with_executor(
+    input_path: &Path,
+    extraction_path: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    executor: &dyn Executor,
+) -> Result<ExtractReport> {
+    let extraction_start = Instant::now();
+    let progress_handler = progress_handler.as_ref();
+    // Iterating on encode settings reruns the same extraction, which can reuse the frames it already rendered
+    let input_hash = manifest::input_hash(input_path);
+    if let Some(report) = manifest::reuse(
+        input_hash.as_deref(),
+        extraction_path,
+        descriptor,
+        progress_handler,
+    ) {
+        return Ok(ExtractReport {
+            duration: extraction_start.elapsed(),
+            ..report
+        });
+    }
+    // The frames are about to change, so the manifest no longer describes them
+    fs::remove_file(extraction_path.join(MANIFEST_FILE)).ok();
+    // A retried extraction resumes from the frames the failed attempt rendered
+    let report = retry::with_retries(|| {
+        render_frames(
+            input_path,
+            extraction_path,
+            descriptor,
+            progress_handler,
+            cancellation_token,
+            executor,
+            extraction_start,
+        )
+    })?;
+    ExtractionManifest {
+        input_hash,
+        descriptor: descriptor.clone(),
+        report: report.clone(),
+    }
+    .write(extraction_path)?;
+    Ok(report)
+}
+
+fn render_frames(
+    input_path: &Path,
+    extraction_path: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    progress_handler: Option<&impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    executor: &dyn Executor,
+    extraction_start: Instant,
+) -> Result<ExtractReport> {
+    let input = ffprobe_info(input_path, executor)?;
+    let extraction = Extraction::new(input_path, descriptor, &input)?;
+    let descriptor = &extraction.descriptor;
+    progress::report(
+        progress_handler,
+        ProgressEvent::ExtractStarted {
+            frame_count: descriptor.frame_count,
+        },
+    );
+    let (remaining, single_pass) =
+        match extraction.render_strategy(&input, extraction_path, progress_handler)? {
+            RenderStrategy::Ffmpeg {
+                remaining,
+                single_pass,
+            } => (remaining, single_pass),
+            RenderStrategy::Native => {
+                let frame_durations = native::extract_frames_native(
+                    &extraction,
+                    &input,
+                    extraction_path,
+                    progress_handler,
+                    cancellation_token,
+                    executor,
+                )
+                .map_err(|e| remove_frames_on_cancel(e, extraction_path))?;
+                progress::report(progress_handler, ProgressEvent::Finished);
+                return Ok(extraction.report(
+                    extraction_path,
+                    frame_durations,
+                    false,
+                    extraction_start.elapsed(),
+                ));
+            }
+        };
+    if single_pass {
+        match extract_frames_single_pass(
+            &extraction,
+            extraction_path,
+            progress_handler,
+            cancellation_token,
+            executor,
+        )
+        .map_err(|e| remove_frames_on_cancel(e, extraction_path))
+        {
+            Ok(frame_durations) => {
+                progress::report(progress_handler, ProgressEvent::Finished);
+                return Ok(extraction.report(
+                    extraction_path,
+                    frame_durations,
+                    true,
+                    extraction_start.elapsed(),
+                ));
+            }
+            Err(DragonflyError::Cancelled) => return Err(DragonflyError::Cancelled),
+            Err(e) => warn!(
+                "Single pass extraction failed ({e}), falling back to one ffmpeg process per frame"
+            ),
+        }
+    }
+
+    // Keep exactly j ffmpeg processes busy, launching the next frame as soon as any process finishes
+    let j = descriptor.thread_count();
+    let mut running: Vec<(usize, String, Box<dyn Process>)> = Vec::with_capacity(j);
+    let mut started = vec![extraction_start; descriptor.frame_count];
+    let mut frame_durations = vec![Duration::ZERO; descriptor.frame_count];
+    let mut on_finished = |frame: usize, started: Instant| {
+        frame_durations[frame] = started.elapsed();
+        progress::report(
+            progress_handler,
+            ProgressEvent::FrameExtracted {
+                index: frame,
+                path: Some(extraction.frame_path(extraction_path, frame)),
+                duration: started.elapsed(),
+            },
+        );
+    };
+    // Extract frames
+    for frame in remaining {
+        while running.len() >= j {
+            let finished = wait_for_any(&mut running, cancellation_token)
+                .map_err(|e| remove_frames_on_cancel(e, extraction_path))?;
+            on_finished(finished, started[finished]);
+        }
+        let mut ffmpeg_cmd = extraction.render_frame_command(
+            extraction_path,
+            frame,
+            &extraction.frame_path(extraction_path, frame),
+        )?;
+        let ffmpeg_child = spawn(executor, &mut ffmpeg_cmd)?;
+        started[frame] = Instant::now();
+        running.push((frame, command_line(&ffmpeg_cmd), ffmpeg_child));
+    }
+    while !running.is_empty() {
+        let finished = wait_for_any(&mut running, cancellation_token)
+            .map_err(|e| remove_frames_on_cancel(e, extraction_path))?;
+        on_finished(finished, started[finished]);
+    }
+    progress::report(progress_handler, ProgressEvent::Finished);
+    Ok(extraction.report(
+        extraction_path,
+        frame_durations,
+        false,
+        extraction_start.elapsed(),
+    ))
+}
+
+/// Waits for any of the running ffmpeg processes to exit and returns the frame it rendered
+///
+/// When a process fails or the token is cancelled, every running process is killed before returning the error.
+fn wait_for_any(
+    running: &mut Vec<(usize, String, Box<dyn Process>)>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<usize> {
+    let kill_all = |running: &mut Vec<(usize, String, Box<dyn Process>)>| {
+        for (_, _, mut child) in running.drain(..) {
+            child.kill().ok();
+            child.wait().ok();
+        }
+    };
+    loop {
+        if cancel::is_cancelled(cancellation_token) {
+            kill_all(running);
+            return Err(DragonflyError::Cancelled);
+        }
+        for i in 0..running.len() {
+            if let Some(status) = running[i].2.try_wait()? {
+                let (frame, command, mut child) = running.swap_remove(i);
                 if !status.success() {
-                    return Err(DragonflyError::FfmpegExtractFailed);
+                    kill_all(running);
+                    return Err(DragonflyError::FfmpegFailed {
+                        command,
+                        status,
+                        stderr: read_stderr(child.as_mut()),
+                    });
                 }
+                return Ok(frame);
+            }
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Reads the piped stderr of a child that has closed, or is about to close, its other outputs
+///
+/// ffmpeg only logs errors, so its stderr is small enough to read once the progress stream ends.
+fn read_stderr(child: &mut dyn Process) -> String {
+    let mut stderr = String::new();
+    if let Some(mut child_stderr) = child.take_stderr() {
+        child_stderr.read_to_string(&mut stderr).ok();
+    }
+    stderr
+}
+
+/// Returns the command line of a command, quoting arguments as needed so it can be pasted into a shell
+fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=,%+@".contains(c))
+            {
+                arg.into_owned()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
             }
-            tasks.clear();
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Removes the partially written frames when the error is a cancellation, passing the error through
+///
+/// The frames that finished rendering are kept, so extracting to the same directory again resumes from them.
+fn remove_frames_on_cancel(error: DragonflyError, extraction_path: &Path) -> DragonflyError {
+    if let DragonflyError::Cancelled = error {
+        let partial_frames = fs::read_dir(extraction_path)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| is_frame_file_name(&entry.file_name().to_string_lossy()))
+            .filter(|entry| !is_complete_frame(&entry.path()));
+        for frame in partial_frames {
+            fs::remove_file(frame.path()).ok();
+        }
+    }
+    error
+}
+
+/// Renders every frame with a single ffmpeg process by looping the still input and driving the v360 pose
+/// of each frame with sendcmd
+///
+/// Returns the time spent rendering each frame. ffmpeg failing usually means the v360 filter does not support
+/// runtime commands.
+fn extract_frames_single_pass(
+    extraction: &Extraction,
+    extraction_path: &Path,
+    progress_handler: Option<&impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    executor: &dyn Executor,
+) -> Result<Vec<Duration>> {
+    let (mut ffmpeg_cmd, _script) = extraction.single_pass_command(extraction_path)?;
+    let mut ffmpeg_child = spawn(executor, &mut ffmpeg_cmd)?;
+    let stderr = ffmpeg_child.take_stderr().expect("piped stderr");
+    let extract_progress =
+        cancel::kill_on_cancel(&mut [ffmpeg_child.as_mut()], cancellation_token, || {
+            progress::read_extract_progress(
+                stderr,
+                extraction.descriptor.frame_count,
+                Some((extraction_path, extraction.descriptor.intermediate_format)),
+                progress_handler,
+            )
+        })?;
+    let status = ffmpeg_child.wait()?;
+    if cancel::is_cancelled(cancellation_token) {
+        return Err(DragonflyError::Cancelled);
+    }
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr: extract_progress.log().to_string(),
+        });
+    }
+    Ok(extract_progress.into_durations())
+}
+
+/// How the frames of an extraction are rendered, see [`Extraction::render_strategy`]
+enum RenderStrategy {
+    /// In process by the native backend, every frame again
+    Native,
+    /// By ffmpeg, in a single pass when `single_pass` is true and one process per remaining frame otherwise, or
+    /// when the single pass fails
+    Ffmpeg {
+        remaining: Vec<usize>,
+        single_pass: bool,
+    },
+}
+
+/// Where a single pass extraction writes its frames
+enum SinglePassOutput<'a> {
+    /// Numbered image files in the extraction directory, holding an input of the given color
+    Directory(&'a Path, &'a SourceColor),
+    /// Raw RGB frames written to stdout
+    Pipe,
+}
+
+/// A sendcmd script that sets the v360 pose of every frame, removed when dropped
+struct SendCmdScript {
+    path: PathBuf,
+}
+
+impl SendCmdScript {
+    fn new(descriptor: &ExtractFramesDescriptor, input_filename: &str) -> Result<Self> {
+        Self::write(&sendcmd_commands(descriptor, input_filename))
+    }
+
+    /// Writes a script that renders the poses of the given frames, one after another
+    fn with_frames(
+        descriptor: &ExtractFramesDescriptor,
+        input_filename: &str,
+        frames: &[usize],
+    ) -> Result<Self> {
+        Self::write(&sendcmd_frame_commands(
+            descriptor,
+            input_filename,
+            frames.iter().copied(),
+        ))
+    }
+
+    fn write(commands: &str) -> Result<Self> {
+        static SCRIPT_COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "com.jshrake.dragonfly-sendcmd-{}-{}.txt",
+            std::process::id(),
+            SCRIPT_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, commands)?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for SendCmdScript {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+/// Returns the sendcmd script that sets the v360 pose of every frame
+fn sendcmd_commands(descriptor: &ExtractFramesDescriptor, input_filename: &str) -> String {
+    sendcmd_frame_commands(descriptor, input_filename, 0..descriptor.frame_count)
+}
+
+/// Returns the sendcmd script that renders the pose of each given frame, in order, as consecutive output frames
+fn sendcmd_frame_commands(
+    descriptor: &ExtractFramesDescriptor,
+    input_filename: &str,
+    frames: impl Iterator<Item = usize>,
+) -> String {
+    // With an input frame rate of 1, output frame n is presented at n seconds
+    let mut commands = String::new();
+    for (time, frame) in frames.enumerate() {
+        let pose = descriptor.pose(frame);
+        commands.push_str(&format!(
+            "{time} v360@camera yaw {}, v360@camera pitch {}, v360@camera roll {}, v360@camera h_fov {}, v360@camera v_fov {}",
+            wrap_degrees(pose.yaw),
+            wrap_degrees(pose.pitch),
+            wrap_degrees(pose.roll),
+            pose.h_fov,
+            pose.v_fov,
+        ));
+        for (instance, _, text) in descriptor
+            .text_overlays(frame, input_filename, &pose)
+            .into_iter()
+            .filter(|(instance, text_overlay, _)| {
+                *instance == "debug" || text_overlay.is_animated()
+            })
+        {
+            // The argument is parsed as a token by sendcmd and then as an option string by drawtext
+            let option = format!("text={}", escape_special(&text, "':"));
+            commands.push_str(&format!(
+                ", drawtext@{instance} reinit {}",
+                escape_special(&option, "' ,;\t\n\r\x0c")
+            ));
+        }
+        commands.push_str(";\n");
+    }
+    commands
+}
+
+/// Builds the ffmpeg command that renders every frame of a still input in one process
+fn single_pass_command(
+    input_path_str: &str,
+    output: SinglePassOutput,
+    descriptor: &ExtractFramesDescriptor,
+    (output_width, output_height): (u32, u32),
+    script_path: &Path,
+) -> Result<Command> {
+    let script_path_str = script_path
+        .to_str()
+        .ok_or_else(|| DragonflyError::InvalidPathString(script_path.to_path_buf()))?;
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd.args([
+        // Quiet output
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-nostats",
+        // Machine readable progress on stderr, leaving stdout free for piped frames
+        "-progress",
+        "pipe:2",
+        // Filter threads used to render each frame
+        "-filter_threads",
+        &descriptor.thread_count().to_string(),
+        // Input file, repeated once per output frame
+        "-loop",
+        "1",
+        "-framerate",
+        "1",
+    ]);
+    ffmpeg_cmd.args(&descriptor.extra_input_args);
+    ffmpeg_cmd.args([
+        "-i",
+        input_path_str,
+        // Video filter arguments
+        // See https://ffmpeg.org/ffmpeg-filters.html#sendcmd_002c-asendcmd
+        "-vf",
+        &format!(
+            "sendcmd=f={},{}",
+            escape_filter_value(script_path_str),
+            descriptor.frame_filter_string(
+                0,
+                &input_filename(Path::new(input_path_str)),
+                output_width,
+                output_height
+            )?
+        ),
+        "-frames:v",
+        &descriptor.frame_count.to_string(),
+    ]);
+    if let SinglePassOutput::Directory(_, color) = output {
+        ffmpeg_cmd.args(
+            descriptor
+                .intermediate_format
+                .output_args(descriptor.jpeg_quality, color),
+        );
+    }
+    ffmpeg_cmd.args(&descriptor.extra_output_args);
+    match output {
+        SinglePassOutput::Directory(extraction_path, _) => {
+            let frame_path_template =
+                frame_path_template(extraction_path, descriptor.intermediate_format);
+            let frame_path_template_str = frame_path_template
+                .to_str()
+                .ok_or_else(|| DragonflyError::InvalidPathString(frame_path_template.clone()))?;
+            ffmpeg_cmd.args([
+                // Output files
+                // https://ffmpeg.org/ffmpeg-formats.html#image2-1
+                "-start_number",
+                "0",
+                "-f",
+                "image2",
+                "-y",
+                frame_path_template_str,
+            ]);
         }
-        if let Some(progress_callback) = progress_callback.as_ref() {
-            progress_callback(frame, descriptor.frame_count);
+        SinglePassOutput::Pipe => {
+            ffmpeg_cmd.args(["-f", "rawvideo", "-pix_fmt", "rgb24", "pipe:1"]);
+        }
+    }
+    Ok(ffmpeg_cmd)
+}
+
+/// Escapes a value, such as a file path, for use as a filter option inside a filtergraph
+///
+/// Values are unescaped twice: once when the filtergraph is split into filters and once when the filter
+/// options are parsed. See <https://ffmpeg.org/ffmpeg-filters.html#Notes-on-filtergraph-escaping>
+pub(crate) fn escape_filter_value(value: &str) -> String {
+    escape_special(&escape_special(value, "':"), "'[],;")
+}
+
+/// Escapes backslashes and the given special characters with a backslash
+fn escape_special(value: &str, special: &str) -> String {
+    value.chars().fold(String::new(), |mut escaped, c| {
+        if c == '\\' || special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        escaped
+    })
+}
+
+/// Returns the file name of the input, as shown by text overlays
+fn input_filename(input_path: &Path) -> String {
+    input_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Extracts frames from a still 360 image and streams them straight into the encoder without writing them to disk
+///
+/// Frames are rendered in a single ffmpeg pass, so video inputs and ffmpeg builds whose v360 filter does not
+/// support runtime commands are not supported.
+pub fn extract_and_encode(
+    input_path: &Path,
+    output_path: &Path,
+    extract_descriptor: &ExtractFramesDescriptor,
+    encode_descriptor: &EncodeFramesDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<ExitStatus> {
+    extract_and_encode_with_executor(
+        input_path,
+        output_path,
+        extract_descriptor,
+        encode_descriptor,
+        progress_handler,
+        cancellation_token,
+        &SystemExecutor,
+    )
+}
+
+/// Streams frames like [`extract_and_encode`], starting ffmpeg and ffprobe with the given executor
+pub fn extract_and_encode_with_executor(
+    input_path: &Path,
+    output_path: &Path,
+    extract_descriptor: &ExtractFramesDescriptor,
+    encode_descriptor: &EncodeFramesDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    executor: &dyn Executor,
+) -> Result<ExitStatus> {
+    let progress_handler = progress_handler.as_ref();
+    retry::with_retries(|| {
+        stream_frames(
+            input_path,
+            output_path,
+            extract_descriptor,
+            encode_descriptor,
+            progress_handler,
+            cancellation_token,
+            executor,
+        )
+    })
+}
+
+fn stream_frames(
+    input_path: &Path,
+    output_path: &Path,
+    extract_descriptor: &ExtractFramesDescriptor,
+    encode_descriptor: &EncodeFramesDescriptor,
+    progress_handler: Option<&impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    executor: &dyn Executor,
+) -> Result<ExitStatus> {
+    let input_path_str = input_path
+        .to_str()
+        .ok_or_else(|| DragonflyError::InvalidPathString(input_path.to_path_buf()))?;
+    let input = ffprobe_info(input_path, executor)?;
+    if input.video.is_some() {
+        return Err(DragonflyError::StreamingVideoInput);
+    }
+    let extract_descriptor = &match read_gpano(input_path) {
+        Some(gpano) => extract_descriptor.clone().with_gpano(&gpano),
+        None => extract_descriptor.clone(),
+    };
+    extract_descriptor.validate()?;
+    extract_descriptor.check_input_aspect(input.width, input.height);
+    let (output_width, output_height) =
+        extract_descriptor.output_resolution(input.width, input.height);
+    debug!("Output resolution {output_width}x{output_height}");
+    let encode_descriptor = encode_descriptor.with_preset()?;
+    if let Some(poster) = &encode_descriptor.poster {
+        // Streamed frames never reach the disk, so there is no frame to export
+        warn!("Skipping the poster {poster:?}, which needs extracted frames");
+    }
+    if extract_descriptor.alpha {
+        warn!("Streamed frames are RGB, so the alpha channel is dropped");
+    }
+    progress::report(
+        progress_handler,
+        ProgressEvent::ExtractStarted {
+            frame_count: extract_descriptor.frame_count,
+        },
+    );
+    let script = SendCmdScript::new(extract_descriptor, &input_filename(input_path))?;
+    let mut extract_cmd = single_pass_command(
+        input_path_str,
+        SinglePassOutput::Pipe,
+        extract_descriptor,
+        (output_width, output_height),
+        &script.path,
+    )?;
+    extract_cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut extract_child = spawn(executor, &mut extract_cmd)?;
+    let source = FrameSource::Pipe {
+        frame_count: extract_descriptor.frame_count,
+        width: output_width,
+        height: output_height,
+    };
+    let mut encode_cmd = encode_command(output_path, &source, &encode_descriptor)?;
+    // The encoder keeps pace with the extraction, so its own progress is discarded
+    encode_cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut encode_child = spawn(executor, &mut encode_cmd)?;
+    let mut frames = extract_child.take_stdout().expect("piped stdout");
+    let mut encode_stdin = encode_child.take_stdin().expect("piped stdin");
+    let stderr = extract_child.take_stderr().expect("piped stderr");
+    let extract_progress = thread::scope(|scope| {
+        // Frames are copied between the processes while the extraction progress is read on this thread,
+        // closing the encoder's stdin once the extraction finishes
+        scope.spawn(move || io::copy(&mut frames, &mut encode_stdin).ok());
+        cancel::kill_on_cancel(
+            &mut [extract_child.as_mut(), encode_child.as_mut()],
+            cancellation_token,
+            || {
+                progress::read_extract_progress(
+                    stderr,
+                    extract_descriptor.frame_count,
+                    None,
+                    progress_handler,
+                )
+            },
+        )
+    })?;
+    let extract_status = extract_child.wait()?;
+    let encode_stderr = read_stderr(encode_child.as_mut());
+    let encode_status = encode_child.wait()?;
+    if cancel::is_cancelled(cancellation_token) {
+        fs::remove_file(output_path).ok();
+        return Err(DragonflyError::Cancelled);
+    }
+    if !extract_status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&extract_cmd),
+            status: extract_status,
+            stderr: extract_progress.log().to_string(),
+        });
+    }
+    if !encode_status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&encode_cmd),
+            status: encode_status,
+            stderr: encode_stderr,
+        });
+    }
+    if encode_descriptor.spherical {
+        inject_spherical_metadata(output_path)?;
+    }
+    sprites::write_sprite_sheet(output_path, &encode_descriptor, executor)?;
+    progress::report(progress_handler, ProgressEvent::Finished);
+    Ok(encode_status)
+}
+
+/// The frames fed to the encoder
+enum FrameSource<'a> {
+    /// Frames previously extracted to a directory from an input of the given color
+    Directory {
+        path: &'a Path,
+        frame_count: usize,
+        color: SourceColor,
+    },
+    /// Raw RGB frames streamed to the encoder's stdin
+    Pipe {
+        frame_count: usize,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl FrameSource<'_> {
+    fn frame_count(&self) -> usize {
+        match self {
+            FrameSource::Directory { frame_count, .. } => *frame_count,
+            FrameSource::Pipe { frame_count, .. } => *frame_count,
+        }
+    }
+
+    /// Streamed frames are always 8 bit RGB
+    fn color(&self) -> &SourceColor {
+        lazy_static::lazy_static! {
+            static ref STREAMED: SourceColor = SourceColor::default();
+        }
+        match self {
+            FrameSource::Directory { color, .. } => color,
+            FrameSource::Pipe { .. } => &STREAMED,
+        }
+    }
+
+    /// Returns the ffmpeg input arguments that read the frames at the given frame rate
+    fn input_args(&self, input_frames_per_second: f32) -> Result<Vec<String>> {
+        let input_frames_per_second = input_frames_per_second.to_string();
+        match self {
+            FrameSource::Directory { path, .. } => {
+                let frame_path_template =
+                    frame_path_template(path, IntermediateFormat::of_extraction(path));
+                let frame_path_template_str = frame_path_template.to_str().ok_or_else(|| {
+                    DragonflyError::InvalidPathString(frame_path_template.clone())
+                })?;
+                Ok(vec![
+                    "-f".to_string(),
+                    "image2".to_string(),
+                    // Input FPS
+                    "-framerate".to_string(),
+                    input_frames_per_second,
+                    // Input directory path containing images
+                    "-i".to_string(),
+                    frame_path_template_str.to_string(),
+                ])
+            }
+            FrameSource::Pipe { width, height, .. } => Ok(vec![
+                "-f".to_string(),
+                "rawvideo".to_string(),
+                "-pix_fmt".to_string(),
+                "rgb24".to_string(),
+                "-video_size".to_string(),
+                format!("{width}x{height}"),
+                // Input FPS
+                "-framerate".to_string(),
+                input_frames_per_second,
+                // Frames streamed from the extraction process
+                "-i".to_string(),
+                "pipe:0".to_string(),
+            ]),
         }
     }
-    Ok(())
+}
+
+/// Returns the number of frames in the extraction directory, from its manifest when there is one
+fn extracted_frame_count(extraction_path: &Path) -> Result<usize> {
+    if let Ok(manifest) = ExtractionManifest::read(extraction_path) {
+        // The encoder reads the frames in index order, so every listed frame must be in place
+        let frame_count = manifest.report.frames.len();
+        let format = IntermediateFormat::of_extraction(extraction_path);
+        if let Some(missing) = (0..frame_count)
+            .map(|frame| frame_path(extraction_path, frame, format))
+            .find(|path| !path.is_file())
+        {
+            return Err(DragonflyError::MissingFrame(missing));
+        }
+        return Ok(frame_count);
+    }
+    // Other files, such as the frame fingerprints or a stray .DS_Store, would skew the input frame rate
+    Ok(fs::read_dir(extraction_path)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_frame_file_name(&entry.file_name().to_string_lossy()))
+        .filter(|entry| entry.file_type().ok().is_some_and(|ft| ft.is_file()))
+        .count())
 }
 
 pub fn encode_frames(
     output_path: &Path,
     extraction_path: &Path,
     descriptor: &EncodeFramesDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<ExitStatus> {
+    encode_frames_with_executor(
+        output_path,
+        extraction_path,
+        descriptor,
+        progress_handler,
+        cancellation_token,
+        &SystemExecutor,
+    )
+}
+
+/// Encodes frames like [`encode_frames`], starting ffmpeg with the given executor
+pub fn encode_frames_with_executor(
+    output_path: &Path,
+    extraction_path: &Path,
+    descriptor: &EncodeFramesDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    executor: &dyn Executor,
+) -> Result<ExitStatus> {
+    let progress_handler = progress_handler.as_ref();
+    retry::with_retries(|| {
+        encode_frames_once(
+            output_path,
+            extraction_path,
+            descriptor,
+            progress_handler,
+            cancellation_token,
+            executor,
+        )
+    })
+}
+
+fn encode_frames_once(
+    output_path: &Path,
+    extraction_path: &Path,
+    descriptor: &EncodeFramesDescriptor,
+    progress_handler: Option<&impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    executor: &dyn Executor,
 ) -> Result<ExitStatus> {
+    let descriptor = descriptor.with_preset()?;
+    let source = FrameSource::Directory {
+        path: extraction_path,
+        frame_count: extracted_frame_count(extraction_path)?,
+        color: SourceColor::of_extraction(extraction_path),
+    };
+    let mut ffmpeg_cmd = encode_command(output_path, &source, &descriptor)?;
+    progress::report(
+        progress_handler,
+        ProgressEvent::EncodeStarted {
+            frame_count: descriptor.output_frame_count(),
+        },
+    );
+    ffmpeg_cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut ffmpeg_child = spawn(executor, &mut ffmpeg_cmd)?;
+    let stdout = ffmpeg_child.take_stdout().expect("piped stdout");
+    cancel::kill_on_cancel(&mut [ffmpeg_child.as_mut()], cancellation_token, || {
+        progress::read_encode_progress(stdout, progress_handler)
+    })?;
+    let stderr = read_stderr(ffmpeg_child.as_mut());
+    let status = ffmpeg_child.wait()?;
+    if cancel::is_cancelled(cancellation_token) {
+        fs::remove_file(output_path).ok();
+        return Err(DragonflyError::Cancelled);
+    }
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr,
+        });
+    }
+    if descriptor.spherical {
+        inject_spherical_metadata(output_path)?;
+    }
+    if let Some(mut poster_cmd) = poster::poster_command(extraction_path, &descriptor)? {
+        let mut poster_child = spawn(executor, &mut poster_cmd)?;
+        let stderr = read_stderr(poster_child.as_mut());
+        let status = poster_child.wait()?;
+        if !status.success() {
+            return Err(DragonflyError::FfmpegFailed {
+                command: command_line(&poster_cmd),
+                status,
+                stderr,
+            });
+        }
+    }
+    sprites::write_sprite_sheet(output_path, &descriptor, executor)?;
+    progress::report(progress_handler, ProgressEvent::Finished);
+    Ok(status)
+}
+
+fn encode_command(
+    output_path: &Path,
+    source: &FrameSource,
+    descriptor: &EncodeFramesDescriptor,
+) -> Result<Command> {
     let ext = output_path
         .extension()
         .map(|e| e.to_str().expect("TODO"))
         .ok_or_else(|| DragonflyError::InvalidPathString(output_path.to_path_buf()))?;
+    if descriptor.spherical && !matches!(ext, "mp4" | "mov") {
+        return Err(DragonflyError::SphericalMetadata(format!(
+            "{ext} output is not supported"
+        )));
+    }
+    if descriptor.audio.is_some() && matches!(ext, "gif" | "webp" | "apng") {
+        return Err(DragonflyError::AudioNotSupported(ext.to_string()));
+    }
     match ext {
-        "gif" => encode_frames_to_gif(output_path, extraction_path, descriptor),
-        "mp4" => encode_frames_to_mp4(output_path, extraction_path, descriptor),
-        "webm" => encode_frames_to_webm(output_path, extraction_path, descriptor),
+        "gif" => encode_frames_to_gif(output_path, source, descriptor),
+        "mp4" | "mov" | "mkv" | "webm" => {
+            let encoder = descriptor.container_encoder(ext)?;
+            encode_frames_to_video(output_path, source, descriptor, encoder)
+        }
+        "webp" => {
+            let quality = descriptor.webp_quality.min(100).to_string();
+            encode_frames_to_animated_image(
+                output_path,
+                source,
+                descriptor,
+                &[
+                    "-c:v",
+                    "libwebp",
+                    "-quality",
+                    &quality,
+                    // Slowest and smallest
+                    "-compression_level",
+                    "6",
+                    // Loop forever
+                    "-loop",
+                    "0",
+                ],
+            )
+        }
+        // Mixed prediction gives the smallest output at the cost of encode time
+        "apng" => encode_frames_to_animated_image(
+            output_path,
+            source,
+            descriptor,
+            &[
+                "-c:v", "apng", "-pred", "mixed", "-plays", "0", "-f", "apng",
+            ],
+        ),
         _ => Err(DragonflyError::UnsupportedOutputFormat(ext.to_string())),
     }
 }
 
 fn encode_frames_to_gif(
     output_path: &Path,
-    extraction_path: &Path,
+    source: &FrameSource,
     descriptor: &EncodeFramesDescriptor,
-) -> Result<ExitStatus> {
+) -> Result<Command> {
     // Encode output
-    let frame_path_template = extraction_path.join("frame_%08d.jpg");
-    let frame_path_template_str = frame_path_template
-        .to_str()
-        .ok_or_else(|| DragonflyError::InvalidPathString(frame_path_template.clone()))?;
     let output_fps_string = descriptor.fps.to_string();
-    // If the user passed in a scale factor, use that. Otherwise, use the scale string as-is
-    let scale_filter_string = if let Ok(scale) = descriptor.scale.parse::<f32>() {
-        format!("scale=iw*{scale}:ih*{scale}")
-    } else {
-        format!("scale={}", &descriptor.scale)
-    };
     let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
     let output_path_str = output_path
         .to_str()
         .ok_or_else(|| DragonflyError::InvalidPathString(output_path.to_path_buf()))?;
-    let total_frame_count = fs::read_dir(extraction_path)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_type().ok().map_or(false, |ft| ft.is_file()))
-        .count();
+    let total_frame_count = source.frame_count();
     debug!("Total frame count {total_frame_count}");
-    let input_frames_per_second = total_frame_count as f32 / descriptor.length;
+    let played_frame_count = descriptor.played_frame_count(total_frame_count);
+    let input_frames_per_second = played_frame_count as f32 / descriptor.length;
+    let video_filter_string = descriptor.gif_filter_string(total_frame_count)?;
     ffmpeg_cmd.args([
         // Quiet output
         "-hide_banner",
         "-loglevel",
         "error",
         "-nostats",
-        "-f",
-        "image2",
-        // Input FPS
-        "-framerate",
-        input_frames_per_second.to_string().as_str(),
-        // Input directory path containing images
-        "-i",
-        frame_path_template_str,
+        // Machine readable progress on stdout
+        "-progress",
+        "pipe:1",
+    ]);
+    ffmpeg_cmd.args(&descriptor.extra_input_args);
+    ffmpeg_cmd.args(source.input_args(input_frames_per_second)?);
+    ffmpeg_cmd.args(descriptor.metadata_args());
+    ffmpeg_cmd.args(descriptor.muxer_args(output_path));
+    ffmpeg_cmd.args([
         "-vf",
-        scale_filter_string.as_str(),
+        video_filter_string.as_str(),
         // output framerate
         // https://trac.ffmpeg.org/wiki/ChangingFrameRate
         "-r",
         output_fps_string.as_str(),
+        // Loop forever
+        "-loop",
+        "0",
+    ]);
+    ffmpeg_cmd.args(&descriptor.extra_output_args);
+    ffmpeg_cmd.args([
         // Output file path
         "-y",
         output_path_str,
     ]);
-    debug!("Spawning command: {:?}", &ffmpeg_cmd);
-    let mut ffmpeg_child = ffmpeg_cmd.stdout(Stdio::piped()).spawn()?;
-    let status = ffmpeg_child.wait()?;
-    Ok(status)
+    Ok(ffmpeg_cmd)
 }
 
-fn encode_frames_to_mp4(
+fn encode_frames_to_animated_image(
     output_path: &Path,
-    extraction_path: &Path,
+    source: &FrameSource,
     descriptor: &EncodeFramesDescriptor,
-) -> Result<ExitStatus> {
+    codec_args: &[&str],
+) -> Result<Command> {
     // Encode output
-    let frame_path_template = extraction_path.join("frame_%08d.jpg");
-    let frame_path_template_str = frame_path_template
-        .to_str()
-        .ok_or_else(|| DragonflyError::InvalidPathString(frame_path_template.clone()))?;
     let output_fps_string = descriptor.fps.to_string();
-    // If the user passed in a scale factor, use that. Otherwise, use the scale string as-is
-    let scale_filter_string = if let Ok(scale) = descriptor.scale.parse::<f32>() {
-        format!("scale=iw*{scale}:ih*{scale}")
-    } else {
-        format!("scale={}", &descriptor.scale)
-    };
     let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
     let output_path_str = output_path
         .to_str()
         .ok_or_else(|| DragonflyError::InvalidPathString(output_path.to_path_buf()))?;
-    let total_frame_count = fs::read_dir(extraction_path)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_type().ok().map_or(false, |ft| ft.is_file()))
-        .count();
+    let total_frame_count = source.frame_count();
     debug!("Total frame count {total_frame_count}");
-    let input_frames_per_second = total_frame_count as f32 / descriptor.length;
+    let played_frame_count = descriptor.played_frame_count(total_frame_count);
+    let input_frames_per_second = played_frame_count as f32 / descriptor.length;
+    let video_filter_string = descriptor.video_filter_string(total_frame_count)?;
     ffmpeg_cmd.args([
         // Quiet output
         "-hide_banner",
         "-loglevel",
         "error",
         "-nostats",
-        // Input FPS
-        "-r",
-        input_frames_per_second.to_string().as_str(),
-        // Input directory path containing images
-        "-i",
-        frame_path_template_str,
-        // h264
-        "-c:v",
-        "libx264",
-        // preset
-        "-preset",
-        "slow",
-        // crf
-        "-crf",
-        "18",
-        // pixel format
-        "-pix_fmt",
-        "yuv420p",
-        // TODO: configurable
-        "-tune",
-        "stillimage",
-        // key frame the first and last frame
-        "-g",
-        &format!("{}", total_frame_count - 1),
-        // Filters
-        // - Frame interpolation/blending
-        // - Scaling
+        // Machine readable progress on stdout
+        "-progress",
+        "pipe:1",
+    ]);
+    ffmpeg_cmd.args(&descriptor.extra_input_args);
+    ffmpeg_cmd.args(source.input_args(input_frames_per_second)?);
+    ffmpeg_cmd.args(codec_args);
+    ffmpeg_cmd.args(descriptor.metadata_args());
+    ffmpeg_cmd.args(descriptor.muxer_args(output_path));
+    ffmpeg_cmd.args([
         "-vf",
-        scale_filter_string.as_str(),
+        video_filter_string.as_str(),
         // output framerate
         // https://trac.ffmpeg.org/wiki/ChangingFrameRate
         "-r",
         output_fps_string.as_str(),
+    ]);
+    ffmpeg_cmd.args(&descriptor.extra_output_args);
+    ffmpeg_cmd.args([
         // Output file path
         "-y",
         output_path_str,
     ]);
-    debug!("Spawning command: {:?}", &ffmpeg_cmd);
-    let mut ffmpeg_child = ffmpeg_cmd.stdout(Stdio::piped()).spawn()?;
-    let status = ffmpeg_child.wait()?;
-    Ok(status)
+    Ok(ffmpeg_cmd)
 }
 
-fn encode_frames_to_webm(
+/// Returns the audio codec arguments suited to the output container and video codec
+fn audio_codec_args(output_path: &Path, encoder: Encoder) -> [&'static str; 4] {
+    match (
+        output_path.extension().and_then(|e| e.to_str()),
+        encoder.codec(),
+    ) {
+        (Some("webm"), _) => ["-c:a", "libopus", "-b:a", "128k"],
+        // Editors expect uncompressed audio alongside intermediate codecs
+        (_, Codec::Prores | Codec::Dnxhr) => ["-c:a", "pcm_s16le", "-ar", "48000"],
+        _ => ["-c:a", "aac", "-b:a", "192k"],
+    }
+}
+
+fn encode_frames_to_video(
     output_path: &Path,
-    extraction_path: &Path,
+    source: &FrameSource,
     descriptor: &EncodeFramesDescriptor,
-) -> Result<ExitStatus> {
+    encoder: Encoder,
+) -> Result<Command> {
     // Encode output
-    let frame_path_template = extraction_path.join("frame_%08d.jpg");
-    let frame_path_template_str = frame_path_template
-        .to_str()
-        .ok_or_else(|| DragonflyError::InvalidPathString(frame_path_template.clone()))?;
-    //let output_fps_string = descriptor.fps.to_string();
-    // If the user passed in a scale factor, use that. Otherwise, use the scale string as-is
-    let scale_filter_string = if let Ok(scale) = descriptor.scale.parse::<f32>() {
-        format!("scale=iw*{scale}:ih*{scale}")
-    } else {
-        format!("scale={}", &descriptor.scale)
-    };
+    let output_fps_string = descriptor.fps.to_string();
     let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
     let output_path_str = output_path
         .to_str()
         .ok_or_else(|| DragonflyError::InvalidPathString(output_path.to_path_buf()))?;
-    let total_frame_count = fs::read_dir(extraction_path)?
-        .filter_map(|entry| entry.ok())
-        .filter(|entry| entry.file_type().ok().map_or(false, |ft| ft.is_file()))
-        .count();
+    let total_frame_count = source.frame_count();
     debug!("Total frame count {total_frame_count}");
-    let input_frames_per_second = total_frame_count as f32 / descriptor.length;
-    // https://trac.ffmpeg.org/wiki/Encode/VP9
+    let played_frame_count = descriptor.played_frame_count(total_frame_count);
+    let input_frames_per_second = played_frame_count as f32 / descriptor.length;
+    let output_color = color::OutputColor::new(descriptor, source.color());
+    // Converted with the tagged matrix, so players do not show the video washed out
+    let mut video_filter_string = format!(
+        "{},{}",
+        descriptor.video_filter_string(total_frame_count)?,
+        output_color.scale_filter()
+    );
+    if let Some(suffix) = encoder.filter_suffix() {
+        video_filter_string = format!("{video_filter_string},{suffix}");
+    }
     ffmpeg_cmd.args([
         // Quiet output
         "-hide_banner",
         "-loglevel",
         "error",
         "-nostats",
-        // Input FPS
-        "-r",
-        input_frames_per_second.to_string().as_str(),
-        // Input directory path containing images
-        "-i",
-        frame_path_template_str,
-        "-c:v",
-        "libvpx-vp9",
-        // crf
-        "-crf",
-        "30",
-        "-b:v",
-        "0",
+        // Machine readable progress on stdout
+        "-progress",
+        "pipe:1",
+    ]);
+    ffmpeg_cmd.args(encoder.global_args());
+    ffmpeg_cmd.args(&descriptor.extra_input_args);
+    ffmpeg_cmd.args(source.input_args(input_frames_per_second)?);
+    if let Some(audio_path) = &descriptor.audio {
+        // Loop the audio forever and let -t trim it to the video length
+        ffmpeg_cmd.args(["-stream_loop", "-1", "-i"]);
+        ffmpeg_cmd.arg(audio_path);
+        ffmpeg_cmd.args(["-map", "0:v", "-map", "1:a"]);
+        ffmpeg_cmd.args(audio_codec_args(output_path, encoder));
+        if descriptor.audio_fade_out > 0.0 {
+            let fade_out = descriptor.audio_fade_out.min(descriptor.total_length());
+            ffmpeg_cmd.args([
+                "-af".to_string(),
+                format!(
+                    "afade=t=out:st={}:d={}",
+                    descriptor.total_length() - fade_out,
+                    fade_out
+                ),
+            ]);
+        }
+        ffmpeg_cmd.args(["-t".to_string(), descriptor.total_length().to_string()]);
+    }
+    // key frame the first and last frame
+    ffmpeg_cmd.args(encoder.codec_args(
+        descriptor,
+        played_frame_count.saturating_sub(1).max(1),
+        source.color(),
+    )?);
+    ffmpeg_cmd.args(output_color.args());
+    // QuickTime and Safari only play HEVC in mp4 when tagged as hvc1 rather than ffmpeg's default hev1
+    if encoder.codec() == Codec::Hevc && output_path.extension().is_some_and(|e| e == "mp4") {
+        ffmpeg_cmd.args(["-tag:v", "hvc1"]);
+    }
+    ffmpeg_cmd.args(descriptor.metadata_args());
+    ffmpeg_cmd.args(descriptor.muxer_args(output_path));
+    ffmpeg_cmd.args([
         // Filters
         // - Frame interpolation/blending
         // - Scaling
         "-vf",
-        scale_filter_string.as_str(),
+        video_filter_string.as_str(),
         // output framerate
         // https://trac.ffmpeg.org/wiki/ChangingFrameRate
-        //"-r",
-        //output_fps_string.as_str(),
+        "-r",
+        output_fps_string.as_str(),
+    ]);
+    ffmpeg_cmd.args(&descriptor.extra_output_args);
+    ffmpeg_cmd.args([
         // Output file path
         "-y",
         output_path_str,
     ]);
-    debug!("Spawning command: {:?}", &ffmpeg_cmd);
-    let mut ffmpeg_child = ffmpeg_cmd.stdout(Stdio::piped()).spawn()?;
-    let status = ffmpeg_child.wait()?;
-    Ok(status)
+    Ok(ffmpeg_cmd)
 }
@@ -0,0 +1,123 @@
+use crate::{
+    command_line, extracted_frame_count, ffprobe_info, frame_path, read_stderr, spawn,
+    DragonflyError, IntermediateFormat, Result, SystemExecutor, FFMPEG_BINARY_PATH,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// How closely the last frame leads back into the first, measured as SSIM where 1 is identical and as PSNR in
+/// decibels, where identical frames are infinite
+///
+/// A seamless loop changes as much across the seam as between any two neighboring frames. A seam well below the
+/// step between neighbors pops when the video loops, which blending the loop at encode time hides.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct LoopQuality {
+    /// Similarity of the last frame and the first frame
+    pub seam_ssim: f64,
+    /// Mean similarity of the first two frames and of the last two frames
+    pub step_ssim: f64,
+    pub seam_psnr: f64,
+    pub step_psnr: f64,
+}
+
+impl LoopQuality {
+    /// Returns true if the seam differs noticeably more than neighboring frames do
+    pub fn pops(&self) -> bool {
+        self.seam_ssim < self.step_ssim - 0.01
+    }
+
+    /// Compares the seam of a sequence of `frame_count` frames, where `frame` returns the path holding the given
+    /// frame, along with its index when the path is a video
+    fn measure(
+        frame_count: usize,
+        frame: impl Fn(usize) -> (String, Option<usize>),
+    ) -> Result<Self> {
+        let last = frame_count - 1;
+        let similarity = |a: usize, b: usize| similarity(frame(a), frame(b));
+        let (seam_ssim, seam_psnr) = similarity(last, 0)?;
+        let (first_ssim, first_psnr) = similarity(0, 1)?;
+        let (last_ssim, last_psnr) = similarity(last - 1, last)?;
+        Ok(Self {
+            seam_ssim,
+            step_ssim: (first_ssim + last_ssim) / 2.0,
+            seam_psnr,
+            step_psnr: (first_psnr + last_psnr) / 2.0,
+        })
+    }
+}
+
+/// Measures how seamlessly the frames extracted to `extraction_path` loop
+pub fn loop_quality(extraction_path: &Path) -> Result<LoopQuality> {
+    let frame_count = extracted_frame_count(extraction_path)?;
+    if frame_count < 3 {
+        return Err(DragonflyError::NoFrames(extraction_path.to_path_buf()));
+    }
+    let format = IntermediateFormat::of_extraction(extraction_path);
+    LoopQuality::measure(frame_count, |frame| {
+        let path = frame_path(extraction_path, frame, format);
+        (path.to_string_lossy().to_string(), None)
+    })
+}
+
+/// Measures how seamlessly an encoded video loops, comparing its last frame with its first
+pub fn verify_loop(video_path: &Path) -> Result<LoopQuality> {
+    let video = ffprobe_info(video_path, &SystemExecutor)?
+        .video
+        .filter(|video| video.frame_count >= 3)
+        .ok_or_else(|| DragonflyError::NotAVideo(video_path.to_path_buf()))?;
+    let video_path_str = video_path
+        .to_str()
+        .ok_or_else(|| DragonflyError::InvalidPathString(video_path.to_path_buf()))?;
+    LoopQuality::measure(video.frame_count, |frame| {
+        (video_path_str.to_string(), Some(frame))
+    })
+}
+
+/// Returns the SSIM and PSNR of two frames, each given as a path and the index of the frame in a video
+fn similarity(a: (String, Option<usize>), b: (String, Option<usize>)) -> Result<(f64, f64)> {
+    let select = |(_, index): &(String, Option<usize>)| {
+        index
+            .map(|index| format!("select=eq(n\\,{index}),"))
+            .unwrap_or_default()
+    };
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd
+        .args(["-hide_banner", "-nostats", "-i", &a.0, "-i", &b.0])
+        .args([
+            "-filter_complex",
+            &format!(
+                "[0]{}setpts=PTS-STARTPTS,split[a0][a1];\
+                [1]{}setpts=PTS-STARTPTS,split[b0][b1];\
+                [a0][b0]ssim;[a1][b1]psnr",
+                select(&a),
+                select(&b)
+            ),
+            "-frames:v",
+            "1",
+            "-f",
+            "null",
+            "-",
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut ffmpeg_child = spawn(&SystemExecutor, &mut ffmpeg_cmd)?;
+    let stderr = read_stderr(ffmpeg_child.as_mut());
+    let status = ffmpeg_child.wait()?;
+    // The summaries read SSIM Y:0.98 (17.8) ... All:0.987 (18.9) and PSNR y:35.1 ... average:35.9 min:...
+    let summary = |key: &str| {
+        stderr
+            .lines()
+            .filter_map(|line| line.split_once(key))
+            .filter_map(|(_, rest)| rest.split_whitespace().next()?.parse::<f64>().ok())
+            .next_back()
+    };
+    match (summary(" All:"), summary(" average:")) {
+        (Some(ssim), Some(psnr)) if status.success() => Ok((ssim, psnr)),
+        _ => Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr,
+        }),
+    }
+}
@@ -0,0 +1,105 @@
+use crate::progress::{self, ProgressEvent, ProgressHandler};
+use crate::{fnv1a, is_complete_frame, ExtractFramesDescriptor, ExtractReport, Result};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+/// The file in an extraction directory that describes its frames and how they were extracted
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Describes a completed extraction: every frame file with its camera pose, and the parameters that rendered
+/// them, so an identical extraction into the same directory can reuse the frames
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExtractionManifest {
+    /// Hash of the contents of the input file, missing when the input is not a readable file
+    pub input_hash: Option<String>,
+    pub descriptor: ExtractFramesDescriptor,
+    pub report: ExtractReport,
+}
+
+impl ExtractionManifest {
+    /// Reads the manifest written to an extraction directory by [`extract_frames`](crate::extract_frames)
+    pub fn read(extraction_path: &Path) -> Result<Self> {
+        let manifest = fs::read(extraction_path.join(MANIFEST_FILE))?;
+        Ok(serde_json::from_slice(&manifest)?)
+    }
+
+    pub(crate) fn write(&self, extraction_path: &Path) -> Result<()> {
+        fs::write(
+            extraction_path.join(MANIFEST_FILE),
+            serde_json::to_vec_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Returns the 64 bit FNV-1a hash of the input file contents, or None when the input is not a readable file
+pub(crate) fn input_hash(input_path: &Path) -> Option<String> {
+    let mut file = fs::File::open(input_path).ok()?;
+    let mut hash = crate::FNV_OFFSET_BASIS;
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buffer).ok()? {
+            0 => return Some(format!("{hash:016x}")),
+            n => hash = fnv1a(hash, &buffer[..n]),
+        }
+    }
+}
+
+/// Returns the descriptor without the settings that do not change the rendered frames
+fn normalized(descriptor: &ExtractFramesDescriptor) -> Option<serde_json::Value> {
+    serde_json::to_value(ExtractFramesDescriptor {
+        j: 0,
+        per_frame: false,
+        no_disk_check: false,
+        ..descriptor.clone()
+    })
+    .ok()
+}
+
+/// Returns the report of an identical earlier extraction into the directory if all of its frames are still in
+/// place, reporting each of them as extracted
+pub(crate) fn reuse(
+    input_hash: Option<&str>,
+    extraction_path: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    progress_handler: Option<&impl ProgressHandler>,
+) -> Option<ExtractReport> {
+    let manifest = ExtractionManifest::read(extraction_path).ok()?;
+    let is_identical = input_hash.is_some()
+        && manifest.input_hash.as_deref() == input_hash
+        && normalized(&manifest.descriptor) == normalized(descriptor)
+        && manifest
+            .report
+            .frames
+            .iter()
+            .all(|frame| is_complete_frame(&frame.path));
+    if !is_identical {
+        return None;
+    }
+    info!(
+        "Reusing {} frames from an identical extraction",
+        manifest.report.frames.len()
+    );
+    progress::report(
+        progress_handler,
+        ProgressEvent::ExtractStarted {
+            frame_count: manifest.report.frames.len(),
+        },
+    );
+    for frame in &manifest.report.frames {
+        progress::report(
+            progress_handler,
+            ProgressEvent::FrameExtracted {
+                index: frame.index,
+                path: Some(frame.path.clone()),
+                duration: Duration::ZERO,
+            },
+        );
+    }
+    progress::report(progress_handler, ProgressEvent::Finished);
+    Some(manifest.report)
+}
@@ -0,0 +1,99 @@
+use crate::{
+    command_line, extracted_frame_count, frame_path_template, read_stderr, spawn, DragonflyError,
+    IntermediateFormat, Result, SystemExecutor, FFMPEG_BINARY_PATH,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// A grid of evenly spaced extracted frames, giving an overview of the whole rotation in a single image
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct MontageDescriptor {
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Tile every Nth frame, defaults to spacing the tiles evenly over the rotation",
+            long
+        )
+    )]
+    pub every: Option<usize>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Number of tiles when --every is not given",
+            long,
+            default_value = "24"
+        )
+    )]
+    pub tiles: usize,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Number of tiles in each row", long, default_value = "6")
+    )]
+    pub columns: usize,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Width of each tile in pixels", long, default_value = "320")
+    )]
+    pub tile_width: u32,
+}
+
+impl MontageDescriptor {
+    /// Returns the spacing between tiled frames and the number of rows for the given number of frames
+    fn layout(&self, frame_count: usize) -> (usize, usize) {
+        let every = self
+            .every
+            .unwrap_or_else(|| frame_count.div_ceil(self.tiles.max(1)))
+            .max(1);
+        let tiles = frame_count.div_ceil(every);
+        (every, tiles.div_ceil(self.columns.max(1)).max(1))
+    }
+}
+
+/// Tiles the frames extracted to `extraction_path` into a single image at `output_path`
+pub fn montage(
+    extraction_path: &Path,
+    output_path: &Path,
+    descriptor: &MontageDescriptor,
+) -> Result<()> {
+    let frame_count = extracted_frame_count(extraction_path)?;
+    if frame_count == 0 {
+        return Err(DragonflyError::NoFrames(extraction_path.to_path_buf()));
+    }
+    let (every, rows) = descriptor.layout(frame_count);
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd
+        .args(["-hide_banner", "-loglevel", "error", "-nostats", "-i"])
+        .arg(frame_path_template(
+            extraction_path,
+            IntermediateFormat::of_extraction(extraction_path),
+        ))
+        .args([
+            "-vf",
+            &format!(
+                "select='not(mod(n\\,{every}))',scale={}:-2,tile={}x{rows}",
+                descriptor.tile_width,
+                descriptor.columns.max(1)
+            ),
+            "-frames:v",
+            "1",
+            "-update",
+            "1",
+            "-y",
+        ])
+        .arg(output_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut ffmpeg_child = spawn(&SystemExecutor, &mut ffmpeg_cmd)?;
+    let stderr = read_stderr(ffmpeg_child.as_mut());
+    let status = ffmpeg_child.wait()?;
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,431 @@
+use crate::progress::{self, ProgressEvent, ProgressHandler};
+use crate::{
+    cancel, command_line, frame_path, frame_path_template, read_stderr, spawn, CameraPose,
+    CancellationToken, DragonflyError, Executor, ExtractFramesDescriptor, Extraction, InputInfo,
+    Interpolation, OutputProjection, Result, StereoLayout, FFMPEG_BINARY_PATH,
+};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use strum::{Display, EnumString};
+
+/// Renders the extracted frames
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Default, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// The v360 filter of ffmpeg
+    #[default]
+    Ffmpeg,
+    /// Reproject in-process, with one ffmpeg process to decode the input and another to write the frames
+    Native,
+    /// The remap filter of ffmpeg, driven by pixel maps computed once for each camera orientation
+    Remap,
+}
+
+/// An 8 bit RGB image held in memory, with rows stored top to bottom
+#[derive(Clone, Debug, PartialEq)]
+pub struct RgbImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl RgbImage {
+    /// Returns a black image
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 3],
+        }
+    }
+
+    /// Renders the view of this equirectangular image seen by the camera `pose`, the same way the v360 filter
+    /// does for the projection and interpolation of `descriptor`
+    pub fn reproject(
+        &self,
+        descriptor: &ExtractFramesDescriptor,
+        pose: &CameraPose,
+        width: u32,
+        height: u32,
+    ) -> Result<RgbImage> {
+        check_projection(Backend::Native, descriptor)?;
+        let wraps = descriptor.ih_fov >= 360.0;
+        let mut output = RgbImage::new(width, height);
+        for_each_source(
+            descriptor,
+            pose,
+            (width, height),
+            (self.width, self.height),
+            |i, j, x, y| {
+                let rgb = match descriptor.interpolation {
+                    Interpolation::Near => self.pixel(x.round() as i64, y.round() as i64, wraps),
+                    Interpolation::Lanczos => self.lanczos(x, y, wraps),
+                    _ => self.bilinear(x, y, wraps),
+                };
+                let offset = (j as usize * width as usize + i as usize) * 3;
+                for (c, value) in rgb.iter().enumerate() {
+                    output.data[offset + c] = value.round().clamp(0.0, 255.0) as u8;
+                }
+            },
+        );
+        Ok(output)
+    }
+
+    /// Returns the pixel at the given coordinates, wrapping around the 360 degree seam or clamping to the edge
+    fn pixel(&self, x: i64, y: i64, wraps: bool) -> [f32; 3] {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let x = if wraps {
+            x.rem_euclid(width)
+        } else {
+            x.clamp(0, width - 1)
+        };
+        let y = y.clamp(0, height - 1);
+        let offset = ((y * width + x) * 3) as usize;
+        [
+            self.data[offset] as f32,
+            self.data[offset + 1] as f32,
+            self.data[offset + 2] as f32,
+        ]
+    }
+
+    fn bilinear(&self, x: f32, y: f32, wraps: bool) -> [f32; 3] {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let mut rgb = [0.0; 3];
+        for (xi, yi, weight) in [
+            (x0, y0, (1.0 - dx) * (1.0 - dy)),
+            (x0 + 1, y0, dx * (1.0 - dy)),
+            (x0, y0 + 1, (1.0 - dx) * dy),
+            (x0 + 1, y0 + 1, dx * dy),
+        ] {
+            let pixel = self.pixel(xi, yi, wraps);
+            for c in 0..3 {
+                rgb[c] += pixel[c] * weight;
+            }
+        }
+        rgb
+    }
+
+    /// Samples with a 6x6 Lanczos kernel, normalized so the weights sum to 1
+    fn lanczos(&self, x: f32, y: f32, wraps: bool) -> [f32; 3] {
+        const A: i64 = 3;
+        let kernel = |t: f32| {
+            if t.abs() < 1e-6 {
+                1.0
+            } else if t.abs() < A as f32 {
+                let pt = PI * t;
+                A as f32 * pt.sin() * (pt / A as f32).sin() / (pt * pt)
+            } else {
+                0.0
+            }
+        };
+        let (x0, y0) = (x.floor() as i64, y.floor() as i64);
+        let mut rgb = [0.0; 3];
+        let mut total = 0.0;
+        for yi in y0 - A + 1..=y0 + A {
+            let wy = kernel(y - yi as f32);
+            for xi in x0 - A + 1..=x0 + A {
+                let weight = wy * kernel(x - xi as f32);
+                let pixel = self.pixel(xi, yi, wraps);
+                for c in 0..3 {
+                    rgb[c] += pixel[c] * weight;
+                }
+                total += weight;
+            }
+        }
+        rgb.map(|value| value / total)
+    }
+}
+
+/// Calls `f` with the column and row of every pixel of a `output_size` frame seen by the camera `pose`, along
+/// with the coordinates of the point of the `input_size` equirectangular input it sees
+pub(crate) fn for_each_source(
+    descriptor: &ExtractFramesDescriptor,
+    pose: &CameraPose,
+    (width, height): (u32, u32),
+    (input_width, input_height): (u32, u32),
+    mut f: impl FnMut(u32, u32, f32, f32),
+) {
+    let rotation = rotation(pose);
+    let (ih_range, iv_range) = (
+        descriptor.ih_fov.to_radians() / 2.0,
+        descriptor.iv_fov.to_radians() / 2.0,
+    );
+    for j in 0..height {
+        for i in 0..width {
+            // Normalized output coordinates of the pixel center, in the range [-1, 1]
+            let u = (2 * i + 1) as f32 / width as f32 - 1.0;
+            let v = (2 * j + 1) as f32 / height as f32 - 1.0;
+            let d = multiply(&rotation, direction(descriptor.projection, pose, u, v));
+            let phi = d[0].atan2(d[2]);
+            let theta = (d[1] / length(d)).clamp(-1.0, 1.0).asin();
+            let x = (phi / ih_range + 1.0) * input_width as f32 / 2.0 - 0.5;
+            let y = (theta / iv_range + 1.0) * input_height as f32 / 2.0 - 0.5;
+            f(i, j, x, y);
+        }
+    }
+}
+
+/// Returns an error naming the first setting of the descriptor that only the ffmpeg backend renders
+///
+/// The remap backend still runs the filters that follow the reprojection, such as text overlays.
+pub(crate) fn check_supported(
+    backend: Backend,
+    descriptor: &ExtractFramesDescriptor,
+    input: &InputInfo,
+) -> Result<()> {
+    let unsupported = if input.video.is_some() {
+        Some("video inputs".to_string())
+    } else if backend == Backend::Remap && input.width.max(input.height) > u16::MAX as u32 {
+        // The maps store input coordinates as 16 bit samples
+        Some(format!("inputs larger than {} pixels", u16::MAX))
+    } else if descriptor.alpha {
+        Some("alpha".to_string())
+    } else if descriptor.stereo != StereoLayout::Mono {
+        Some("stereo inputs".to_string())
+    } else if descriptor.horizon_pitch.is_some() || descriptor.horizon_roll.is_some() {
+        Some("leveling the horizon".to_string())
+    } else if descriptor.nadir.is_enabled() {
+        Some("the nadir patch".to_string())
+    } else if backend == Backend::Native
+        && (descriptor.text_overlay.is_some() || descriptor.debug_overlay)
+    {
+        Some("text overlays".to_string())
+    } else if backend == Backend::Native && descriptor.extra_vf.is_some() {
+        Some("extra filters".to_string())
+    } else {
+        None
+    };
+    match unsupported {
+        Some(option) => Err(DragonflyError::UnsupportedByBackend(backend, option)),
+        None => check_projection(backend, descriptor),
+    }
+}
+
+/// The remap filter always samples the nearest pixel, so only the native backend checks the interpolation
+fn check_projection(backend: Backend, descriptor: &ExtractFramesDescriptor) -> Result<()> {
+    match descriptor.projection {
+        OutputProjection::Flat
+        | OutputProjection::Stereographic
+        | OutputProjection::Equirectangular => {}
+        projection => {
+            return Err(DragonflyError::UnsupportedByBackend(
+                backend,
+                format!("the {projection} projection"),
+            ))
+        }
+    }
+    match descriptor.interpolation {
+        _ if backend == Backend::Remap => Ok(()),
+        Interpolation::Near | Interpolation::Linear | Interpolation::Lanczos => Ok(()),
+        ref interpolation => Err(DragonflyError::UnsupportedByBackend(
+            backend,
+            format!("{interpolation} interpolation"),
+        )),
+    }
+}
+
+/// Returns the direction the camera sees at normalized output coordinates, as v360 computes it for each projection
+fn direction(projection: OutputProjection, pose: &CameraPose, u: f32, v: f32) -> [f32; 3] {
+    match projection {
+        OutputProjection::Stereographic => {
+            let x = u * (pose.h_fov.min(359.0).to_radians() / 4.0).tan();
+            let y = v * (pose.v_fov.min(359.0).to_radians() / 4.0).tan();
+            let r = x.hypot(y);
+            if r == 0.0 {
+                return [0.0, 0.0, 1.0];
+            }
+            let theta = 2.0 * r.atan();
+            [x / r * theta.sin(), y / r * theta.sin(), theta.cos()]
+        }
+        OutputProjection::Equirectangular => {
+            let phi = u * pose.h_fov.to_radians() / 2.0;
+            let theta = v * pose.v_fov.to_radians() / 2.0;
+            [
+                theta.cos() * phi.sin(),
+                theta.sin(),
+                theta.cos() * phi.cos(),
+            ]
+        }
+        _ => [
+            u * (pose.h_fov.to_radians() / 2.0).tan(),
+            v * (pose.v_fov.to_radians() / 2.0).tan(),
+            1.0,
+        ],
+    }
+}
+
+/// Returns the rotation applying the roll, then the pitch, then the yaw, matching the default v360 rotation order
+fn rotation(pose: &CameraPose) -> [[f32; 3]; 3] {
+    let (sy, cy) = pose.yaw.to_radians().sin_cos();
+    let (sp, cp) = pose.pitch.to_radians().sin_cos();
+    let (sr, cr) = pose.roll.to_radians().sin_cos();
+    let yaw = [[cy, 0.0, sy], [0.0, 1.0, 0.0], [-sy, 0.0, cy]];
+    let pitch = [[1.0, 0.0, 0.0], [0.0, cp, -sp], [0.0, sp, cp]];
+    let roll = [[cr, -sr, 0.0], [sr, cr, 0.0], [0.0, 0.0, 1.0]];
+    let product = |a: [[f32; 3]; 3], b: [[f32; 3]; 3]| {
+        let mut m = [[0.0; 3]; 3];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| a[r][k] * b[k][c]).sum();
+            }
+        }
+        m
+    };
+    product(product(yaw, pitch), roll)
+}
+
+fn multiply(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+fn length(v: [f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+/// Decodes the input with ffmpeg into memory
+fn decode(extraction: &Extraction, input: &InputInfo, executor: &dyn Executor) -> Result<RgbImage> {
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd.args(["-hide_banner", "-loglevel", "error", "-nostats"]);
+    ffmpeg_cmd.args(&extraction.descriptor.extra_input_args);
+    ffmpeg_cmd
+        .args(["-i", extraction.input_path_str])
+        .args([
+            "-frames:v",
+            "1",
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgb24",
+            "pipe:1",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut ffmpeg_child = spawn(executor, &mut ffmpeg_cmd)?;
+    let mut image = RgbImage::new(input.width, input.height);
+    let read = match ffmpeg_child.take_stdout() {
+        Some(mut stdout) => stdout.read_exact(&mut image.data).is_ok(),
+        None => false,
+    };
+    let stderr = read_stderr(ffmpeg_child.as_mut());
+    let status = ffmpeg_child.wait()?;
+    if !status.success() || !read {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr,
+        });
+    }
+    Ok(image)
+}
+
+/// Renders every frame of the extraction in-process, returning the time spent rendering each frame
+///
+/// The input is decoded once and the frames are written by a single ffmpeg process, rendering up to `j` frames
+/// at a time on separate threads.
+pub(crate) fn extract_frames_native(
+    extraction: &Extraction,
+    input: &InputInfo,
+    extraction_path: &Path,
+    progress_handler: Option<&impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+    executor: &dyn Executor,
+) -> Result<Vec<Duration>> {
+    let descriptor = &extraction.descriptor;
+    check_supported(Backend::Native, descriptor, input)?;
+    if input.color.is_high_bit_depth() {
+        warn!("The native backend decodes frames to 8 bits, use the ffmpeg backend to keep the bit depth of the input");
+    }
+    let image = decode(extraction, input, executor)?;
+    let (width, height) = extraction.output_resolution;
+    let frame_path_template = frame_path_template(extraction_path, descriptor.intermediate_format);
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd
+        .args(["-hide_banner", "-loglevel", "error", "-nostats"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-video_size"])
+        .arg(format!("{width}x{height}"))
+        .args(["-framerate", "1", "-i", "pipe:0"]);
+    ffmpeg_cmd.args(
+        descriptor
+            .intermediate_format
+            .output_args(descriptor.jpeg_quality, &extraction.color),
+    );
+    ffmpeg_cmd.args(&descriptor.extra_output_args);
+    ffmpeg_cmd
+        .arg("-frames:v")
+        .arg(descriptor.frame_count.to_string())
+        .args(["-start_number", "0", "-f", "image2", "-y"])
+        .arg(&frame_path_template)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut ffmpeg_child = spawn(executor, &mut ffmpeg_cmd)?;
+    let mut stdin = ffmpeg_child.take_stdin().expect("piped stdin");
+    let frames = (0..descriptor.frame_count).collect::<Vec<_>>();
+    let mut durations = Vec::with_capacity(frames.len());
+    'frames: for batch in frames.chunks(descriptor.thread_count().max(1)) {
+        if cancel::is_cancelled(cancellation_token) {
+            ffmpeg_child.kill().ok();
+            ffmpeg_child.wait().ok();
+            return Err(DragonflyError::Cancelled);
+        }
+        let rendered = thread::scope(|scope| {
+            let handles = batch
+                .iter()
+                .map(|&frame| {
+                    let image = &image;
+                    scope.spawn(move || {
+                        let start = Instant::now();
+                        let pose = descriptor.pose(frame);
+                        image
+                            .reproject(descriptor, &pose, width, height)
+                            .map(|frame| (frame, start.elapsed()))
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("render thread panicked"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        for (&frame, (output, duration)) in batch.iter().zip(rendered) {
+            if stdin.write_all(&output.data).is_err() {
+                // ffmpeg exited early, which the status below reports
+                break 'frames;
+            }
+            durations.push(duration);
+            progress::report(
+                progress_handler,
+                ProgressEvent::FrameExtracted {
+                    index: frame,
+                    path: Some(frame_path(
+                        extraction_path,
+                        frame,
+                        descriptor.intermediate_format,
+                    )),
+                    duration,
+                },
+            );
+        }
+    }
+    drop(stdin);
+    let stderr = read_stderr(ffmpeg_child.as_mut());
+    let status = ffmpeg_child.wait()?;
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr,
+        });
+    }
+    Ok(durations)
+}
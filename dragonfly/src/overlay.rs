@@ -0,0 +1,300 @@
+use crate::{escape_filter_value, wrap_degrees, CameraPose, DragonflyError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use strum::{Display, EnumString};
+
+/// Position of an overlay within the video frame
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, Display, EnumString, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl Anchor {
+    /// Returns the x and y expressions that place an overlay, whose size is given by the `w` and `h`
+    /// variables, within a frame of width `W` and height `H`, inset by `margin` pixels
+    pub(crate) fn position_expressions(
+        &self,
+        margin: u32,
+        (w, h): (&str, &str),
+    ) -> (String, String) {
+        match self {
+            Anchor::TopLeft => (format!("{margin}"), format!("{margin}")),
+            Anchor::TopRight => (format!("W-{w}-{margin}"), format!("{margin}")),
+            Anchor::BottomLeft => (format!("{margin}"), format!("H-{h}-{margin}")),
+            Anchor::BottomRight => (format!("W-{w}-{margin}"), format!("H-{h}-{margin}")),
+            Anchor::Center => (format!("(W-{w})/2"), format!("(H-{h})/2")),
+        }
+    }
+}
+
+/// An image, such as a logo, overlaid on every frame of the encoded video
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct WatermarkDescriptor {
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Path to an image to overlay on the video", long)
+    )]
+    pub watermark: Option<PathBuf>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Position of the watermark",
+            long,
+            default_value = "bottom-right"
+        )
+    )]
+    pub watermark_position: Anchor,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Opacity of the watermark, from 0 (invisible) to 1 (opaque)",
+            long,
+            default_value = "0.8"
+        )
+    )]
+    pub watermark_opacity: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Width of the watermark as a fraction of the video width",
+            long,
+            default_value = "0.15"
+        )
+    )]
+    pub watermark_scale: f32,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Distance in pixels between the watermark and the edge of the video",
+            long,
+            default_value = "16"
+        )
+    )]
+    pub watermark_margin: u32,
+}
+
+impl Default for WatermarkDescriptor {
+    fn default() -> Self {
+        Self {
+            watermark: None,
+            watermark_position: Anchor::BottomRight,
+            watermark_opacity: 0.8,
+            watermark_scale: 0.15,
+            watermark_margin: 16,
+        }
+    }
+}
+
+impl WatermarkDescriptor {
+    /// Returns the filter graph that overlays the watermark on the output of `filter_string`, if a
+    /// watermark is set
+    pub(crate) fn apply(&self, filter_string: String) -> Result<String> {
+        let Some(path) = &self.watermark else {
+            return Ok(filter_string);
+        };
+        let path_str = path
+            .to_str()
+            .ok_or_else(|| DragonflyError::InvalidPathString(path.clone()))?;
+        let (x, y) = self
+            .watermark_position
+            .position_expressions(self.watermark_margin, ("w", "h"));
+        Ok(format!(
+            "{filter_string}[video];\
+            movie={},format=rgba,colorchannelmixer=aa={}[logo];\
+            [logo][video]scale2ref=w=main_w*{}:h=ow/a[logo][video];\
+            [video][logo]overlay=x={x}:y={y}",
+            escape_filter_value(path_str),
+            self.watermark_opacity.clamp(0.0, 1.0),
+            self.watermark_scale,
+        ))
+    }
+}
+
+/// A patch composited over the nadir of the input panorama to hide the tripod
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct NadirPatchDescriptor {
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Path to an image, such as a circular logo, composited over the nadir to hide the tripod",
+            long
+        )
+    )]
+    pub nadir_patch: Option<PathBuf>,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Hide the tripod by mirroring the ground around the nadir over it",
+            long,
+            conflicts_with = "nadir_patch"
+        )
+    )]
+    pub nadir_mirror: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Angular diameter in degrees of the region around the nadir that the patch covers",
+            long,
+            default_value = "40.0"
+        )
+    )]
+    pub nadir_size: f32,
+}
+
+impl Default for NadirPatchDescriptor {
+    fn default() -> Self {
+        Self {
+            nadir_patch: None,
+            nadir_mirror: false,
+            nadir_size: 40.0,
+        }
+    }
+}
+
+impl NadirPatchDescriptor {
+    /// Returns true if a patch or mirror hides the nadir
+    pub fn is_enabled(&self) -> bool {
+        self.nadir_patch.is_some() || self.nadir_mirror
+    }
+
+    /// Returns the filters that patch the nadir of a full equirectangular panorama, ending with a comma so the
+    /// next filter continues the chain
+    pub(crate) fn filter_string(&self) -> Result<String> {
+        // The bottom rows of an equirectangular panorama cover the degrees nearest the nadir
+        let fraction = self.nadir_size / 360.0;
+        if let Some(path) = &self.nadir_patch {
+            let path_str = path
+                .to_str()
+                .ok_or_else(|| DragonflyError::InvalidPathString(path.clone()))?;
+            // Project the image as if it were a fisheye photo looking straight down, transparent elsewhere
+            Ok(format!(
+                "null[pano];\
+                movie={},format=rgba,v360=fisheye:e:ih_fov={1}:iv_fov={1}:pitch=90:alpha_mask=1[nadir];\
+                [nadir][pano]scale2ref=w=main_w:h=main_h[nadir][pano];\
+                [pano][nadir]overlay,",
+                escape_filter_value(path_str),
+                self.nadir_size,
+            ))
+        } else if self.nadir_mirror {
+            Ok(format!(
+                "split[pano][ground];\
+                [ground]crop=iw:ih*{fraction}:0:ih*{},vflip[mirror];\
+                [pano][mirror]overlay=0:H-h,",
+                1.0 - 2.0 * fraction,
+            ))
+        } else {
+            Ok(String::new())
+        }
+    }
+}
+
+/// Text drawn on every frame with the drawtext filter
+///
+/// The text is a template that may reference `{filename}`, `{frame}`, `{yaw}`, `{pitch}`, `{roll}`,
+/// `{h_fov}`, and `{v_fov}`. Only `{frame}` is replaced when the text is drawn at encode time.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TextOverlay {
+    pub text: String,
+    /// Falls back to the default fontconfig font when missing
+    pub font_file: Option<PathBuf>,
+    pub position: Anchor,
+    pub font_size: u32,
+    pub font_color: String,
+    pub margin: u32,
+}
+
+impl TextOverlay {
+    /// Renders the template for an extracted frame of the input named `filename`
+    pub fn render(&self, filename: &str, frame: usize, pose: &CameraPose) -> String {
+        self.text
+            .replace("{filename}", filename)
+            .replace("{frame}", &frame.to_string())
+            .replace("{yaw}", &format!("{:.0}", wrap_degrees(pose.yaw)))
+            .replace("{pitch}", &format!("{:.0}", wrap_degrees(pose.pitch)))
+            .replace("{roll}", &format!("{:.0}", wrap_degrees(pose.roll)))
+            .replace("{h_fov}", &format!("{:.0}", pose.h_fov))
+            .replace("{v_fov}", &format!("{:.0}", pose.v_fov))
+    }
+
+    /// Returns true if the rendered text changes from frame to frame
+    pub(crate) fn is_animated(&self) -> bool {
+        [
+            "{frame}", "{yaw}", "{pitch}", "{roll}", "{h_fov}", "{v_fov}",
+        ]
+        .iter()
+        .any(|variable| self.text.contains(variable))
+    }
+
+    /// Returns the overlay `--debug-overlay` draws in the top left corner of every extracted frame
+    pub(crate) fn debug() -> Self {
+        Self {
+            text: String::new(),
+            font_file: None,
+            position: Anchor::TopLeft,
+            font_size: 24,
+            font_color: "yellow".to_string(),
+            margin: 16,
+        }
+    }
+
+    /// Returns the text the debug overlay draws on an extracted frame, precise enough to diagnose camera
+    /// paths and seams
+    pub(crate) fn debug_text(frame: usize, pose: &CameraPose) -> String {
+        format!(
+            "frame {frame}  yaw {:.2}  pitch {:.2}  roll {:.2}  fov {:.2}x{:.2}",
+            wrap_degrees(pose.yaw),
+            wrap_degrees(pose.pitch),
+            wrap_degrees(pose.roll),
+            pose.h_fov,
+            pose.v_fov
+        )
+    }
+
+    /// Returns the drawtext filter named `drawtext@{instance}` that draws `text` verbatim, so sendcmd can
+    /// change its text
+    pub(crate) fn drawtext_filter_string(&self, text: &str, instance: &str) -> Result<String> {
+        self.filter_string(text, "none", &format!("drawtext@{instance}"))
+    }
+
+    /// Returns the drawtext filter used at encode time, where `{frame}` counts the encoded frames
+    pub(crate) fn encode_filter_string(&self) -> Result<String> {
+        // Escape drawtext's own expansion syntax before substituting the frame number expansion
+        let text = self
+            .text
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace("{frame}", "%{frame_num}");
+        self.filter_string(&text, "normal", "drawtext")
+    }
+
+    fn filter_string(&self, text: &str, expansion: &str, name: &str) -> Result<String> {
+        let font_option = match &self.font_file {
+            Some(font_file) => {
+                let font_file_str = font_file
+                    .to_str()
+                    .ok_or_else(|| DragonflyError::InvalidPathString(font_file.clone()))?;
+                format!("fontfile={}:", escape_filter_value(font_file_str))
+            }
+            None => String::new(),
+        };
+        let (x, y) = self
+            .position
+            .position_expressions(self.margin, ("tw", "th"));
+        Ok(format!(
+            "{name}={font_option}text={}:expansion={expansion}:fontsize={}:fontcolor={}:\
+            shadowx=2:shadowy=2:x={x}:y={y}",
+            escape_filter_value(text),
+            self.font_size,
+            escape_filter_value(&self.font_color),
+        ))
+    }
+}
@@ -0,0 +1,160 @@
+use crate::progress::{self, ProgressEvent, ProgressHandler};
+use crate::{
+    cancel, command_line, ffprobe_info, frame_path_template, spawn, Aspect, CancellationToken,
+    DragonflyError, IntermediateFormat, Result, SystemExecutor, FFMPEG_BINARY_PATH,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// A window sliding across an ordinary wide panorama, for inputs that are not 360 images
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "clap", derive(clap::Args))]
+pub struct PanDescriptor {
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Number of frames to extract", long, default_value = "360")
+    )]
+    pub frame_count: usize,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Aspect ratio of the window, which spans the full height of the panorama",
+            long,
+            default_value = "16:9"
+        )
+    )]
+    pub aspect: Aspect,
+    #[cfg_attr(
+        feature = "clap",
+        arg(help = "Pan from right to left instead of left to right", long)
+    )]
+    pub reverse: bool,
+    #[cfg_attr(
+        feature = "clap",
+        arg(
+            help = "Height in pixels of the extracted frames, defaults to the height of the panorama",
+            long
+        )
+    )]
+    pub height: Option<u32>,
+}
+
+impl Default for PanDescriptor {
+    fn default() -> Self {
+        Self {
+            frame_count: 360,
+            aspect: Aspect::Landscape,
+            reverse: false,
+            height: None,
+        }
+    }
+}
+
+impl PanDescriptor {
+    /// Returns the crop and scale filters that render every frame of the pan across an input of the given size
+    fn filter_string(&self, input_width: u32, input_height: u32) -> Result<String> {
+        // Even dimensions keep the frames encodable as yuv420p
+        let window_width = ((input_height as f32 * self.aspect.ratio()) as u32) & !1;
+        if window_width == 0 || window_width > input_width {
+            return Err(DragonflyError::PanoramaTooNarrow {
+                width: input_width,
+                height: input_height,
+                aspect: self.aspect,
+            });
+        }
+        let last = self.frame_count.saturating_sub(1).max(1);
+        let progress = if self.reverse {
+            format!("(1-n/{last})")
+        } else {
+            format!("n/{last}")
+        };
+        let mut filter_string =
+            format!("crop=w={window_width}:h=ih:x='(iw-{window_width})*{progress}':y=0");
+        if let Some(height) = self.height {
+            filter_string.push_str(&format!(",scale=-2:{height}:flags=lanczos"));
+        }
+        Ok(filter_string)
+    }
+}
+
+/// Extracts the frames of a pan across the wide panorama at `input_path` to `extraction_path`
+pub fn extract_pan_frames(
+    input_path: &Path,
+    extraction_path: &Path,
+    descriptor: &PanDescriptor,
+    progress_handler: Option<impl ProgressHandler>,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<()> {
+    if descriptor.frame_count == 0 {
+        return Err(DragonflyError::InvalidFrameCount);
+    }
+    let input = ffprobe_info(input_path, &SystemExecutor)?;
+    let filter_string = descriptor.filter_string(input.width, input.height)?;
+    fs::create_dir_all(extraction_path)?;
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd
+        .args([
+            "-hide_banner",
+            "-loglevel",
+            "error",
+            "-nostats",
+            "-progress",
+            "pipe:2",
+            // Input file, repeated once per output frame
+            "-loop",
+            "1",
+            "-framerate",
+            "1",
+            "-i",
+        ])
+        .arg(input_path)
+        .args([
+            "-vf",
+            &filter_string,
+            "-frames:v",
+            &descriptor.frame_count.to_string(),
+            "-start_number",
+            "0",
+            "-f",
+            "image2",
+            "-y",
+        ])
+        .arg(frame_path_template(
+            extraction_path,
+            IntermediateFormat::Jpeg,
+        ))
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    progress::report(
+        progress_handler.as_ref(),
+        ProgressEvent::ExtractStarted {
+            frame_count: descriptor.frame_count,
+        },
+    );
+    let mut ffmpeg_child = spawn(&SystemExecutor, &mut ffmpeg_cmd)?;
+    let stderr = ffmpeg_child.take_stderr().expect("piped stderr");
+    let extract_progress =
+        cancel::kill_on_cancel(&mut [ffmpeg_child.as_mut()], cancellation_token, || {
+            progress::read_extract_progress(
+                stderr,
+                descriptor.frame_count,
+                Some((extraction_path, IntermediateFormat::Jpeg)),
+                progress_handler.as_ref(),
+            )
+        })?;
+    let status = ffmpeg_child.wait()?;
+    if cancel::is_cancelled(cancellation_token) {
+        return Err(DragonflyError::Cancelled);
+    }
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr: extract_progress.log().to_string(),
+        });
+    }
+    progress::report(progress_handler.as_ref(), ProgressEvent::Finished);
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! Plans of the ffmpeg and ffprobe commands an extraction or encode would run, returned as data instead of
+//! being executed
+
+use crate::{
+    command_line, encode_command, ffprobe_command, sendcmd_commands, EncodeFramesDescriptor,
+    ExtractFramesDescriptor, Extraction, FrameSource, InputInfo, Result, SourceColor,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// An ffmpeg or ffprobe invocation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Files that must be written before the command runs, such as sendcmd scripts
+    pub files: Vec<PlannedFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+impl PlannedCommand {
+    /// Writes the planned files and returns the command, ready to spawn
+    pub fn prepare(&self) -> Result<Command> {
+        for file in &self.files {
+            fs::write(&file.path, &file.contents)?;
+        }
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        Ok(command)
+    }
+
+    /// Returns the command as a shell command line
+    pub fn command_line(&self) -> String {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command_line(&command)
+    }
+}
+
+impl From<&Command> for PlannedCommand {
+    fn from(command: &Command) -> Self {
+        Self {
+            program: command.get_program().to_string_lossy().into_owned(),
+            args: command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+            files: vec![],
+        }
+    }
+}
+
+/// Returns the commands [`extract_frames`](crate::extract_frames) would run for an input with the given
+/// properties, without running anything
+///
+/// The first command is the ffprobe that reports `input`. Single pass extraction reads its sendcmd script from
+/// the extraction directory, and its fallback to one process per frame is not planned.
+pub fn plan_extract_frames(
+    input_path: &Path,
+    extraction_path: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    input: &InputInfo,
+) -> Result<Vec<PlannedCommand>> {
+    let extraction = Extraction::new(input_path, descriptor, input)?;
+    let mut commands = vec![PlannedCommand::from(&ffprobe_command(input_path)?)];
+    if extraction.is_single_pass() {
+        let script_path = extraction_path.join("sendcmd.txt");
+        let mut command = PlannedCommand::from(
+            &extraction.single_pass_command_with_script(extraction_path, &script_path)?,
+        );
+        command.files.push(PlannedFile {
+            contents: sendcmd_commands(&extraction.descriptor, &extraction.input_filename),
+            path: script_path,
+        });
+        commands.push(command);
+    } else {
+        for frame in 0..extraction.descriptor.frame_count {
+            let output_path = extraction.frame_path(extraction_path, frame);
+            commands.push(PlannedCommand::from(
+                &extraction.frame_command(frame, &output_path)?,
+            ));
+        }
+    }
+    Ok(commands)
+}
+
+/// Returns the command [`encode_frames`](crate::encode_frames) would run for `frame_count` frames extracted
+/// to `extraction_path`, without running anything
+///
+/// Spherical metadata is injected by dragonfly itself rather than by a command, so it is not part of the plan.
+pub fn plan_encode_frames(
+    output_path: &Path,
+    extraction_path: &Path,
+    frame_count: usize,
+    descriptor: &EncodeFramesDescriptor,
+) -> Result<PlannedCommand> {
+    let descriptor = descriptor.with_preset()?;
+    let source = FrameSource::Directory {
+        path: extraction_path,
+        frame_count,
+        color: SourceColor::of_extraction(extraction_path),
+    };
+    Ok(PlannedCommand::from(&encode_command(
+        output_path,
+        &source,
+        &descriptor,
+    )?))
+}
@@ -0,0 +1,60 @@
+use crate::{
+    extracted_frame_count, frame_path, wrap_degrees, DragonflyError, EncodeFramesDescriptor,
+    ExtractionManifest, IntermediateFormat, Result, FFMPEG_BINARY_PATH,
+};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Builds the command that exports the poster image, or returns None when no poster was requested
+pub(crate) fn poster_command(
+    extraction_path: &Path,
+    descriptor: &EncodeFramesDescriptor,
+) -> Result<Option<Command>> {
+    let Some(poster_path) = &descriptor.poster else {
+        return Ok(None);
+    };
+    let frame = poster_frame(extraction_path, descriptor)?;
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd
+        .args(["-hide_banner", "-loglevel", "error", "-nostats", "-i"])
+        .arg(&frame);
+    let filters = descriptor.frame_filters()?;
+    if !filters.is_empty() {
+        ffmpeg_cmd.args(["-vf", &filters.join(",")]);
+    }
+    ffmpeg_cmd
+        .args(["-frames:v", "1", "-update", "1", "-y"])
+        .arg(poster_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    Ok(Some(ffmpeg_cmd))
+}
+
+/// Returns the path of the extracted frame chosen as the poster
+fn poster_frame(extraction_path: &Path, descriptor: &EncodeFramesDescriptor) -> Result<PathBuf> {
+    let index = match descriptor.poster_yaw {
+        Some(yaw) => {
+            // The poses of the frames are only known from the manifest
+            let manifest = ExtractionManifest::read(extraction_path).map_err(|_| {
+                DragonflyError::PosterYawWithoutManifest(extraction_path.to_path_buf())
+            })?;
+            let distance = |frame_yaw: f32| wrap_degrees(frame_yaw - yaw).abs();
+            manifest
+                .report
+                .frames
+                .iter()
+                .min_by(|a, b| distance(a.pose.yaw).total_cmp(&distance(b.pose.yaw)))
+                .map_or(0, |frame| frame.index)
+        }
+        None => descriptor.poster_frame.unwrap_or(0),
+    };
+    let frame_count = extracted_frame_count(extraction_path)?;
+    if index >= frame_count {
+        return Err(DragonflyError::PosterFrameOutOfRange { index, frame_count });
+    }
+    Ok(frame_path(
+        extraction_path,
+        index,
+        IntermediateFormat::of_extraction(extraction_path),
+    ))
+}
@@ -0,0 +1,158 @@
+use crate::{Codec, DragonflyError, EncodeFramesDescriptor, Encoder, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Names of the presets built into dragonfly
+pub const BUILTIN_PRESETS: [&str; 6] = [
+    "youtube-4k",
+    "youtube-1080p",
+    "instagram",
+    "instagram-square",
+    "twitter",
+    "gif-small",
+];
+
+/// Encoding settings and limits tailored to a destination, such as a video sharing site
+///
+/// Codec, encoder, crf, and bitrate only apply when the descriptor leaves them unset, while the
+/// remaining fields cap the descriptor's values.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncodePreset {
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub max_fps: Option<f32>,
+    /// Longest video length in seconds
+    pub max_length: Option<f32>,
+    pub max_gif_colors: Option<u16>,
+    pub codec: Option<Codec>,
+    pub encoder: Option<Encoder>,
+    pub crf: Option<u8>,
+    /// Maximum bitrate, such as 12M
+    pub bitrate: Option<String>,
+}
+
+impl EncodePreset {
+    /// Returns the built-in preset with the given name
+    pub fn builtin(name: &str) -> Option<Self> {
+        let preset = match name {
+            // https://support.google.com/youtube/answer/1722171
+            "youtube-4k" => EncodePreset {
+                max_width: Some(3840),
+                max_height: Some(2160),
+                max_fps: Some(60.0),
+                codec: Some(Codec::H264),
+                bitrate: Some("68M".to_string()),
+                ..Default::default()
+            },
+            "youtube-1080p" => EncodePreset {
+                max_width: Some(1920),
+                max_height: Some(1080),
+                max_fps: Some(60.0),
+                codec: Some(Codec::H264),
+                bitrate: Some("12M".to_string()),
+                ..Default::default()
+            },
+            "instagram" => EncodePreset {
+                max_width: Some(1080),
+                max_height: Some(1920),
+                max_fps: Some(30.0),
+                max_length: Some(90.0),
+                codec: Some(Codec::H264),
+                bitrate: Some("8M".to_string()),
+                ..Default::default()
+            },
+            "instagram-square" => EncodePreset {
+                max_width: Some(1080),
+                max_height: Some(1080),
+                max_fps: Some(30.0),
+                max_length: Some(60.0),
+                codec: Some(Codec::H264),
+                bitrate: Some("8M".to_string()),
+                ..Default::default()
+            },
+            "twitter" => EncodePreset {
+                max_width: Some(1920),
+                max_height: Some(1200),
+                max_fps: Some(60.0),
+                max_length: Some(140.0),
+                codec: Some(Codec::H264),
+                bitrate: Some("25M".to_string()),
+                ..Default::default()
+            },
+            // Meant for gif output, where the codec and bitrate don't apply
+            "gif-small" => EncodePreset {
+                max_width: Some(480),
+                max_fps: Some(15.0),
+                max_length: Some(10.0),
+                max_gif_colors: Some(128),
+                ..Default::default()
+            },
+            _ => return None,
+        };
+        Some(preset)
+    }
+
+    /// Looks up a preset by name, where presets defined in the user's presets file take precedence
+    /// over the built-in presets
+    pub fn load(name: &str) -> Result<Self> {
+        if let Some(path) = user_presets_path().filter(|path| path.exists()) {
+            debug!("Loading presets from {}", path.display());
+            let contents = fs::read_to_string(&path)?;
+            let mut presets: HashMap<String, EncodePreset> = toml::from_str(&contents)?;
+            if let Some(preset) = presets.remove(name) {
+                return Ok(preset);
+            }
+        }
+        EncodePreset::builtin(name).ok_or_else(|| DragonflyError::UnknownPreset(name.to_string()))
+    }
+
+    /// Returns a copy of the descriptor with the preset applied
+    pub fn apply(&self, descriptor: &EncodeFramesDescriptor) -> EncodeFramesDescriptor {
+        let mut descriptor = descriptor.clone();
+        if let Some(max_fps) = self.max_fps {
+            descriptor.fps = descriptor.fps.min(max_fps);
+        }
+        if let Some(max_length) = self.max_length.filter(|&max| descriptor.length > max) {
+            warn!(
+                "Shortening the video from {} to {max_length} seconds to fit the preset",
+                descriptor.length
+            );
+            descriptor.length = max_length;
+        }
+        if let Some(max_gif_colors) = self.max_gif_colors {
+            descriptor.gif_colors = descriptor.gif_colors.min(max_gif_colors);
+        }
+        descriptor.max_width = min_option(descriptor.max_width, self.max_width);
+        descriptor.max_height = min_option(descriptor.max_height, self.max_height);
+        // An explicit encoder or codec on the descriptor wins over the preset's choice
+        if descriptor.encoder.is_none() && descriptor.codec.is_none() {
+            descriptor.encoder = self.encoder;
+            descriptor.codec = self.codec;
+        }
+        descriptor.crf = descriptor.crf.or(self.crf);
+        descriptor.bitrate = descriptor.bitrate.or_else(|| self.bitrate.clone());
+        descriptor
+    }
+}
+
+fn min_option(a: Option<u32>, b: Option<u32>) -> Option<u32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Returns `$DRAGONFLY_PRESETS`, falling back to `dragonfly/presets.toml` in the user's config directory
+fn user_presets_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("DRAGONFLY_PRESETS") {
+        return Some(path.into());
+    }
+    let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("dragonfly").join("presets.toml"))
+}
@@ -0,0 +1,212 @@
+use crate::gpano::{read_gpano, GPano};
+use crate::retry;
+use crate::{
+    ffprobe, parse_frame_rate, DragonflyError, FfprobeOutput, FfprobeStreamOutput, Result,
+    SourceVideo, SystemExecutor,
+};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use strum::Display;
+
+/// The projection of an input, as far as its metadata and aspect ratio tell
+#[derive(Clone, Copy, Debug, Display, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum DetectedProjection {
+    /// A full 360x180 equirectangular panorama
+    Equirectangular,
+    /// An equirectangular panorama covering less than 360x180, according to its GPano metadata
+    PartialEquirectangular,
+    /// Neither the metadata nor the aspect ratio suggest an equirectangular panorama
+    Unknown,
+}
+
+/// What ffprobe and the file's metadata tell about an input before extracting it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InputProbe {
+    pub width: u32,
+    pub height: u32,
+    pub aspect_ratio: f64,
+    pub codec: Option<String>,
+    pub pix_fmt: Option<String>,
+    /// Bits per color component
+    pub bit_depth: Option<u32>,
+    /// Frame timing when the input is a video rather than a still image
+    pub video: Option<SourceVideo>,
+    pub gpano: Option<GPano>,
+    pub projection: DetectedProjection,
+}
+
+/// Analyzes an input with ffprobe and reads its GPano metadata
+pub fn probe_input(input_path: &Path) -> Result<InputProbe> {
+    let ffprobe_output = retry::with_retries(|| ffprobe(input_path, &SystemExecutor))?;
+    let input = ffprobe_output.input_info()?;
+    let stream = ffprobe_output
+        .video_stream()
+        .ok_or(DragonflyError::SourceContainsNoStream)?;
+    let aspect_ratio = input.width as f64 / input.height.max(1) as f64;
+    let gpano = read_gpano(input_path);
+    let projection = match &gpano {
+        Some(gpano) if gpano.projection_type.as_deref() == Some("equirectangular") => {
+            if gpano.is_cropped() {
+                DetectedProjection::PartialEquirectangular
+            } else {
+                DetectedProjection::Equirectangular
+            }
+        }
+        Some(gpano) if gpano.projection_type.is_some() => DetectedProjection::Unknown,
+        // Stitched 360x180 panoramas are twice as wide as they are tall
+        _ if (aspect_ratio - 2.0).abs() < 0.02 => DetectedProjection::Equirectangular,
+        _ => DetectedProjection::Unknown,
+    };
+    Ok(InputProbe {
+        width: input.width,
+        height: input.height,
+        aspect_ratio,
+        codec: stream.codec_name.clone(),
+        pix_fmt: stream.pix_fmt.clone(),
+        bit_depth: stream.bit_depth(),
+        video: input.video,
+        gpano,
+        projection,
+    })
+}
+
+impl FfprobeStreamOutput {
+    /// Returns the bits per color component of the stream
+    pub(crate) fn bit_depth(&self) -> Option<u32> {
+        self.bits_per_raw_sample
+            .as_deref()
+            .and_then(|bits| bits.parse().ok())
+            .or_else(|| self.pix_fmt.as_deref().map(pix_fmt_bit_depth))
+    }
+}
+
+/// Returns the bits per component of a pixel format such as yuv420p10le or rgb48be
+fn pix_fmt_bit_depth(pix_fmt: &str) -> u32 {
+    let name = pix_fmt.trim_end_matches("le").trim_end_matches("be");
+    let digits = name.bytes().rev().take_while(u8::is_ascii_digit).count();
+    let bits: u32 = name[name.len() - digits..].parse().unwrap_or(0);
+    match bits {
+        // Packed formats such as rgb48 give the bits of the whole pixel
+        48 | 64 => 16,
+        9..=16 if name.starts_with("gray") || name[..name.len() - digits].ends_with('p') => bits,
+        _ => 8,
+    }
+}
+
+/// The kind of data a stream of an input holds
+#[derive(Clone, Copy, Debug, Display, PartialEq, Serialize, Deserialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Data,
+    Attachment,
+    Unknown,
+}
+
+/// A stream of an input, as reported by ffprobe
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StreamInfo {
+    pub index: usize,
+    pub kind: StreamKind,
+    pub codec: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub pix_fmt: Option<String>,
+    /// Bits per color component of a video stream
+    pub bit_depth: Option<u32>,
+    /// Frames per second, which ffprobe also reports for still images
+    pub frame_rate: Option<f64>,
+    pub frame_count: Option<usize>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+    /// Degrees players rotate the frames clockwise to display them, from the display matrix or rotate tag
+    pub rotation: Option<f64>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+}
+
+/// Everything ffprobe reports about an input that matters to rendering it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MediaInfo {
+    /// Names of the demuxer, such as image2 or mov,mp4,m4a,3gp,3g2,mj2
+    pub format_name: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+    /// Bits per second
+    pub bit_rate: Option<u64>,
+    pub streams: Vec<StreamInfo>,
+}
+
+impl MediaInfo {
+    /// Returns the first video stream, which is the one dragonfly extracts
+    pub fn video_stream(&self) -> Option<&StreamInfo> {
+        self.streams
+            .iter()
+            .find(|stream| stream.kind == StreamKind::Video)
+    }
+}
+
+impl FfprobeOutput {
+    /// Returns every stream of the input along with its container format
+    pub(crate) fn media_info(&self) -> MediaInfo {
+        let format = self.format.as_ref();
+        MediaInfo {
+            format_name: format.and_then(|format| format.format_name.clone()),
+            duration: format.and_then(|format| format.duration.as_deref()?.parse().ok()),
+            bit_rate: format.and_then(|format| format.bit_rate.as_deref()?.parse().ok()),
+            streams: self.streams.iter().map(StreamInfo::from).collect(),
+        }
+    }
+}
+
+impl From<&FfprobeStreamOutput> for StreamInfo {
+    fn from(stream: &FfprobeStreamOutput) -> Self {
+        let kind = match stream.codec_type.as_deref() {
+            Some("video") => StreamKind::Video,
+            Some("audio") => StreamKind::Audio,
+            Some("subtitle") => StreamKind::Subtitle,
+            Some("data") => StreamKind::Data,
+            Some("attachment") => StreamKind::Attachment,
+            _ => StreamKind::Unknown,
+        };
+        // The display matrix rotates counterclockwise, while the older rotate tag rotates clockwise
+        let rotation = stream
+            .side_data_list
+            .iter()
+            .find_map(|side_data| side_data.rotation)
+            .map(|rotation| -rotation)
+            .or_else(|| stream.tags.as_ref()?.rotate.as_deref()?.parse().ok())
+            .map(|rotation: f64| rotation.rem_euclid(360.0));
+        Self {
+            index: stream.index,
+            kind,
+            codec: stream.codec_name.clone(),
+            width: stream.width,
+            height: stream.height,
+            pix_fmt: stream.pix_fmt.clone(),
+            bit_depth: (kind == StreamKind::Video)
+                .then(|| stream.bit_depth())
+                .flatten(),
+            frame_rate: stream.r_frame_rate.as_deref().and_then(parse_frame_rate),
+            frame_count: stream.nb_frames.as_deref().and_then(|n| n.parse().ok()),
+            duration: stream.duration.as_deref().and_then(|d| d.parse().ok()),
+            rotation,
+            sample_rate: stream
+                .sample_rate
+                .as_deref()
+                .and_then(|rate| rate.parse().ok()),
+            channels: stream.channels,
+        }
+    }
+}
+
+/// Runs ffprobe on an input and returns all of its streams along with its container format
+pub fn probe(input_path: &Path) -> Result<MediaInfo> {
+    let ffprobe_output = retry::with_retries(|| ffprobe(input_path, &SystemExecutor))?;
+    Ok(ffprobe_output.media_info())
+}
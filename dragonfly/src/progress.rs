@@ -0,0 +1,178 @@
+use crate::{frame_path, IntermediateFormat, Result};
+use log::debug;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, SyncSender};
+use std::time::{Duration, Instant};
+
+/// A status update reported while extracting or encoding frames
+#[derive(Clone, Debug)]
+pub enum ProgressEvent {
+    /// Extraction is about to render `frame_count` frames
+    ExtractStarted { frame_count: usize },
+    /// A frame finished rendering, in no particular order when extracting with multiple processes
+    FrameExtracted {
+        index: usize,
+        /// Missing when the frame is streamed straight into the encoder
+        path: Option<PathBuf>,
+        /// Time spent rendering the frame
+        duration: Duration,
+    },
+    /// Encoding is about to write `frame_count` frames
+    EncodeStarted { frame_count: usize },
+    /// The encoder has written `frame` frames at a rate of `fps` frames per second
+    EncodeProgress { frame: usize, fps: f32 },
+    /// The extraction or encode completed successfully
+    Finished,
+}
+
+/// Receives progress events, implemented for closures and channel senders
+pub trait ProgressHandler {
+    fn handle(&self, event: ProgressEvent);
+}
+
+impl<F: Fn(ProgressEvent)> ProgressHandler for F {
+    fn handle(&self, event: ProgressEvent) {
+        self(event)
+    }
+}
+
+impl ProgressHandler for Sender<ProgressEvent> {
+    fn handle(&self, event: ProgressEvent) {
+        // A dropped receiver means nobody is listening anymore
+        self.send(event).ok();
+    }
+}
+
+impl ProgressHandler for SyncSender<ProgressEvent> {
+    fn handle(&self, event: ProgressEvent) {
+        self.send(event).ok();
+    }
+}
+
+/// Reports extracted frames from the lines written by `ffmpeg -progress`
+pub(crate) struct ExtractProgress<'a> {
+    frame_count: usize,
+    /// Frames are written here in the given format when given, and streamed elsewhere otherwise
+    frames: Option<(&'a Path, IntermediateFormat)>,
+    reported: usize,
+    last_report: Instant,
+    /// Time spent rendering each frame reported so far
+    durations: Vec<Duration>,
+    /// Lines logged by ffmpeg in between the progress reports, such as errors
+    log: String,
+}
+
+impl<'a> ExtractProgress<'a> {
+    pub(crate) fn new(frame_count: usize, frames: Option<(&'a Path, IntermediateFormat)>) -> Self {
+        Self {
+            frame_count,
+            frames,
+            reported: 0,
+            last_report: Instant::now(),
+            durations: vec![],
+            log: String::new(),
+        }
+    }
+
+    pub(crate) fn log(&self) -> &str {
+        &self.log
+    }
+
+    /// Returns the time spent rendering each reported frame, ordered by index
+    pub(crate) fn into_durations(self) -> Vec<Duration> {
+        self.durations
+    }
+
+    pub(crate) fn line(&mut self, line: &str, progress_handler: Option<&impl ProgressHandler>) {
+        // ffmpeg periodically reports the number of frames written so far
+        if let Some(frames) = line
+            .strip_prefix("frame=")
+            .and_then(|f| f.trim().parse::<usize>().ok())
+        {
+            let frames = frames.min(self.frame_count);
+            if frames > self.reported {
+                // Frames written between two reports share the elapsed time equally
+                let duration = self.last_report.elapsed() / (frames - self.reported) as u32;
+                for index in self.reported..frames {
+                    self.durations.push(duration);
+                    report(
+                        progress_handler,
+                        ProgressEvent::FrameExtracted {
+                            index,
+                            path: self
+                                .frames
+                                .map(|(path, format)| frame_path(path, index, format)),
+                            duration,
+                        },
+                    );
+                }
+                self.reported = frames;
+                self.last_report = Instant::now();
+            }
+        } else if !line.contains('=') {
+            debug!("ffmpeg: {line}");
+            self.log.push_str(line);
+            self.log.push('\n');
+        }
+    }
+}
+
+/// Reports the encoder's output from the lines written by `ffmpeg -progress`
+#[derive(Default)]
+pub(crate) struct EncodeProgress {
+    frame: usize,
+    fps: f32,
+}
+
+impl EncodeProgress {
+    pub(crate) fn line(&mut self, line: &str, progress_handler: Option<&impl ProgressHandler>) {
+        match line.split_once('=') {
+            Some(("frame", value)) => self.frame = value.trim().parse().unwrap_or(self.frame),
+            Some(("fps", value)) => self.fps = value.trim().parse().unwrap_or(self.fps),
+            // Each block of key value pairs ends with the progress key
+            Some(("progress", _)) => report(
+                progress_handler,
+                ProgressEvent::EncodeProgress {
+                    frame: self.frame,
+                    fps: self.fps,
+                },
+            ),
+            Some(_) => {}
+            None => debug!("ffmpeg: {line}"),
+        }
+    }
+}
+
+/// Reports extracted frames from the output of `ffmpeg -progress` until the stream closes
+pub(crate) fn read_extract_progress<'a>(
+    progress: impl Read,
+    frame_count: usize,
+    frames: Option<(&'a Path, IntermediateFormat)>,
+    progress_handler: Option<&impl ProgressHandler>,
+) -> Result<ExtractProgress<'a>> {
+    let mut extract_progress = ExtractProgress::new(frame_count, frames);
+    for line in BufReader::new(progress).lines() {
+        extract_progress.line(&line?, progress_handler);
+    }
+    Ok(extract_progress)
+}
+
+/// Reports the encoder's output from `ffmpeg -progress` until the stream closes
+pub(crate) fn read_encode_progress(
+    progress: impl Read,
+    progress_handler: Option<&impl ProgressHandler>,
+) -> Result<()> {
+    let mut encode_progress = EncodeProgress::default();
+    for line in BufReader::new(progress).lines() {
+        encode_progress.line(&line?, progress_handler);
+    }
+    Ok(())
+}
+
+/// Sends the event to the handler, if there is one
+pub(crate) fn report(progress_handler: Option<&impl ProgressHandler>, event: ProgressEvent) {
+    if let Some(progress_handler) = progress_handler {
+        progress_handler.handle(event);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{EncodeFramesDescriptor, ExtractFramesDescriptor, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Everything needed to reproduce a render: the input, the output, and every extract and encode setting,
+/// including the camera path
+///
+/// Projects are saved as TOML, conventionally with a `.dfproj` extension. Paths inside the project's directory
+/// are stored relative to it, so a directory holding a project and its input can be shared as is.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Project {
+    pub input_path: PathBuf,
+    pub output_path: PathBuf,
+    pub extract: ExtractFramesDescriptor,
+    pub encode: EncodeFramesDescriptor,
+}
+
+impl Project {
+    /// Reads a project, resolving its relative paths against the project's directory
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut project: Project = toml::from_str(&fs::read_to_string(path)?)?;
+        let dir = project_dir(path);
+        project.input_path = dir.join(&project.input_path);
+        project.output_path = dir.join(&project.output_path);
+        Ok(project)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let dir = std::path::absolute(project_dir(path))?;
+        let project = Project {
+            input_path: relative_to(&self.input_path, &dir)?,
+            output_path: relative_to(&self.output_path, &dir)?,
+            ..self.clone()
+        };
+        // Converting to a value first emits the settings before the camera path and other nested tables, which
+        // TOML requires
+        let project = toml::Value::try_from(project)?;
+        fs::write(path, toml::to_string(&project)?)?;
+        Ok(())
+    }
+}
+
+fn project_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// Returns the path relative to `dir` if it is inside it, and the absolute path otherwise
+fn relative_to(path: &Path, dir: &Path) -> Result<PathBuf> {
+    let path = std::path::absolute(path)?;
+    Ok(path
+        .strip_prefix(dir)
+        .map(Path::to_path_buf)
+        .unwrap_or(path))
+}
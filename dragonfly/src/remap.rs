@@ -0,0 +1,71 @@
+use crate::native::for_each_source;
+use crate::{fingerprint, CameraPose, ExtractFramesDescriptor, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Returns the paths of the maps holding the input column and row that each output pixel of the camera `pose`
+/// samples, which are shared by every frame seen from the same orientation
+pub(crate) fn map_paths(
+    directory: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    pose: &CameraPose,
+    input_size: (u32, u32),
+    output_size: (u32, u32),
+) -> (PathBuf, PathBuf) {
+    let key = fingerprint(&format!(
+        "{} {} {} {} {} {} {} {} {:?} {:?}",
+        descriptor.projection,
+        descriptor.ih_fov,
+        descriptor.iv_fov,
+        pose.yaw,
+        pose.pitch,
+        pose.roll,
+        pose.h_fov,
+        pose.v_fov,
+        input_size,
+        output_size
+    ));
+    (
+        directory.join(format!("remap_{key}_x.pgm")),
+        directory.join(format!("remap_{key}_y.pgm")),
+    )
+}
+
+/// Writes the maps of the camera `pose` to `directory`, unless an earlier frame already wrote them
+pub(crate) fn write_maps(
+    directory: &Path,
+    descriptor: &ExtractFramesDescriptor,
+    pose: &CameraPose,
+    input_size: (u32, u32),
+    output_size: (u32, u32),
+) -> Result<()> {
+    let (x_path, y_path) = map_paths(directory, descriptor, pose, input_size, output_size);
+    if x_path.exists() && y_path.exists() {
+        return Ok(());
+    }
+    let (input_width, input_height) = (input_size.0 as i64, input_size.1 as i64);
+    let wraps = descriptor.ih_fov >= 360.0;
+    // 16 bit binary PGM, which stores samples big endian
+    let header = format!("P5\n{} {}\n65535\n", output_size.0, output_size.1);
+    let mut x_map = header.clone().into_bytes();
+    let mut y_map = header.into_bytes();
+    for_each_source(descriptor, pose, output_size, input_size, |_, _, x, y| {
+        // The remap filter samples the nearest pixel
+        let (x, y) = (x.round() as i64, y.round() as i64);
+        let x = if wraps {
+            x.rem_euclid(input_width)
+        } else {
+            x.clamp(0, input_width - 1)
+        };
+        let y = y.clamp(0, input_height - 1);
+        x_map.extend_from_slice(&(x as u16).to_be_bytes());
+        y_map.extend_from_slice(&(y as u16).to_be_bytes());
+    });
+    // Written under a temporary name first, so an interrupted extraction never leaves a partial map behind
+    for (path, map) in [(x_path, x_map), (y_path, y_map)] {
+        let partial_path = path.with_extension("pgm.partial");
+        fs::write(&partial_path, map)?;
+        fs::rename(partial_path, path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use crate::{
+    encode_frames, CancellationToken, DragonflyError, EncodeFramesDescriptor, ProgressEvent, Result,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// One step of a bitrate ladder: the extracted frames encoded at a given height and, optionally, bitrate
+///
+/// Parsed from `HEIGHT[p][:BITRATE]`, such as `1080p:6M` or `720`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rendition {
+    pub height: u32,
+    /// Maximum bitrate, such as 6M, replacing the bitrate of the encode settings
+    pub bitrate: Option<String>,
+}
+
+impl Rendition {
+    /// Returns `{stem}_{height}p.{extension}` next to `output_path`
+    pub fn output_path(&self, output_path: &Path) -> PathBuf {
+        let stem = output_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let file_name = match output_path.extension() {
+            Some(extension) => format!("{stem}_{}p.{}", self.height, extension.to_string_lossy()),
+            None => format!("{stem}_{}p", self.height),
+        };
+        output_path.with_file_name(file_name)
+    }
+
+    /// Returns the encode settings of this rendition, scaling the frames to its height
+    pub fn descriptor(&self, descriptor: &EncodeFramesDescriptor) -> EncodeFramesDescriptor {
+        EncodeFramesDescriptor {
+            scale: format!("-2:{}", self.height),
+            bitrate: self.bitrate.clone().or_else(|| descriptor.bitrate.clone()),
+            ..descriptor.clone()
+        }
+    }
+}
+
+impl fmt::Display for Rendition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.bitrate {
+            Some(bitrate) => write!(f, "{}p:{bitrate}", self.height),
+            None => write!(f, "{}p", self.height),
+        }
+    }
+}
+
+impl FromStr for Rendition {
+    type Err = DragonflyError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (height, bitrate) = match s.split_once(':') {
+            Some((height, bitrate)) => (height, Some(bitrate.to_string())),
+            None => (s, None),
+        };
+        let height = height
+            .trim_end_matches('p')
+            .parse::<u32>()
+            .ok()
+            .filter(|height| *height >= 2)
+            .ok_or_else(|| DragonflyError::InvalidRendition(s.to_string()))?;
+        if bitrate.as_deref().is_some_and(str::is_empty) {
+            return Err(DragonflyError::InvalidRendition(s.to_string()));
+        }
+        Ok(Self { height, bitrate })
+    }
+}
+
+/// Encodes the frames extracted to `extraction_path` once per rendition, running up to `jobs` encoders at a
+/// time, and returns the path and exit status of each rendition in order
+///
+/// Only the first rendition exports the poster and sprite sheet, which would otherwise be written over by
+/// each of the others. The first error is returned once every encoder has finished.
+pub fn encode_renditions(
+    output_path: &Path,
+    extraction_path: &Path,
+    descriptor: &EncodeFramesDescriptor,
+    renditions: &[Rendition],
+    jobs: usize,
+    cancellation_token: Option<&CancellationToken>,
+) -> Result<Vec<(PathBuf, ExitStatus)>> {
+    let next_rendition = AtomicUsize::new(0);
+    let results = Mutex::new(vec![]);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, renditions.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next_rendition.fetch_add(1, Ordering::Relaxed);
+                let Some(rendition) = renditions.get(index) else {
+                    break;
+                };
+                let mut rendition_descriptor = rendition.descriptor(descriptor);
+                if index > 0 {
+                    rendition_descriptor.poster = None;
+                    rendition_descriptor.sprite_sheet = None;
+                }
+                let rendition_path = rendition.output_path(output_path);
+                let result = encode_frames(
+                    &rendition_path,
+                    extraction_path,
+                    &rendition_descriptor,
+                    None::<fn(ProgressEvent)>,
+                    cancellation_token,
+                );
+                results
+                    .lock()
+                    .unwrap()
+                    .push((index, result.map(|status| (rendition_path, status))));
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
@@ -0,0 +1,43 @@
+use crate::{CameraPose, SourceColor};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The frames written by an extraction, for callers that post-process or verify them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExtractReport {
+    /// Every extracted frame, ordered by index
+    pub frames: Vec<ExtractedFrame>,
+    pub width: u32,
+    pub height: u32,
+    /// True when every frame was rendered by a single ffmpeg process
+    pub single_pass: bool,
+    /// Wall clock time of the whole extraction, including probing the input
+    pub duration: Duration,
+    /// Bit depth and color tags of the input, which the encoder keeps
+    #[serde(default)]
+    pub color: SourceColor,
+}
+
+/// A frame written by an extraction
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExtractedFrame {
+    pub index: usize,
+    pub path: PathBuf,
+    pub pose: CameraPose,
+    /// Time spent rendering the frame, which is an average over several frames for single pass extraction
+    pub duration: Duration,
+}
+
+impl ExtractReport {
+    pub fn average_frame_duration(&self) -> Duration {
+        match self.frames.len() {
+            0 => Duration::ZERO,
+            n => self.frames.iter().map(|f| f.duration).sum::<Duration>() / n as u32,
+        }
+    }
+
+    pub fn slowest_frame(&self) -> Option<&ExtractedFrame> {
+        self.frames.iter().max_by_key(|f| f.duration)
+    }
+}
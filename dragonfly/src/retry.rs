@@ -0,0 +1,184 @@
+use crate::{DragonflyError, Process, Result};
+use log::warn;
+use std::io::{self, Read, Write};
+use std::process::ExitStatus;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+static PROCESS_POLICY: RwLock<ProcessPolicy> = RwLock::new(ProcessPolicy {
+    timeout: None,
+    retries: 0,
+});
+
+/// How long each ffmpeg and ffprobe process may run, and how many times a failed job is retried
+///
+/// Retries help with transient failures, such as an input on a network mount that briefly cannot be read.
+/// An extraction that is retried resumes from the frames it already rendered. The policy applies to the
+/// blocking functions; wrap the futures of the async functions in `tokio::time::timeout` instead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProcessPolicy {
+    /// Kills any process that runs longer than this
+    pub timeout: Option<Duration>,
+    /// Times a job is run again after failing, 0 to fail on the first error
+    pub retries: u32,
+}
+
+/// Sets the process policy used by every following job
+pub fn set_process_policy(policy: ProcessPolicy) {
+    *PROCESS_POLICY.write().unwrap() = policy;
+}
+
+/// Returns the process policy set with [`set_process_policy`]
+pub fn process_policy() -> ProcessPolicy {
+    *PROCESS_POLICY.read().unwrap()
+}
+
+/// Runs `f`, running it again after each transient failure until the policy's retries are used up
+///
+/// Once every attempt failed, the error lists the failure of each attempt.
+pub(crate) fn with_retries<T>(mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let retries = process_policy().retries;
+    let mut attempts = vec![];
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if !is_transient(&e) => return Err(e),
+            Err(e) if retries == 0 => return Err(e),
+            Err(e) => {
+                attempts.push(e);
+                if attempts.len() > retries as usize {
+                    return Err(DragonflyError::AttemptsFailed(attempts));
+                }
+                // Back off a little longer after each failure, giving a flaky mount time to recover
+                let delay = Duration::from_secs(1 << (attempts.len() - 1).min(5));
+                warn!(
+                    "Attempt {} failed ({}), retrying in {delay:?}",
+                    attempts.len(),
+                    attempts.last().expect("a failed attempt")
+                );
+                thread::sleep(delay);
+            }
+        }
+    }
+}
+
+/// Returns true for the failures that running the job again may not repeat
+fn is_transient(error: &DragonflyError) -> bool {
+    matches!(
+        error,
+        DragonflyError::FfmpegFailed { .. }
+            | DragonflyError::Command(_)
+            | DragonflyError::UndecodableInput { .. }
+    )
+}
+
+/// Formats the failure of each attempt, one per line
+pub(crate) fn attempts_message(attempts: &[DragonflyError]) -> String {
+    attempts
+        .iter()
+        .enumerate()
+        .map(|(i, e)| format!("\nAttempt {}: {e}", i + 1))
+        .collect()
+}
+
+/// Wraps `process` in a watchdog that kills it once the policy's timeout passes, or returns it unchanged
+/// when there is no timeout
+pub(crate) fn with_timeout(process: Box<dyn Process>) -> Box<dyn Process> {
+    match process_policy().timeout {
+        Some(timeout) => Box::new(TimedProcess::new(process, timeout)),
+        None => process,
+    }
+}
+
+/// A process killed by a watchdog thread once it runs longer than its timeout
+///
+/// Waiting on a killed process returns a [`io::ErrorKind::TimedOut`] error rather than its exit status.
+struct TimedProcess {
+    process: Arc<Mutex<Box<dyn Process>>>,
+    timeout: Duration,
+    exited: Arc<AtomicBool>,
+    timed_out: Arc<AtomicBool>,
+}
+
+impl TimedProcess {
+    fn new(process: Box<dyn Process>, timeout: Duration) -> Self {
+        let process = Arc::new(Mutex::new(process));
+        let exited = Arc::new(AtomicBool::new(false));
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let started = Instant::now();
+        {
+            let (process, exited, timed_out) = (process.clone(), exited.clone(), timed_out.clone());
+            thread::spawn(move || {
+                while !exited.load(Ordering::SeqCst) {
+                    if started.elapsed() >= timeout {
+                        timed_out.store(true, Ordering::SeqCst);
+                        process.lock().unwrap().kill().ok();
+                        break;
+                    }
+                    thread::sleep(Duration::from_millis(10));
+                }
+            });
+        }
+        Self {
+            process,
+            timeout,
+            exited,
+            timed_out,
+        }
+    }
+
+    fn exit(&self, status: ExitStatus) -> io::Result<ExitStatus> {
+        self.exited.store(true, Ordering::SeqCst);
+        if self.timed_out.load(Ordering::SeqCst) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "process killed after running longer than {:?}",
+                    self.timeout
+                ),
+            ));
+        }
+        Ok(status)
+    }
+}
+
+impl Process for TimedProcess {
+    fn take_stdin(&mut self) -> Option<Box<dyn Write + Send>> {
+        self.process.lock().unwrap().take_stdin()
+    }
+
+    fn take_stdout(&mut self) -> Option<Box<dyn Read + Send>> {
+        self.process.lock().unwrap().take_stdout()
+    }
+
+    fn take_stderr(&mut self) -> Option<Box<dyn Read + Send>> {
+        self.process.lock().unwrap().take_stderr()
+    }
+
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        let status = self.process.lock().unwrap().try_wait()?;
+        status.map(|status| self.exit(status)).transpose()
+    }
+
+    fn wait(&mut self) -> io::Result<ExitStatus> {
+        // Polling leaves the process unlocked for the watchdog to kill
+        loop {
+            if let Some(status) = self.try_wait()? {
+                return Ok(status);
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn kill(&mut self) -> io::Result<()> {
+        self.process.lock().unwrap().kill()
+    }
+}
+
+impl Drop for TimedProcess {
+    fn drop(&mut self) {
+        self.exited.store(true, Ordering::SeqCst);
+    }
+}
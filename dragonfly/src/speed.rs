@@ -0,0 +1,366 @@
+use crate::DragonflyError;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Number of steps the speed is integrated over
+const STEPS: usize = 1024;
+
+/// Maps normalized animation progress to the progress of a rotation whose speed follows an expression
+///
+/// The expression gives the relative speed at normalized time `t` in [0, 1], such as `0.2 + t^2` for a
+/// slow reveal that speeds into a fast spin. It may use numbers, `t`, `pi`, `+ - * / ^`, parentheses,
+/// and the functions `sin`, `cos`, `tan`, `exp`, `ln`, `sqrt`, `abs`, `min`, `max`, and `pow`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SpeedRamp {
+    expression: String,
+    /// Distance travelled at each step, normalized so the rotation ends at 1
+    progress: Vec<f32>,
+}
+
+impl SpeedRamp {
+    /// Returns the progress of the rotation at normalized time `t` in the range [0, 1]
+    pub fn apply(&self, t: f32) -> f32 {
+        let x = t.clamp(0.0, 1.0) * STEPS as f32;
+        let i = (x as usize).min(STEPS - 1);
+        let a = self.progress[i];
+        let b = self.progress[i + 1];
+        a + (b - a) * (x - i as f32)
+    }
+}
+
+impl fmt::Display for SpeedRamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl FromStr for SpeedRamp {
+    type Err = DragonflyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| DragonflyError::InvalidSpeed(format!("{s}: {reason}"));
+        let mut parser = Parser {
+            tokens: tokenize(s).map_err(|e| invalid(&e))?,
+            position: 0,
+        };
+        let expr = parser.expression().map_err(|e| invalid(&e))?;
+        if parser.position < parser.tokens.len() {
+            return Err(invalid("unexpected trailing input"));
+        }
+        // Integrate the speed with the trapezoidal rule
+        let speed = (0..=STEPS)
+            .map(|i| expr.eval(i as f64 / STEPS as f64))
+            .collect::<Vec<_>>();
+        if speed.iter().any(|v| !(v.is_finite() && *v >= 0.0)) {
+            return Err(invalid(
+                "speed must be a finite number of at least 0 for every t",
+            ));
+        }
+        let mut progress = Vec::with_capacity(STEPS + 1);
+        let mut distance = 0.0;
+        progress.push(0.0);
+        for pair in speed.windows(2) {
+            distance += (pair[0] + pair[1]) / 2.0;
+            progress.push(distance);
+        }
+        if distance <= 0.0 {
+            return Err(invalid("speed must be above 0 for part of the rotation"));
+        }
+        Ok(Self {
+            expression: s.trim().to_string(),
+            progress: progress.iter().map(|d| (d / distance) as f32).collect(),
+        })
+    }
+}
+
+impl TryFrom<String> for SpeedRamp {
+    type Error = DragonflyError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<SpeedRamp> for String {
+    fn from(speed: SpeedRamp) -> Self {
+        speed.expression
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Symbol(char),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                number.push(c);
+                chars.next();
+            }
+            let value = number
+                .parse()
+                .map_err(|_| format!("invalid number {number}"))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                name.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Name(name));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected character {c}"));
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(f64),
+    Time,
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, t: f64) -> f64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Time => t,
+            Expr::Negate(expr) => -expr.eval(t),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(t), b.eval(t));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    '/' => a / b,
+                    _ => a.powf(b),
+                }
+            }
+            Expr::Call(name, args) => {
+                let args = args.iter().map(|arg| arg.eval(t)).collect::<Vec<_>>();
+                match (name.as_str(), &args[..]) {
+                    ("sin", [x]) => x.sin(),
+                    ("cos", [x]) => x.cos(),
+                    ("tan", [x]) => x.tan(),
+                    ("exp", [x]) => x.exp(),
+                    ("ln", [x]) => x.ln(),
+                    ("sqrt", [x]) => x.sqrt(),
+                    ("abs", [x]) => x.abs(),
+                    ("min", [a, b]) => a.min(*b),
+                    ("max", [a, b]) => a.max(*b),
+                    ("pow", [a, b]) => a.powf(*b),
+                    _ => f64::NAN,
+                }
+            }
+        }
+    }
+}
+
+/// A recursive descent parser over the usual precedence of sums, products, and right associative powers
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        while let Some(op) = ['+', '-'].into_iter().find(|op| self.eat(*op)) {
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(op) = ['*', '/'].into_iter().find(|op| self.eat(*op)) {
+            expr = Expr::Binary(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        let base = self.primary()?;
+        if self.eat('^') {
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(self.unary()?)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.peek().cloned().ok_or("unexpected end of input")?;
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Symbol('(') => {
+                let expr = self.expression()?;
+                if !self.eat(')') {
+                    return Err("missing )".to_string());
+                }
+                Ok(expr)
+            }
+            Token::Name(name) if name == "t" => Ok(Expr::Time),
+            Token::Name(name) if name == "pi" => Ok(Expr::Number(std::f64::consts::PI)),
+            Token::Name(name) => {
+                let arity = match name.as_str() {
+                    "sin" | "cos" | "tan" | "exp" | "ln" | "sqrt" | "abs" => 1,
+                    "min" | "max" | "pow" => 2,
+                    _ => return Err(format!("unknown name {name}")),
+                };
+                if !self.eat('(') {
+                    return Err(format!("missing ( after {name}"));
+                }
+                let mut args = vec![self.expression()?];
+                while self.eat(',') {
+                    args.push(self.expression()?);
+                }
+                if !self.eat(')') {
+                    return Err(format!("missing ) after the arguments of {name}"));
+                }
+                if args.len() != arity {
+                    return Err(format!("{name} takes {arity} arguments"));
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Token::Symbol(c) => Err(format!("unexpected {c}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the expression and evaluates it at normalized time `t`
+    fn eval(expression: &str, t: f64) -> f64 {
+        let mut parser = Parser {
+            tokens: tokenize(expression).unwrap(),
+            position: 0,
+        };
+        let expr = parser.expression().unwrap();
+        assert_eq!(parser.position, parser.tokens.len(), "{expression}");
+        expr.eval(t)
+    }
+
+    /// Returns the reason the expression is rejected
+    fn error(expression: &str) -> String {
+        expression.parse::<SpeedRamp>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn follows_operator_precedence() {
+        assert_eq!(eval("1 + 2 * 3", 0.0), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0), 9.0);
+        assert_eq!(eval("8 / 4 / 2", 0.0), 1.0);
+        assert_eq!(eval("2 * 3 ^ 2", 0.0), 18.0);
+        assert_eq!(eval("0.2 + t^2", 0.5), 0.45);
+    }
+
+    #[test]
+    fn raises_powers_right_to_left() {
+        assert_eq!(eval("2^3^2", 0.0), 512.0);
+    }
+
+    #[test]
+    fn negates_after_raising_to_a_power() {
+        assert_eq!(eval("-t^2", 3.0), -9.0);
+        assert_eq!(eval("(-t)^2", 3.0), 9.0);
+        assert_eq!(eval("2 - -t", 1.0), 3.0);
+    }
+
+    #[test]
+    fn evaluates_functions_and_constants() {
+        assert_eq!(eval("max(t, 0.5)", 0.25), 0.5);
+        assert_eq!(eval("min(t, 0.5)", 0.25), 0.25);
+        assert_eq!(eval("pow(2, 10)", 0.0), 1024.0);
+        assert_eq!(eval("sqrt(abs(-16))", 0.0), 4.0);
+        assert!((eval("sin(pi / 2)", 0.0) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rejects_the_wrong_number_of_arguments() {
+        for (expression, arity) in [
+            ("sin(t, t)", 1),
+            ("cos(t, t)", 1),
+            ("tan(t, t)", 1),
+            ("exp(t, t)", 1),
+            ("ln(t, t)", 1),
+            ("sqrt(t, t)", 1),
+            ("abs(t, t)", 1),
+            ("min(t)", 2),
+            ("max(t)", 2),
+            ("pow(t)", 2),
+        ] {
+            let name = &expression[..expression.find('(').unwrap()];
+            assert!(
+                error(expression).contains(&format!("{name} takes {arity} arguments")),
+                "{}",
+                error(expression)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        assert!(error("t t").contains("unexpected trailing input"));
+        assert!(error("1 + speed").contains("unknown name speed"));
+        assert!(error("sin t").contains("missing ( after sin"));
+        assert!(error("(1 + t").contains("missing )"));
+        assert!(error("1 +").contains("unexpected end of input"));
+        assert!(error("t % 2").contains("unexpected character %"));
+        assert!(error("1..2").contains("invalid number 1..2"));
+    }
+
+    #[test]
+    fn rejects_negative_and_non_finite_speeds() {
+        assert!(error("t - 0.5").contains("finite number of at least 0"));
+        assert!(error("1 / t").contains("finite number of at least 0"));
+        assert!(error("ln(t - 1)").contains("finite number of at least 0"));
+        assert!(error("0 * t").contains("above 0 for part of the rotation"));
+    }
+
+    #[test]
+    fn starts_at_0_and_ends_at_1() {
+        for expression in ["1", "0.2 + t^2", "sin(pi * t)", "max(0, t - 0.5)"] {
+            let speed: SpeedRamp = expression.parse().unwrap();
+            assert_eq!(speed.apply(0.0), 0.0, "{expression}");
+            assert_eq!(speed.apply(1.0), 1.0, "{expression}");
+        }
+        let constant: SpeedRamp = "1".parse().unwrap();
+        assert!((constant.apply(0.25) - 0.25).abs() < 1e-6);
+    }
+}
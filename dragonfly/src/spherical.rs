@@ -0,0 +1,235 @@
+//! Injects Spherical Video V1 metadata into mp4 and mov files
+//!
+//! See <https://github.com/google/spatial-media/blob/master/docs/spherical-video-rfc.md>.
+//! The metadata is an XMP document stored in a `uuid` box inside the video track, which
+//! YouTube and Facebook use to detect 360 video.
+
+use crate::{DragonflyError, Result};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const SPHERICAL_UUID: [u8; 16] = [
+    0xff, 0xcc, 0x82, 0x63, 0xf8, 0x55, 0x4a, 0x93, 0x88, 0x14, 0x58, 0x7a, 0x02, 0x52, 0x1f, 0xdd,
+];
+
+const SPHERICAL_XML: &str = "<?xml version=\"1.0\"?>\
+<rdf:SphericalVideo xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\" \
+xmlns:GSpherical=\"http://ns.google.com/videos/1.0/spherical/\">\
+<GSpherical:Spherical>true</GSpherical:Spherical>\
+<GSpherical:Stitched>true</GSpherical:Stitched>\
+<GSpherical:StitchingSoftware>dragonfly</GSpherical:StitchingSoftware>\
+<GSpherical:ProjectionType>equirectangular</GSpherical:ProjectionType>\
+</rdf:SphericalVideo>";
+
+/// Boxes whose payload is made up entirely of child boxes, on the path to the chunk offset tables
+const CONTAINER_BOXES: [&[u8; 4]; 5] = [b"moov", b"trak", b"mdia", b"minf", b"stbl"];
+
+/// An ISO base media file format box
+struct Mp4Box {
+    kind: [u8; 4],
+    content: BoxContent,
+}
+
+enum BoxContent {
+    Data(Vec<u8>),
+    Children(Vec<Mp4Box>),
+}
+
+impl Mp4Box {
+    fn parse_all(mut data: &[u8]) -> Result<Vec<Mp4Box>> {
+        let mut boxes = vec![];
+        while !data.is_empty() {
+            let (header_len, box_len) = box_header(data)?;
+            let kind: [u8; 4] = data[4..8].try_into().expect("4 byte box type");
+            let payload = &data[header_len..box_len];
+            let content = if CONTAINER_BOXES.contains(&&kind) {
+                BoxContent::Children(Mp4Box::parse_all(payload)?)
+            } else {
+                BoxContent::Data(payload.to_vec())
+            };
+            boxes.push(Mp4Box { kind, content });
+            data = &data[box_len..];
+        }
+        Ok(boxes)
+    }
+
+    fn children(&self) -> &[Mp4Box] {
+        match &self.content {
+            BoxContent::Children(children) => children,
+            BoxContent::Data(_) => &[],
+        }
+    }
+
+    fn child(&self, kind: &[u8; 4]) -> Option<&Mp4Box> {
+        self.children().iter().find(|b| &b.kind == kind)
+    }
+
+    /// Returns true if this is a track whose handler type is video
+    fn is_video_track(&self) -> bool {
+        let handler = self.child(b"mdia").and_then(|mdia| mdia.child(b"hdlr"));
+        match handler.map(|hdlr| &hdlr.content) {
+            // Version and flags, then pre_defined, then the handler type
+            Some(BoxContent::Data(data)) => data.get(8..12) == Some(b"vide"),
+            _ => false,
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend([0; 4]);
+        out.extend(self.kind);
+        match &self.content {
+            BoxContent::Data(data) => out.extend(data),
+            BoxContent::Children(children) => {
+                for child in children {
+                    child.write(out);
+                }
+            }
+        }
+        let len = (out.len() - start) as u32;
+        out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+    }
+
+    /// Shifts every chunk offset that points past `position` by `delta` bytes
+    fn shift_chunk_offsets(&mut self, position: u64, delta: i64) -> Result<()> {
+        let overflow = || DragonflyError::SphericalMetadata("chunk offset overflow".to_string());
+        match (&self.kind, &mut self.content) {
+            // Entries follow the version, flags, and entry count
+            (b"stco", BoxContent::Data(data)) if data.len() >= 8 => {
+                for entry in data[8..].chunks_exact_mut(4) {
+                    let offset = u32::from_be_bytes(entry.try_into().expect("4 byte entry"));
+                    if offset as u64 > position {
+                        let offset = (offset as u64)
+                            .checked_add_signed(delta)
+                            .and_then(|offset| u32::try_from(offset).ok())
+                            .ok_or_else(overflow)?;
+                        entry.copy_from_slice(&offset.to_be_bytes());
+                    }
+                }
+            }
+            (b"co64", BoxContent::Data(data)) if data.len() >= 8 => {
+                for entry in data[8..].chunks_exact_mut(8) {
+                    let offset = u64::from_be_bytes(entry.try_into().expect("8 byte entry"));
+                    if offset > position {
+                        let offset = offset.checked_add_signed(delta).ok_or_else(overflow)?;
+                        entry.copy_from_slice(&offset.to_be_bytes());
+                    }
+                }
+            }
+            (_, BoxContent::Children(children)) => {
+                for child in children {
+                    child.shift_chunk_offsets(position, delta)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Returns the header length and total length of the box at the start of `data`
+fn box_header(data: &[u8]) -> Result<(usize, usize)> {
+    let invalid = || DragonflyError::SphericalMetadata("truncated box".to_string());
+    let size = u32::from_be_bytes(data.get(0..4).ok_or_else(invalid)?.try_into().unwrap());
+    let (header_len, box_len) = match size {
+        // The box extends to the end of the data
+        0 => (8, data.len()),
+        // A 64-bit size follows the box type
+        1 => {
+            let size = data.get(8..16).ok_or_else(invalid)?.try_into().unwrap();
+            (16, u64::from_be_bytes(size) as usize)
+        }
+        size => (8, size as usize),
+    };
+    if box_len < header_len || box_len > data.len() {
+        return Err(invalid());
+    }
+    Ok((header_len, box_len))
+}
+
+/// Returns the byte offset and length of the top-level `moov` box
+fn find_moov(file: &mut File) -> Result<(u64, u64)> {
+    let file_len = file.metadata()?.len();
+    let mut position = 0;
+    while position + 8 <= file_len {
+        let mut header = [0; 16];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut header[..8])?;
+        let box_len = match u32::from_be_bytes(header[0..4].try_into().unwrap()) {
+            0 => file_len - position,
+            1 => {
+                file.read_exact(&mut header[8..16])?;
+                u64::from_be_bytes(header[8..16].try_into().unwrap())
+            }
+            size => size as u64,
+        };
+        if box_len < 8 {
+            return Err(DragonflyError::SphericalMetadata(
+                "invalid box size".to_string(),
+            ));
+        }
+        if &header[4..8] == b"moov" {
+            return Ok((position, box_len));
+        }
+        position += box_len;
+    }
+    Err(DragonflyError::SphericalMetadata(
+        "no moov box found".to_string(),
+    ))
+}
+
+/// Marks the mp4 or mov file at `path` as an equirectangular 360 video
+pub fn inject_spherical_metadata(path: &Path) -> Result<()> {
+    let mut file = File::open(path)?;
+    let (moov_offset, moov_len) = find_moov(&mut file)?;
+    let mut moov_data = vec![0; moov_len as usize];
+    file.seek(SeekFrom::Start(moov_offset))?;
+    file.read_exact(&mut moov_data)?;
+    let mut moov = Mp4Box::parse_all(&moov_data)?
+        .pop()
+        .ok_or_else(|| DragonflyError::SphericalMetadata("empty moov box".to_string()))?;
+
+    let mut uuid_payload = SPHERICAL_UUID.to_vec();
+    uuid_payload.extend(SPHERICAL_XML.as_bytes());
+    let BoxContent::Children(tracks) = &mut moov.content else {
+        unreachable!("moov is a container box");
+    };
+    let video_track = tracks
+        .iter_mut()
+        .find(|b| &b.kind == b"trak" && b.is_video_track())
+        .ok_or_else(|| DragonflyError::SphericalMetadata("no video track found".to_string()))?;
+    if let BoxContent::Children(children) = &mut video_track.content {
+        children.retain(|b| {
+            !(&b.kind == b"uuid"
+                && matches!(&b.content, BoxContent::Data(data) if data.starts_with(&SPHERICAL_UUID)))
+        });
+        children.push(Mp4Box {
+            kind: *b"uuid",
+            content: BoxContent::Data(uuid_payload),
+        });
+    }
+
+    // Media data stored after the moov box moves by however much the moov box grows
+    let mut new_moov = vec![];
+    moov.write(&mut new_moov);
+    let delta = new_moov.len() as i64 - moov_len as i64;
+    if delta != 0 {
+        moov.shift_chunk_offsets(moov_offset, delta)?;
+        new_moov.clear();
+        moov.write(&mut new_moov);
+    }
+
+    // Write the rewritten file next to the original, then swap it into place
+    let temp_path = path.with_extension("spherical.tmp");
+    {
+        let mut output = File::create(&temp_path)?;
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut (&mut file).take(moov_offset), &mut output)?;
+        output.write_all(&new_moov)?;
+        file.seek(SeekFrom::Start(moov_offset + moov_len))?;
+        io::copy(&mut file, &mut output)?;
+    }
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use crate::{
+    command_line, ffprobe_info, read_stderr, spawn, DragonflyError, EncodeFramesDescriptor,
+    Executor, Result, FFMPEG_BINARY_PATH,
+};
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Returns the path of the WebVTT file that indexes the sprite sheet at `sprite_path`
+pub fn sprite_vtt_path(sprite_path: &Path) -> PathBuf {
+    sprite_path.with_extension("vtt")
+}
+
+/// Exports the scrub thumbnails of the encoded video at `output_path` to the sprite sheet requested by
+/// `descriptor`, along with the WebVTT file web players read to show them, or does nothing when no sprite
+/// sheet was requested
+///
+/// The thumbnails are taken from the encoded video, so they match what plays at each time.
+pub(crate) fn write_sprite_sheet(
+    output_path: &Path,
+    descriptor: &EncodeFramesDescriptor,
+    executor: &dyn Executor,
+) -> Result<()> {
+    let Some(sprite_path) = &descriptor.sprite_sheet else {
+        return Ok(());
+    };
+    let interval = descriptor.sprite_interval.max(0.1);
+    let length = descriptor.total_length();
+    let count = (length / interval).ceil().max(1.0) as usize;
+    let columns = descriptor.sprite_columns.clamp(1, count);
+    let rows = count.div_ceil(columns);
+    let video = ffprobe_info(output_path, executor)?;
+    let width = descriptor.sprite_width;
+    // Even heights keep the thumbnails of yuv420p video aligned
+    let height =
+        ((width as f32 * video.height as f32 / video.width.max(1) as f32 / 2.0).round() as u32 * 2)
+            .max(2);
+    let mut ffmpeg_cmd = Command::new(FFMPEG_BINARY_PATH.as_os_str());
+    ffmpeg_cmd
+        .args(["-hide_banner", "-loglevel", "error", "-nostats", "-i"])
+        .arg(output_path)
+        .args([
+            "-vf",
+            &format!("fps=1/{interval},scale={width}:{height},tile={columns}x{rows}"),
+            "-frames:v",
+            "1",
+            "-update",
+            "1",
+            "-y",
+        ])
+        .arg(sprite_path)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut ffmpeg_child = spawn(executor, &mut ffmpeg_cmd)?;
+    let stderr = read_stderr(ffmpeg_child.as_mut());
+    let status = ffmpeg_child.wait()?;
+    if !status.success() {
+        return Err(DragonflyError::FfmpegFailed {
+            command: command_line(&ffmpeg_cmd),
+            status,
+            stderr,
+        });
+    }
+    // The cues reference the sprite sheet relative to the WebVTT file next to it
+    let sprite_name = sprite_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| DragonflyError::InvalidPathString(sprite_path.to_path_buf()))?;
+    let mut vtt = String::from("WEBVTT\n");
+    for i in 0..count {
+        let start = i as f32 * interval;
+        let end = (start + interval).min(length);
+        let (x, y) = ((i % columns) as u32 * width, (i / columns) as u32 * height);
+        write!(
+            vtt,
+            "\n{} --> {}\n{sprite_name}#xywh={x},{y},{width},{height}\n",
+            vtt_timestamp(start),
+            vtt_timestamp(end)
+        )
+        .expect("writing to a string");
+    }
+    fs::write(sprite_vtt_path(sprite_path), vtt)?;
+    Ok(())
+}
+
+/// Formats seconds as a WebVTT timestamp, such as 00:01:02.500
+fn vtt_timestamp(seconds: f32) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
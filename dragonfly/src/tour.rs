@@ -0,0 +1,135 @@
+use crate::{CameraPath, DragonflyError, Easing, Keyframe, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// A point of interest the camera turns to and holds on during a [`Tour`]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Waypoint {
+    #[serde(default)]
+    pub yaw: f32,
+    #[serde(default)]
+    pub pitch: f32,
+    /// Horizontal field of view, falls back to the descriptor's when missing
+    pub fov: Option<f32>,
+    /// Seconds the camera holds still on the waypoint
+    #[serde(default)]
+    pub dwell_seconds: f32,
+    /// Seconds the camera takes to turn from this waypoint to the next
+    #[serde(default = "default_transition_seconds")]
+    pub transition_seconds: f32,
+    /// Easing applied to the transition to the next waypoint
+    #[serde(default = "default_transition_easing")]
+    pub easing: Easing,
+}
+
+fn default_transition_seconds() -> f32 {
+    2.0
+}
+
+fn default_transition_easing() -> Easing {
+    Easing::EaseInOut
+}
+
+/// A guided tour that turns the camera from one waypoint to the next, pausing on each
+///
+/// Tours are timed in seconds and converted into a [`CameraPath`] spanning [`Tour::duration`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Tour {
+    /// When true, the last waypoint transitions back to the first so the video loops seamlessly
+    #[serde(default, rename = "loop")]
+    pub looping: bool,
+    pub waypoints: Vec<Waypoint>,
+}
+
+impl Tour {
+    /// Loads a tour from a JSON or TOML file, depending on the file extension
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let tour: Tour = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&contents)?,
+            Some("toml") => toml::from_str(&contents)?,
+            _ => {
+                return Err(DragonflyError::InvalidTour(format!(
+                    "{} must be a .json or .toml file",
+                    path.display()
+                )))
+            }
+        };
+        tour.validate()?;
+        Ok(tour)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.waypoints.is_empty() {
+            return Err(DragonflyError::InvalidTour(
+                "tour must contain at least one waypoint".to_string(),
+            ));
+        }
+        if let Some(waypoint) = self
+            .waypoints
+            .iter()
+            .find(|w| !(w.dwell_seconds >= 0.0 && w.transition_seconds >= 0.0))
+        {
+            return Err(DragonflyError::InvalidTour(format!(
+                "waypoint at yaw {} must not have negative dwell or transition seconds",
+                waypoint.yaw
+            )));
+        }
+        if self.duration() <= 0.0 {
+            return Err(DragonflyError::InvalidTour(
+                "tour must last longer than 0 seconds".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the waypoints whose transitions are part of the tour, in order
+    fn transitions(&self) -> impl Iterator<Item = &Waypoint> {
+        // Without looping, the tour ends on the last waypoint rather than leaving it
+        let count = self.waypoints.len() - usize::from(!self.looping);
+        self.waypoints.iter().take(count)
+    }
+
+    /// Returns the length of the tour in seconds
+    pub fn duration(&self) -> f32 {
+        let dwell: f32 = self.waypoints.iter().map(|w| w.dwell_seconds).sum();
+        let transitions: f32 = self.transitions().map(|w| w.transition_seconds).sum();
+        dwell + transitions
+    }
+
+    /// Converts the tour into a camera path that holds on each waypoint for its dwell time
+    pub fn camera_path(&self) -> Result<CameraPath> {
+        let duration = self.duration();
+        let keyframe = |waypoint: &Waypoint, seconds: f32, easing| Keyframe {
+            time: (seconds / duration).clamp(0.0, 1.0),
+            yaw: waypoint.yaw,
+            pitch: waypoint.pitch,
+            roll: 0.0,
+            h_fov: waypoint.fov,
+            v_fov: None,
+            easing,
+        };
+        let mut keyframes = vec![];
+        let mut seconds = 0.0;
+        for (i, waypoint) in self.waypoints.iter().enumerate() {
+            keyframes.push(keyframe(waypoint, seconds, Easing::Linear));
+            seconds += waypoint.dwell_seconds;
+            keyframes.push(keyframe(waypoint, seconds, waypoint.easing));
+            if i + 1 < self.waypoints.len() || self.looping {
+                seconds += waypoint.transition_seconds;
+            }
+        }
+        if self.looping {
+            keyframes.push(keyframe(&self.waypoints[0], duration, Easing::Linear));
+        }
+        let mut camera_path = CameraPath::new(keyframes)?;
+        camera_path.looping = self.looping;
+        Ok(camera_path)
+    }
+}
+
+/// Parses a tour file for use as a command-line argument value
+pub fn parse_tour(path: &str) -> Result<Tour> {
+    Tour::from_file(Path::new(path))
+}